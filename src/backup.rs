@@ -0,0 +1,157 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Context, Result};
+use relative_path::RelativePathBuf;
+use walkdir::WalkDir;
+
+use crate::{
+    io::copy_file,
+    manifest::{self, STATE_DIR},
+    time::Utc,
+};
+
+/// The directory inside the state directory which holds the backups of all configs.
+const BACKUP_DIR: &str = "backups";
+
+/// A file of a backup snapshot together with the location it was taken from.
+#[derive(Debug)]
+pub struct BackupFile {
+    /// the copy of the file in the snapshot
+    pub backup: PathBuf,
+    /// the location on the system the file belongs to
+    pub original: PathBuf,
+}
+
+/// A backup of the files a deploy replaces. The directory is only created once the first file is stored.
+#[derive(Debug)]
+pub struct Snapshot {
+    dir: PathBuf,
+}
+
+impl Snapshot {
+    /// Starts a snapshot of a config named after the current time.
+    pub fn new(name: &str) -> Self {
+        Snapshot {
+            dir: dir(name).join(timestamp(SystemTime::now())),
+        }
+    }
+
+    /// Copies a file of the system into the snapshot.
+    /// If the file was already stored, e.g. by an earlier deploy in the same second, the first copy is kept.
+    pub fn store(&self, original: &Path) -> Result<()> {
+        let backup = backup_path(&self.dir, original);
+        if backup.exists() || backup.is_symlink() {
+            return Ok(());
+        }
+        manifest::create_state_dir()?;
+        copy_file(original, &backup)
+            .with_context(|| format!("Could not back up '{}'", original.display()))
+    }
+}
+
+/// Returns the directory which contains the snapshots of a config.
+/// Every snapshot is a directory named after the time it was taken, in which the files are stored
+/// under their absolute path on the system, e.g. `.dottor/backups/nvim/<timestamp>/home/user/.config/nvim/init.lua`.
+pub fn dir(name: &str) -> PathBuf {
+    RelativePathBuf::from(STATE_DIR)
+        .join(BACKUP_DIR)
+        .join(name)
+        .to_path(".")
+}
+
+/// Moves the backups of a renamed config.
+pub fn rename(old: &str, new: &str) -> Result<()> {
+    let from = dir(old);
+    if from.is_dir() {
+        fs::rename(&from, dir(new))
+            .with_context(|| format!("Could not move '{}'", from.display()))?;
+    }
+    Ok(())
+}
+
+/// Lists the snapshots of a config, the oldest first.
+/// # Errors
+/// Returns an error if the backup directory exists but can't be read.
+pub fn snapshots(name: &str) -> Result<Vec<String>> {
+    let dir = dir(name);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut snapshots = Vec::new();
+    for entry in
+        fs::read_dir(&dir).with_context(|| format!("Could not read '{}'", dir.display()))?
+    {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            snapshots.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    // timestamps sort chronologically
+    snapshots.sort();
+    Ok(snapshots)
+}
+
+/// Lists the files of a snapshot.
+/// # Errors
+/// Returns an error if the snapshot can't be read.
+pub fn files(name: &str, snapshot: &str) -> Result<Vec<BackupFile>> {
+    let dir = dir(name).join(snapshot);
+    let mut files = Vec::new();
+    for entry in WalkDir::new(&dir).sort_by_file_name() {
+        let entry = entry.with_context(|| format!("Could not read '{}'", dir.display()))?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(&dir)?;
+        files.push(BackupFile {
+            backup: entry.path().to_path_buf(),
+            original: original_path(relative)?,
+        });
+    }
+    Ok(files)
+}
+
+/// Returns where a file is stored in a snapshot.
+/// The drive letter on windows becomes the first directory.
+fn backup_path(snapshot: &Path, original: &Path) -> PathBuf {
+    let mut path = snapshot.to_path_buf();
+    for component in original.components() {
+        match component {
+            Component::Prefix(prefix) => {
+                path.push(prefix.as_os_str().to_string_lossy().replace(':', ""))
+            }
+            Component::Normal(part) => path.push(part),
+            Component::RootDir | Component::CurDir | Component::ParentDir => {}
+        }
+    }
+    path
+}
+
+/// Formats a point in time as `YYYY-MM-DDTHH-MM-SS` in UTC, which sorts chronologically and is a valid file name everywhere.
+fn timestamp(time: SystemTime) -> String {
+    let utc = Utc::new(time);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}-{:02}-{:02}",
+        utc.year, utc.month, utc.day, utc.hour, utc.minute, utc.second
+    )
+}
+
+/// Returns the location on the system of a file in a snapshot from its path relative to the snapshot.
+/// On windows, the first directory is the drive letter.
+fn original_path(relative: &Path) -> Result<PathBuf> {
+    let mut components = relative.components();
+    let mut original = if cfg!(windows) {
+        let drive = components
+            .next()
+            .ok_or_else(|| anyhow!("The backup '{}' has no drive.", relative.display()))?;
+        PathBuf::from(format!("{}:\\", drive.as_os_str().to_string_lossy()))
+    } else {
+        PathBuf::from("/")
+    };
+    original.extend(components);
+    Ok(original)
+}
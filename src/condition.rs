@@ -0,0 +1,241 @@
+use std::{env, fmt, iter::Peekable, str::Chars};
+
+use anyhow::{anyhow, Result};
+
+use crate::{dependencies::find_program, io::expand_path};
+
+/// A condition from the `when` field of a target, e.g. `command_exists("nvim") && hostname != "work"`.
+/// Conditions combine values with `==`, `!=`, `&&`, `||`, `!` and parentheses.
+/// A value on its own is true if it is set and not empty.
+#[derive(Debug)]
+pub enum Condition {
+    Value(Value),
+    Equals(Value, String),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+/// A value a condition can check.
+#[derive(Debug)]
+pub enum Value {
+    /// the path of a program on the path
+    CommandExists(String),
+    /// the path, if the file or directory exists
+    FileExists(String),
+    /// an environment variable
+    Env(String),
+    Hostname,
+    /// the operating system, like `os` in templates
+    Os,
+}
+
+impl Value {
+    fn resolve(&self, hostname: Option<&str>) -> Option<String> {
+        match self {
+            Value::CommandExists(name) => {
+                find_program(name).map(|path| path.to_string_lossy().into_owned())
+            }
+            Value::FileExists(path) => expand_path(path)
+                .ok()
+                .filter(|path| path.exists())
+                .map(|path| path.to_string_lossy().into_owned()),
+            Value::Env(name) => env::var(name).ok(),
+            Value::Hostname => hostname.map(String::from),
+            Value::Os => Some(String::from(env::consts::OS)),
+        }
+    }
+}
+
+impl Condition {
+    /// Checks if the condition holds on the current machine.
+    pub fn evaluate(&self, hostname: Option<&str>) -> bool {
+        match self {
+            Condition::Value(value) => value.resolve(hostname).is_some_and(|v| !v.is_empty()),
+            Condition::Equals(value, expected) => {
+                value.resolve(hostname).as_ref() == Some(expected)
+            }
+            Condition::Not(condition) => !condition.evaluate(hostname),
+            Condition::And(left, right) => left.evaluate(hostname) && right.evaluate(hostname),
+            Condition::Or(left, right) => left.evaluate(hostname) || right.evaluate(hostname),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Open,
+    Close,
+    Equals,
+    NotEquals,
+    And,
+    Or,
+    Not,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "'{}'", name),
+            Token::Str(string) => write!(f, "\"{}\"", string),
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+            Token::Equals => write!(f, "'=='"),
+            Token::NotEquals => write!(f, "'!='"),
+            Token::And => write!(f, "'&&'"),
+            Token::Or => write!(f, "'||'"),
+            Token::Not => write!(f, "'!'"),
+        }
+    }
+}
+
+/// Parses a condition.
+/// # Errors
+/// Returns an error describing the problem if the condition isn't valid.
+pub fn parse(source: &str) -> Result<Condition> {
+    let tokens = tokenize(source).map_err(|error| anyhow!("{} in '{}'", error, source))?;
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+    };
+    let condition = parser
+        .or()
+        .and_then(|condition| match parser.next() {
+            None => Ok(condition),
+            Some(token) => Err(anyhow!("Unexpected {}", token)),
+        })
+        .map_err(|error| anyhow!("{} in '{}'", error, source))?;
+    Ok(condition)
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '"' | '\'' => Token::Str(string(c, &mut chars)?),
+            '=' if chars.next_if_eq(&'=').is_some() => Token::Equals,
+            '!' if chars.next_if_eq(&'=').is_some() => Token::NotEquals,
+            '!' => Token::Not,
+            '&' if chars.next_if_eq(&'&').is_some() => Token::And,
+            '|' if chars.next_if_eq(&'|').is_some() => Token::Or,
+            c if c.is_alphabetic() || c == '_' => {
+                let mut ident = String::from(c);
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                    ident.push(c);
+                }
+                Token::Ident(ident)
+            }
+            c => return Err(anyhow!("Unexpected character '{}'", c)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// reads a string literal up to the closing quote
+fn string(quote: char, chars: &mut Peekable<Chars>) -> Result<String> {
+    let mut string = String::new();
+    for c in chars.by_ref() {
+        if c == quote {
+            return Ok(string);
+        }
+        string.push(c);
+    }
+    Err(anyhow!("Unterminated string"))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn next_if(&mut self, expected: &Token) -> bool {
+        let matches = self.tokens.get(self.position) == Some(expected);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    fn or(&mut self) -> Result<Condition> {
+        let mut condition = self.and()?;
+        while self.next_if(&Token::Or) {
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> Result<Condition> {
+        let mut condition = self.unary()?;
+        while self.next_if(&Token::And) {
+            condition = Condition::And(Box::new(condition), Box::new(self.unary()?));
+        }
+        Ok(condition)
+    }
+
+    fn unary(&mut self) -> Result<Condition> {
+        if self.next_if(&Token::Not) {
+            return Ok(Condition::Not(Box::new(self.unary()?)));
+        }
+        if self.next_if(&Token::Open) {
+            let condition = self.or()?;
+            if !self.next_if(&Token::Close) {
+                return Err(anyhow!("Missing ')'"));
+            }
+            return Ok(condition);
+        }
+
+        let value = self.value()?;
+        let negated = match self.tokens.get(self.position) {
+            Some(Token::Equals) => false,
+            Some(Token::NotEquals) => true,
+            _ => return Ok(Condition::Value(value)),
+        };
+        self.position += 1;
+        let expected = match self.next() {
+            Some(Token::Str(expected)) => expected.clone(),
+            _ => return Err(anyhow!("Expected a string after the comparison")),
+        };
+        let condition = Condition::Equals(value, expected);
+        Ok(if negated {
+            Condition::Not(Box::new(condition))
+        } else {
+            condition
+        })
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        let name = match self.next() {
+            Some(Token::Ident(name)) => name.clone(),
+            Some(token) => return Err(anyhow!("Unexpected {}", token)),
+            None => return Err(anyhow!("Unexpected end")),
+        };
+        let function: fn(String) -> Value = match name.as_str() {
+            "hostname" => return Ok(Value::Hostname),
+            "os" => return Ok(Value::Os),
+            "command_exists" => Value::CommandExists,
+            "file_exists" => Value::FileExists,
+            "env" => Value::Env,
+            _ => return Err(anyhow!("Unknown value '{}'", name)),
+        };
+        let argument = match self.tokens.get(self.position..self.position + 3) {
+            Some([Token::Open, Token::Str(argument), Token::Close]) => argument.clone(),
+            _ => return Err(anyhow!("Expected a single string argument for '{}'", name)),
+        };
+        self.position += 3;
+        Ok(function(argument))
+    }
+}
@@ -1,40 +1,152 @@
-use std::{cmp::Ordering, fs, path::Path};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::OnceLock,
+};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use regex::Regex;
 use relative_path::RelativePathBuf;
-use serde::{
-    de::{self, Visitor},
-    Deserialize, Serialize,
-};
+use serde::{de, Deserialize, Serialize};
 
+use crate::condition;
 use crate::io::{
     assert_root_present, check_dir_null_or_empty, check_valid_dir, prompt_bool, read_to_string,
     write,
 };
+use crate::structure::same_name;
 
 #[allow(dead_code)]
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RootConfiguration {
+    /// globs of config directories and files which are ignored in every config
     pub exclude: Vec<String>,
+    /// the compiled `exclude` globs
+    #[serde(skip)]
+    pub exclude_patterns: GlobSet,
     pub synchronization: RootSynchronization,
+    /// variables which can be used in templated files
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub variables: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "RootPull::is_default")]
+    pub pull: RootPull,
 }
 
 impl Default for RootConfiguration {
     fn default() -> Self {
         Self {
             exclude: vec![".git/".to_string()],
+            exclude_patterns: Default::default(),
             synchronization: Default::default(),
+            variables: Default::default(),
+            pull: Default::default(),
         }
     }
 }
 
+/// The defaults for pulling configs.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct RootPull {
+    /// what happens to files which were changed in the repository as well as on the system since they were deployed
+    pub on_conflict: OnConflict,
+}
+
+impl RootPull {
+    fn is_default(&self) -> bool {
+        *self == RootPull::default()
+    }
+}
+
+/// How pulling handles files which were changed in the repository as well as on the system since they were deployed.
+/// `--ours` and `--theirs` take precedence.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    /// ask for them, or refuse to pull them with `--force`
+    #[default]
+    Prompt,
+    /// keep the file in the repository
+    Ours,
+    /// pull in the file from the system
+    Theirs,
+    /// pull in the file with conflict markers around the lines which were changed in both places
+    Merge,
+}
+
 #[allow(dead_code)]
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
 pub struct RootSynchronization {
     pub repository: String,
     pub remote: String,
     pub branch: String,
+    /// additional named remotes the dotfiles are synchronized with.
+    /// If set, they replace the single remote described by the fields above.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remotes: Vec<SyncRemote>,
+}
+
+/// A named remote the dotfiles are synchronized with.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SyncRemote {
+    pub name: String,
+    /// the name of the git remote, defaults to `name`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    /// the branch which is pushed, defaults to `synchronization.branch`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// a "user/repository" string or a complete url, which is used instead of the git remote
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+}
+
+impl RootSynchronization {
+    /// Lists the remotes the dotfiles are synchronized with, with all defaults filled in.
+    /// Without `remotes`, the single remote of the older configuration format is used.
+    pub fn remotes(&self) -> Vec<SyncRemote> {
+        if self.remotes.is_empty() {
+            return vec![SyncRemote {
+                name: self.remote.clone(),
+                remote: Some(self.remote.clone()),
+                branch: Some(self.branch.clone()),
+                repository: Some(self.repository.clone()).filter(|r| !r.is_empty()),
+            }];
+        }
+
+        self.remotes
+            .iter()
+            .map(|remote| SyncRemote {
+                name: remote.name.clone(),
+                remote: remote.remote.clone().or_else(|| Some(remote.name.clone())),
+                branch: remote.branch.clone().or_else(|| Some(self.branch.clone())),
+                repository: remote.repository.clone().filter(|r| !r.is_empty()),
+            })
+            .collect()
+    }
+}
+
+impl SyncRemote {
+    /// Returns the url to push to if the remote has a repository, expanding "user/repository" to github.
+    pub fn url(&self) -> Option<String> {
+        let repository = self.repository.as_ref()?;
+        let parts: Vec<&str> = repository.split('/').collect();
+        let is_github = parts.len() == 2
+            && parts
+                .iter()
+                .all(|part| !part.is_empty() && !part.starts_with('.'))
+            && !repository.contains(':');
+        if is_github {
+            Some(format!("https://github.com/{}", repository))
+        } else {
+            Some(repository.clone())
+        }
+    }
 }
 
 impl Default for RootSynchronization {
@@ -51,17 +163,106 @@ impl Default for RootSynchronization {
                     Err(_) => String::from("main"),
                 }
             },
+            remotes: Vec::new(),
         }
     }
 }
 
+/// The default location of the root configuration, relative to the repository.
 pub const ROOT_PATH: &str = "dottor.toml";
 
+static ROOT_CONFIG: OnceLock<PathBuf> = OnceLock::new();
+/// the working directory dottor was started in, before changing to the repository
+static INVOCATION_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Returns the location of the root configuration, relative to the repository.
+pub fn root_path() -> &'static Path {
+    ROOT_CONFIG
+        .get()
+        .map(PathBuf::as_path)
+        .unwrap_or(Path::new(ROOT_PATH))
+}
+
+/// Resolves a path which was passed on the command line against the directory dottor was started in.
+/// Absolute paths are returned as they are.
+pub fn invocation_path(path: impl AsRef<Path>) -> PathBuf {
+    match INVOCATION_DIR.get() {
+        Some(dir) => dir.join(path),
+        None => path.as_ref().to_path_buf(),
+    }
+}
+
+/// Sets the location of the root configuration without searching for the repository.
+pub fn set_root_path(path: Option<&str>) {
+    if let Some(path) = path {
+        ROOT_CONFIG.set(PathBuf::from(path)).ok();
+    }
+}
+
+/// Searches the repository which contains the root configuration and makes it the working directory.
+/// A relative path is searched in the current directory and its parents, like git searches for `.git`.
+/// For an absolute path, the directory of the file is the repository.
+/// If no root configuration is found, the working directory is left unchanged.
+pub fn locate_root(path: Option<&str>) -> Result<()> {
+    let path = PathBuf::from(path.unwrap_or(ROOT_PATH));
+
+    let (repository, relative) = if path.is_absolute() {
+        let Some(file_name) = path.file_name() else {
+            return Err(anyhow!(
+                "'{}' is not a valid root configuration path.",
+                path.display()
+            ));
+        };
+        (
+            path.parent().map(Path::to_path_buf),
+            PathBuf::from(file_name),
+        )
+    } else {
+        let current_dir = env::current_dir()?;
+        let repository = current_dir
+            .ancestors()
+            .find(|dir| dir.join(&path).is_file())
+            .map(Path::to_path_buf);
+        (repository, path)
+    };
+
+    ROOT_CONFIG.set(relative).ok();
+    if let Ok(current_dir) = env::current_dir() {
+        INVOCATION_DIR.set(current_dir).ok();
+    }
+    if let Some(repository) = repository {
+        env::set_current_dir(&repository).with_context(|| {
+            format!(
+                "Could not change to the repository '{}'",
+                repository.display()
+            )
+        })?;
+    }
+    Ok(())
+}
+
 #[allow(dead_code)]
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Configuration {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// if false, the config is neither deployed nor pulled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// the operating systems the config is used on, e.g. `["linux"]`. The config is used everywhere if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub enabled_on: Vec<String>,
+    #[serde(default)]
     pub target: Target,
+    #[serde(default)]
     pub dependencies: Dependencies,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deploy: Option<DeployOptions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull: Option<PullOptions>,
+    /// overrides of the target for single machines, keyed by hostname
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub hosts: HashMap<String, HostOverride>,
 }
 
 #[allow(dead_code)]
@@ -69,14 +270,198 @@ pub struct Configuration {
 pub struct Target {
     #[serde(default)]
     pub exclude: Vec<String>,
+    /// if not empty, only files matching one of these globs are deployed and pulled
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
     #[serde(default)]
     pub require_empty: bool,
+    /// an array of globs of files in which template variables are substituted when deploying
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub templates: Vec<String>,
+    /// an array of globs of files which are stored encrypted in the repository
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secrets: Vec<String>,
+    /// if true, symlinks are followed and the files they point to are copied instead of the links
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub follow_symlinks: bool,
+    /// single files or directories of the config mapped to their locations on the system, on every operating system
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, String>,
+    /// prefixes of file names in the repository mapped to the prefixes they get on the system, e.g. `"dot_" = "."`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rename_rules: BTreeMap<String, String>,
+    /// directories relative to the config which are created when deploying, even though git doesn't keep them if they are empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keep_dirs: Vec<String>,
+    /// whether the files are copied to the system or linked to the repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<DeployStrategy>,
+    /// whether deployed symlinks point to the repository with absolute or relative paths
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symlink_style: Option<SymlinkStyle>,
+    /// a condition like `command_exists("nvim")`, the config is only used on machines where it holds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,
+    /// the line endings text files get on the system, pulling converts them back to the ones of the repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eol: Option<LineEnding>,
+    #[serde(default)]
     pub windows: SingleTarget,
+    #[serde(default)]
     pub linux: SingleTarget,
+    #[serde(default)]
+    pub macos: SingleTarget,
+    /// used instead of `linux` when running under the windows subsystem for linux
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wsl: Option<SingleTarget>,
 }
 
-#[allow(dead_code)]
+/// Options for deploying a config.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct DeployOptions {
+    /// if true, files of earlier deployments which are no longer part of the config are removed
+    #[serde(default)]
+    pub prune: bool,
+    /// if true, files which can't be written because of missing permissions are written as root with sudo or doas, or as administrator on windows
+    #[serde(default)]
+    pub privileged: bool,
+    /// the octal modes deployed files get on unix, keyed by globs relative to the config
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub permissions: BTreeMap<String, String>,
+    #[serde(default)]
+    pub hooks: DeployHooks,
+    /// the same as the top-level `hosts`, for configs which keep everything about deploying in `[deploy]`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub hosts: HashMap<String, HostOverride>,
+    /// the same as `target.files`, for configs which keep everything about deploying in `[deploy]`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, String>,
+}
+
+/// Shell commands which are run when a config is deployed.
 #[derive(Serialize, Deserialize, Debug, Default)]
+pub struct DeployHooks {
+    /// run before any file is written, the deploy is aborted if one of them fails
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre: Vec<String>,
+    /// run after the config was deployed successfully, e.g. to reload a program
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post: Vec<String>,
+}
+
+/// Parses an octal file mode like `600` or `0755`.
+pub fn parse_mode(mode: &str) -> Option<u32> {
+    if !(3..=4).contains(&mode.len()) || !mode.chars().all(|c| ('0'..='7').contains(&c)) {
+        return None;
+    }
+    u32::from_str_radix(mode, 8).ok()
+}
+
+/// Options for pulling changes of a config into the repository.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PullOptions {
+    /// if true, files which are ignored by a `.gitignore` in the target directory aren't pulled
+    #[serde(default)]
+    pub respect_gitignore: bool,
+}
+
+/// How the files of a config are placed on the system.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeployStrategy {
+    /// the files are copied, changes on the system have to be pulled
+    #[default]
+    Copy,
+    /// every file is replaced by a symlink into the repository, so changes on the system end up in the repository directly.
+    /// Templates and secrets are still written as files.
+    Symlink,
+}
+
+/// How the target of a deployed symlink is written.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SymlinkStyle {
+    /// the canonicalized path of the file in the repository
+    #[default]
+    Absolute,
+    /// a path from the directory of the link to the file in the repository,
+    /// which keeps working if both are moved together
+    Relative,
+}
+
+/// The line endings of the text files of a config on the system.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+    /// `crlf` on windows and `lf` everywhere else
+    Native,
+    /// the files are deployed and pulled byte for byte
+    #[default]
+    Keep,
+}
+
+impl LineEnding {
+    /// Resolves `native` to the line ending of the current operating system.
+    pub fn resolve(self) -> Self {
+        match self {
+            LineEnding::Native if cfg!(windows) => LineEnding::Crlf,
+            LineEnding::Native => LineEnding::Lf,
+            eol => eol,
+        }
+    }
+}
+
+impl Target {
+    /// Returns the target for the current operating system.
+    /// Under WSL, the `wsl` target is used if there is one.
+    pub fn current(&self) -> Option<&SingleTarget> {
+        match self.current_name() {
+            "windows" => Some(&self.windows),
+            "linux" => Some(&self.linux),
+            "macos" => Some(&self.macos),
+            "wsl" => self.wsl.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Returns the target for the current operating system.
+    /// Under WSL, the `wsl` target is used if there is one.
+    pub fn current_mut(&mut self) -> Option<&mut SingleTarget> {
+        match self.current_name() {
+            "windows" => Some(&mut self.windows),
+            "linux" => Some(&mut self.linux),
+            "macos" => Some(&mut self.macos),
+            "wsl" => self.wsl.as_mut(),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the table the target for the current operating system is read from.
+    pub fn current_name(&self) -> &'static str {
+        if self.wsl.is_some() && is_wsl() {
+            "wsl"
+        } else {
+            env::consts::OS
+        }
+    }
+}
+
+static WSL: OnceLock<bool> = OnceLock::new();
+
+/// Checks if dottor runs under the windows subsystem for linux.
+pub fn is_wsl() -> bool {
+    *WSL.get_or_init(|| {
+        env::consts::OS == "linux"
+            && (env::var_os("WSL_DISTRO_NAME").is_some()
+                || fs::read_to_string("/proc/version")
+                    .is_ok_and(|version| version.to_lowercase().contains("microsoft")))
+    })
+}
+
+#[allow(dead_code)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct SingleTarget {
     #[serde(default)]
     pub exclude: Vec<String>,
@@ -84,7 +469,95 @@ pub struct SingleTarget {
     pub directory: Option<String>,
     #[serde(default)]
     pub file: Option<String>,
+    /// the path of the file of the config which `file` deploys, defaults to the file name of `file`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// overrides `target.require_empty` for this operating system
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub require_empty: Option<bool>,
+    /// additional files or directories of the config which are deployed to their own locations
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mappings: Vec<Mapping>,
+    /// overrides for linux distributions, keyed by the `ID` of `/etc/os-release`, e.g. `[target.linux.arch]`
+    #[serde(flatten)]
+    pub distros: HashMap<String, HostOverride>,
+}
+
+/// Deploys a single file or directory of a config to a separate location.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Mapping {
+    /// the path of the file or directory, relative to the config
+    pub source: String,
+    /// the location on the system, `~` and environment variables are expanded
+    pub destination: String,
+}
+
+/// Changes to the target of a config which only apply on a single machine or linux distribution.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct HostOverride {
+    /// replaces the target directory, and the target file if one is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directory: Option<String>,
+    /// replaces the target file, and the target directory if one is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// additional excluded files on this machine
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// if false, the config is neither deployed nor pulled on this machine
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+}
+
+impl HostOverride {
+    /// Merges the override over a target.
+    fn apply(&self, target: &mut SingleTarget) {
+        if self.directory.is_some() {
+            target.directory = self.directory.clone();
+            target.file = None;
+        }
+        if self.file.is_some() {
+            target.file = self.file.clone();
+            target.directory = None;
+        }
+        target.exclude.extend(self.exclude.iter().cloned());
+    }
+}
+
+static DISTRO: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Returns the identifiers of the linux distribution, read from `ID` and `ID_LIKE` in `/etc/os-release`.
+/// The most specific one comes first, e.g. `["ubuntu", "debian"]`. Other operating systems have none.
+pub fn distro() -> &'static [String] {
+    DISTRO.get_or_init(|| {
+        if env::consts::OS != "linux" {
+            return Vec::new();
+        }
+        let Ok(release) = fs::read_to_string("/etc/os-release")
+            .or_else(|_| fs::read_to_string("/usr/lib/os-release"))
+        else {
+            return Vec::new();
+        };
+
+        let value = |key: &str| {
+            release
+                .lines()
+                .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+                .map(|value| value.trim().trim_matches(['"', '\'']).to_lowercase())
+        };
+        let mut ids: Vec<String> = value("ID").into_iter().collect();
+        if let Some(like) = value("ID_LIKE") {
+            ids.extend(like.split_whitespace().map(String::from));
+        }
+        ids
+    })
+}
+
+/// Returns the hostname of this machine, if it can be determined.
+pub fn hostname() -> Option<String> {
+    hostname::get()
+        .ok()
+        .map(|hostname| hostname.to_string_lossy().into_owned())
 }
 
 #[allow(dead_code)]
@@ -98,6 +571,33 @@ pub struct Dependencies {
     pub system: Vec<SystemDependency>,
 }
 
+impl Dependencies {
+    /// Returns the names of all local dependencies and whether they are required.
+    /// Dependencies declared in `simple.local` are always required.
+    pub fn local(&self) -> Vec<(&String, bool)> {
+        self.simple
+            .local
+            .iter()
+            .map(|name| (name, true))
+            .chain(self.local.iter().map(|dep| (&dep.name, dep.required)))
+            .collect()
+    }
+
+    /// Returns all system dependencies.
+    /// Dependencies declared in `simple.system` are required and accept every version.
+    pub fn system(&self) -> Vec<SystemDependency> {
+        self.simple
+            .system
+            .iter()
+            .map(|name| SystemDependency {
+                name: name.clone(),
+                ..Default::default()
+            })
+            .chain(self.system.iter().cloned())
+            .collect()
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct SimpleDependencies {
@@ -110,9 +610,12 @@ pub struct SimpleDependencies {
 #[allow(dead_code)]
 #[derive(Serialize, Deserialize, Debug)]
 pub struct LocalDependency {
-    name: String,
-    #[serde(default)]
-    required: bool,
+    pub name: String,
+    #[serde(default = "default_required")]
+    pub required: bool,
+    /// if true, the dependency is deployed whenever the config is deployed on its own
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub auto_deploy: bool,
 }
 
 impl Default for LocalDependency {
@@ -120,23 +623,32 @@ impl Default for LocalDependency {
         Self {
             name: Default::default(),
             required: true,
+            auto_deploy: false,
         }
     }
 }
 
-#[allow(dead_code)]
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SystemDependency {
-    name: String,
-    #[serde(default)]
-    required: bool,
-    #[serde(
-        deserialize_with = "Version::deserialize",
-        serialize_with = "Version::serialize"
-    )]
-    version: Version,
-    #[serde(default)]
-    version_args: String,
+    /// the program, either a name which is searched on the path or a path to the program
+    pub name: String,
+    #[serde(default = "default_required")]
+    pub required: bool,
+    /// the versions of the program which are accepted, every version is accepted if empty
+    #[serde(default, skip_serializing_if = "VersionRequirement::accepts_any")]
+    pub version: VersionRequirement,
+    /// the arguments with which the program prints its version
+    #[serde(default = "default_version_args")]
+    pub version_args: String,
+}
+
+/// dependencies are required unless stated otherwise
+fn default_required() -> bool {
+    true
+}
+
+fn default_version_args() -> String {
+    String::from("--version")
 }
 
 impl Default for SystemDependency {
@@ -150,7 +662,7 @@ impl Default for SystemDependency {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum VersionSpecifier {
     Any,
     None,
@@ -164,12 +676,16 @@ pub enum VersionSpecifier {
 }
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Version {
     pub specifier: VersionSpecifier,
     pub major: u32,
     pub minor: u32,
     pub patch: u32,
+    /// the dot separated prerelease identifiers, e.g. `["rc", "1"]` for `1.0.0-rc.1`
+    pub prerelease: Vec<String>,
+    /// the build metadata, which is kept when serializing but ignored when comparing versions
+    pub build: Option<String>,
 }
 
 impl Version {
@@ -179,29 +695,29 @@ impl Version {
             major,
             minor,
             patch,
+            prerelease: Vec::new(),
+            build: None,
         }
     }
 
     pub fn any() -> Version {
-        Version {
-            specifier: VersionSpecifier::Any,
-            major: 0,
-            minor: 0,
-            patch: 0,
-        }
+        Version::new(VersionSpecifier::Any, 0, 0, 0)
     }
 
+    /// Checks if `version` satisfies the requirement described by `self`.
     pub fn compatible(&self, version: &Self) -> bool {
         match self.specifier {
             VersionSpecifier::Any => true,
-            VersionSpecifier::None | VersionSpecifier::MatchMajor => self.major == version.major,
-            VersionSpecifier::Equals => self == version,
-            VersionSpecifier::GreaterEquals => self >= version,
-            VersionSpecifier::GreaterThan => self > version,
-            VersionSpecifier::LessEquals => self <= version,
-            VersionSpecifier::LessThan => self < version,
+            VersionSpecifier::None | VersionSpecifier::MatchMajor => {
+                self.major == version.major && version >= self
+            }
+            VersionSpecifier::Equals => version == self,
+            VersionSpecifier::GreaterEquals => version >= self,
+            VersionSpecifier::GreaterThan => version > self,
+            VersionSpecifier::LessEquals => version <= self,
+            VersionSpecifier::LessThan => version < self,
             VersionSpecifier::MatchMinor => {
-                self.major == version.major && self.minor == version.minor
+                self.major == version.major && self.minor == version.minor && version >= self
             }
         }
     }
@@ -209,37 +725,14 @@ impl Version {
 
 impl PartialEq for Version {
     fn eq(&self, other: &Self) -> bool {
-        self.major == other.major && self.minor == other.minor && self.patch == other.patch
-    }
-
-    fn ne(&self, other: &Self) -> bool {
-        self.major != other.major || self.minor != other.minor || self.patch != other.patch
+        self.major == other.major
+            && self.minor == other.minor
+            && self.patch == other.patch
+            && self.prerelease == other.prerelease
     }
 }
 
 impl PartialOrd for Version {
-    fn ge(&self, other: &Self) -> bool {
-        self.major >= other.major
-            || self.major == other.major && self.minor >= other.minor
-            || self.major == other.major && self.minor == other.minor && self.patch >= other.patch
-    }
-
-    fn gt(&self, other: &Self) -> bool {
-        self.major > other.major
-            || self.major == other.major && self.minor > other.minor
-            || self.major == other.major && self.minor == other.minor && self.patch > other.patch
-    }
-    fn le(&self, other: &Self) -> bool {
-        self.major <= other.major
-            || self.major == other.major && self.minor <= other.minor
-            || self.major == other.major && self.minor == other.minor && self.patch <= other.patch
-    }
-    fn lt(&self, other: &Self) -> bool {
-        self.major < other.major
-            || self.major == other.major && self.minor < other.minor
-            || self.major == other.major && self.minor == other.minor && self.patch < other.patch
-    }
-
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         let major = self.major.partial_cmp(&other.major)?;
         if major != Ordering::Equal {
@@ -249,22 +742,133 @@ impl PartialOrd for Version {
         if minor != Ordering::Equal {
             return Some(minor);
         }
-        let patch = self.minor.partial_cmp(&other.patch)?;
+        let patch = self.patch.partial_cmp(&other.patch)?;
         if patch != Ordering::Equal {
             return Some(patch);
         }
-        Some(Ordering::Equal)
+        // a prerelease has a lower precedence than its release
+        match (self.prerelease.is_empty(), other.prerelease.is_empty()) {
+            (true, true) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Greater),
+            (false, true) => Some(Ordering::Less),
+            (false, false) => Some(compare_prerelease(&self.prerelease, &other.prerelease)),
+        }
     }
 }
 
+/// Compares prerelease identifiers according to semver precedence.
+/// Numeric identifiers are compared numerically and have a lower precedence than alphanumeric ones,
+/// which are compared lexically. If all identifiers are equal, the longer prerelease is greater.
+fn compare_prerelease(a: &[String], b: &[String]) -> Ordering {
+    for (a, b) in a.iter().zip(b) {
+        let ordering = match (a.parse::<u64>(), b.parse::<u64>()) {
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            (Ok(_), Err(_)) => Ordering::Less,
+            (Err(_), Ok(_)) => Ordering::Greater,
+            (Err(_), Err(_)) => a.cmp(b),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
 impl Default for Version {
     fn default() -> Self {
-        Self {
-            specifier: VersionSpecifier::None,
-            major: 1,
-            minor: 0,
-            patch: 0,
+        Version::new(VersionSpecifier::None, 1, 0, 0)
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.specifier == VersionSpecifier::Any {
+            return write!(f, "*");
+        }
+        write!(
+            f,
+            "{}{}.{}.{}",
+            match self.specifier {
+                VersionSpecifier::Any => "*",
+                VersionSpecifier::None => "",
+                VersionSpecifier::Equals => "=",
+                VersionSpecifier::GreaterEquals => ">=",
+                VersionSpecifier::GreaterThan => ">",
+                VersionSpecifier::LessEquals => "<=",
+                VersionSpecifier::LessThan => "<",
+                VersionSpecifier::MatchMinor => "~",
+                VersionSpecifier::MatchMajor => "^",
+            },
+            self.major,
+            self.minor,
+            self.patch
+        )?;
+        if !self.prerelease.is_empty() {
+            write!(f, "-{}", self.prerelease.join("."))?;
+        }
+        if let Some(build) = &self.build {
+            write!(f, "+{}", build)?;
         }
+        Ok(())
+    }
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    /// Parses a single version constraint like `>=1.2.0` or `*`.
+    fn from_str(v: &str) -> Result<Self, Self::Err> {
+        lazy_static::lazy_static! {
+            static ref RE: Regex = Regex::new(r"^(?P<asterisk>\*)$|^(?P<specifier>=|>=|>|<=|<|~|\^)?(?P<major>0|[1-9]\d*)\.(?P<minor>0|[1-9]\d*)\.(?P<patch>0|[1-9]\d*)(?:-(?P<prerelease>(?:0|[1-9]\d*|\d*[a-zA-Z-][0-9a-zA-Z-]*)(?:\.(?:0|[1-9]\d*|\d*[a-zA-Z-][0-9a-zA-Z-]*))*))?(?:\+(?P<buildmetadata>[0-9a-zA-Z-]+(?:\.[0-9a-zA-Z-]+)*))?$").unwrap();
+        }
+
+        let version_match = RE
+            .captures(v)
+            .ok_or_else(|| format!("could not parse version '{}'", v))?;
+
+        // matches the single asterisk
+        if version_match.name("asterisk").is_some() {
+            return Ok(Version::any());
+        }
+
+        // checks for specifier
+        let specifier = match version_match.name("specifier") {
+            Some(value) => match value.as_str() {
+                "=" => VersionSpecifier::Equals,
+                ">=" => VersionSpecifier::GreaterEquals,
+                ">" => VersionSpecifier::GreaterThan,
+                "<=" => VersionSpecifier::LessEquals,
+                "<" => VersionSpecifier::LessThan,
+                "~" => VersionSpecifier::MatchMinor,
+                "^" => VersionSpecifier::MatchMajor,
+                _ => return Err(String::from("invalid version specifier")),
+            },
+            None => VersionSpecifier::None,
+        };
+
+        // matches actual version
+        let number = |name: &str| {
+            version_match
+                .name(name)
+                .ok_or_else(|| format!("no {} version found", name))?
+                .as_str()
+                .parse::<u32>()
+                .map_err(|error| format!("invalid {} version: {}", name, error))
+        };
+
+        let mut version = Version::new(
+            specifier,
+            number("major")?,
+            number("minor")?,
+            number("patch")?,
+        );
+        if let Some(prerelease) = version_match.name("prerelease") {
+            version.prerelease = prerelease.as_str().split('.').map(String::from).collect();
+        }
+        version.build = version_match
+            .name("buildmetadata")
+            .map(|build| build.as_str().to_string());
+        Ok(version)
     }
 }
 
@@ -273,30 +877,7 @@ impl Serialize for Version {
     where
         S: serde::Serializer,
     {
-        if self.specifier == VersionSpecifier::Any {
-            serializer.serialize_str("*")
-        } else {
-            serializer.serialize_str(
-                format!(
-                    "{}{}.{}.{}",
-                    match self.specifier {
-                        VersionSpecifier::Any => "*",
-                        VersionSpecifier::None => "",
-                        VersionSpecifier::Equals => "=",
-                        VersionSpecifier::GreaterEquals => ">=",
-                        VersionSpecifier::GreaterThan => ">",
-                        VersionSpecifier::LessEquals => "<=",
-                        VersionSpecifier::LessThan => "<",
-                        VersionSpecifier::MatchMinor => "~",
-                        VersionSpecifier::MatchMajor => "^",
-                    },
-                    self.major,
-                    self.minor,
-                    self.patch
-                )
-                .as_str(),
-            )
-        }
+        serializer.serialize_str(&self.to_string())
     }
 }
 
@@ -305,93 +886,84 @@ impl<'de> Deserialize<'de> for Version {
     where
         D: serde::Deserializer<'de>,
     {
-        struct VersionVisitor;
+        let source = String::deserialize(deserializer)?;
+        source.parse().map_err(de::Error::custom)
+    }
+}
 
-        impl<'de> Visitor<'de> for VersionVisitor {
-            type Value = Version;
+/// A list of version constraints which all have to be satisfied, e.g. `>=1.2.0, <2.0.0`.
+#[derive(Debug, Default, Clone)]
+pub struct VersionRequirement(pub Vec<Version>);
 
-            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                formatter.write_str("struct Version")
-            }
+impl VersionRequirement {
+    /// Checks if the requirement accepts every version.
+    pub fn accepts_any(&self) -> bool {
+        self.0
+            .iter()
+            .all(|constraint| constraint.specifier == VersionSpecifier::Any)
+    }
 
-            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
-            where
-                E: de::Error,
-            {
-                lazy_static::lazy_static! {
-                    static ref RE: Regex = Regex::new(r"^(?P<asterisk>\*)$|^(?P<specifier>=|>=|>|<=|<|~|\^)?(?P<major>0|[1-9]\d*)\.(?P<minor>0|[1-9]\d*)\.(?P<patch>0|[1-9]\d*)(?:-(?P<prerelease>(?:0|[1-9]\d*|\d*[a-zA-Z-][0-9a-zA-Z-]*)(?:\.(?:0|[1-9]\d*|\d*[a-zA-Z-][0-9a-zA-Z-]*))*))?(?:\+(?P<buildmetadata>[0-9a-zA-Z-]+(?:\.[0-9a-zA-Z-]+)*))?$").unwrap();
-                }
+    /// Checks if `version` satisfies every constraint.
+    pub fn compatible(&self, version: &Version) -> bool {
+        self.0
+            .iter()
+            .all(|constraint| constraint.compatible(version))
+    }
+}
 
-                if !RE.is_match(v) {
-                    return Err(de::Error::custom("could not parse version"));
-                }
+impl fmt::Display for VersionRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let constraints: Vec<String> = self.0.iter().map(Version::to_string).collect();
+        write!(f, "{}", constraints.join(", "))
+    }
+}
 
-                let version_match = RE.captures(v).unwrap();
+impl FromStr for VersionRequirement {
+    type Err = String;
 
-                // matches the single asterisk
-                if version_match.name("asterisk").is_some() {
-                    return Ok(Version::any());
-                }
+    /// Parses a comma separated list of version constraints.
+    fn from_str(v: &str) -> Result<Self, Self::Err> {
+        let constraints = v
+            .split(',')
+            .map(|constraint| constraint.trim().parse())
+            .collect::<Result<Vec<Version>, String>>()?;
+        Ok(VersionRequirement(constraints))
+    }
+}
 
-                // checks for specifier
-                let specifier = match version_match.name("specifier") {
-                    Some(value) => match value.as_str() {
-                        "=" => VersionSpecifier::Equals,
-                        ">=" => VersionSpecifier::GreaterEquals,
-                        ">" => VersionSpecifier::GreaterThan,
-                        "<=" => VersionSpecifier::LessEquals,
-                        "<" => VersionSpecifier::LessThan,
-                        "~" => VersionSpecifier::MatchMinor,
-                        "^" => VersionSpecifier::MatchMajor,
-                        _ => return Err(de::Error::custom("invalid version specifier")),
-                    },
-                    None => VersionSpecifier::None,
-                };
-
-                // matches actual version
-                let major = version_match
-                    .name("major")
-                    .ok_or_else(|| de::Error::custom("no major version found"))?
-                    .as_str()
-                    .parse::<u32>()
-                    .unwrap();
-                let minor = version_match
-                    .name("minor")
-                    .ok_or_else(|| de::Error::custom("no minor version found"))?
-                    .as_str()
-                    .parse::<u32>()
-                    .unwrap();
-                let patch = version_match
-                    .name("patch")
-                    .ok_or_else(|| de::Error::custom("no patch version found"))?
-                    .as_str()
-                    .parse::<u32>()
-                    .unwrap();
-
-                Ok(Version::new(specifier, major, minor, patch))
-            }
-        }
+impl Serialize for VersionRequirement {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
 
-        deserializer.deserialize_str(VersionVisitor)
+impl<'de> Deserialize<'de> for VersionRequirement {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let source = String::deserialize(deserializer)?;
+        source.parse().map_err(de::Error::custom)
     }
 }
 
 pub const CONFIG_PATH: &str = "dotconfig.toml";
 
-pub fn create_config(name: &str) -> Result<()> {
+/// The operating systems which can be listed in `enabled_on`, named like `std::env::consts::OS`.
+pub const SUPPORTED_OS: [&str; 3] = ["linux", "macos", "windows"];
+
+pub fn create_config(name: &str, config: &Configuration) -> Result<()> {
     let mut path = RelativePathBuf::from(name).to_path(".");
     check_dir_null_or_empty(&path)?;
 
-    fs::create_dir_all(&path);
+    fs::create_dir_all(&path)?;
     path.push(CONFIG_PATH);
-    write(
-        &path,
-        toml::to_string_pretty(&Configuration::default())
-            .context(String::from(
-                "Could not create configuration file in config.",
-            ))?
-            .as_bytes(),
-    )
+    write_configuration(&path, config).context(String::from(
+        "Could not create configuration file in config.",
+    ))
 }
 
 pub fn delete_config(name: &str) -> Result<()> {
@@ -407,6 +979,354 @@ pub fn delete_config(name: &str) -> Result<()> {
     }
 }
 
+pub fn rename_config(old: &str, new: &str) -> Result<()> {
+    let from = RelativePathBuf::from(old).to_path(".");
+    let to = RelativePathBuf::from(new).to_path(".");
+    check_valid_dir(&from)?;
+    // on case-insensitive file systems, a config whose case is changed already exists under the new name
+    if to.exists() && !same_name(old, new) {
+        return Err(anyhow!(
+            "Cannot rename config, '{}' already exists.",
+            to.display()
+        ));
+    }
+
+    fs::rename(&from, &to).with_context(|| {
+        format!(
+            "Could not rename '{}' to '{}'",
+            from.display(),
+            to.display()
+        )
+    })?;
+
+    // keep the name stored inside of the configuration in sync with the directory
+    let path = to.join(CONFIG_PATH);
+    let mut config = read_configuration(&path)?;
+    if config.name.is_some() {
+        config.name = Some(new.to_string());
+        write_configuration(&path, &config)?;
+    }
+    Ok(())
+}
+
+pub fn write_configuration(file: &Path, config: &Configuration) -> Result<()> {
+    write(
+        file,
+        toml::to_string_pretty(config)
+            .context(String::from("Could not serialize configuration."))?
+            .as_bytes(),
+    )
+}
+
+impl Configuration {
+    /// Returns the override of the current linux distribution, if the target has one.
+    /// Distributions which are based on another one, like ubuntu on debian, fall back to its override.
+    fn distro_override<'a>(
+        &self,
+        target: &'a SingleTarget,
+    ) -> Option<(&'static str, &'a HostOverride)> {
+        distro()
+            .iter()
+            .find_map(|id| Some((id.as_str(), target.distros.get(id)?)))
+    }
+
+    /// Returns the target for the current operating system, with the overrides for the linux distribution
+    /// and the host merged over it.
+    /// # Errors
+    /// Returns an error if the operating system isn't supported.
+    pub fn host_target(&self, hostname: Option<&str>) -> Result<SingleTarget> {
+        let current = self
+            .target
+            .current()
+            .ok_or_else(|| anyhow!("Operating system '{}' is not supported.", env::consts::OS))?;
+        let mut target = current.clone();
+
+        if let Some((_, distro)) = self.distro_override(current) {
+            distro.apply(&mut target);
+        }
+        if let Some(host) = hostname.and_then(|hostname| self.host(hostname)) {
+            host.apply(&mut target);
+        }
+        Ok(target)
+    }
+
+    /// Returns the override for a machine from `[hosts]`, or from `[deploy.hosts]` if there is none.
+    pub fn host(&self, hostname: &str) -> Option<&HostOverride> {
+        self.hosts.get(hostname).or_else(|| {
+            self.deploy
+                .as_ref()
+                .and_then(|deploy| deploy.hosts.get(hostname))
+        })
+    }
+
+    /// all overrides for single machines together with the table they are declared in
+    fn all_hosts(&self) -> impl Iterator<Item = (String, &HostOverride)> {
+        let deploy_hosts = self.deploy.iter().flat_map(|deploy| &deploy.hosts);
+        self.hosts
+            .iter()
+            .map(|(hostname, host)| (format!("hosts.{}", hostname), host))
+            .chain(
+                deploy_hosts.map(|(hostname, host)| (format!("deploy.hosts.{}", hostname), host)),
+            )
+    }
+
+    /// Returns the single files or directories which are mapped to their own locations,
+    /// from `target.files` and `[deploy.files]`. A path in both tables keeps the entry of `target.files`.
+    pub fn files(&self) -> BTreeMap<&String, &String> {
+        let deploy_files = self.deploy.iter().flat_map(|deploy| &deploy.files);
+        deploy_files.chain(&self.target.files).collect()
+    }
+
+    /// Checks if the config is used on the current operating system, linux distribution and host.
+    /// Returns the reason if the config has to be skipped.
+    pub fn skip_reason(&self, hostname: Option<&str>) -> Option<String> {
+        if self.enabled == Some(false) {
+            return Some(String::from("disabled"));
+        }
+        if !self.enabled_on.is_empty() && !self.enabled_on.iter().any(|os| os == env::consts::OS) {
+            return Some(format!("not enabled on {}", env::consts::OS));
+        }
+        let distro = self
+            .target
+            .current()
+            .and_then(|target| self.distro_override(target));
+        if let Some((id, distro)) = distro {
+            if distro.enabled == Some(false) {
+                return Some(format!("disabled on {}", id));
+            }
+        }
+        let host = hostname.and_then(|hostname| self.host(hostname).map(|host| (hostname, host)));
+        if let Some((hostname, host)) = host {
+            if host.enabled == Some(false) {
+                return Some(format!("disabled on host '{}'", hostname));
+            }
+        }
+        let when = self.target.when.as_ref()?;
+        match condition::parse(when) {
+            Ok(condition) if condition.evaluate(hostname) => None,
+            Ok(_) => Some(format!("excluded by the condition '{}'", when)),
+            // the config is validated separately, an invalid condition never holds
+            Err(_) => Some(format!("excluded by the invalid condition '{}'", when)),
+        }
+    }
+
+    /// Checks the configuration for semantic errors which aren't caught while parsing.
+    /// Returns a description of every problem found.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        let mut check_globs = |field: &str, patterns: &Vec<String>| {
+            for pattern in patterns {
+                if let Err(error) = Glob::new(pattern) {
+                    problems.push(format!(
+                        "Invalid glob '{}' in '{}': {}",
+                        pattern, field, error
+                    ));
+                }
+            }
+        };
+        check_globs("target.exclude", &self.target.exclude);
+        check_globs("target.include", &self.target.include);
+        check_globs("target.templates", &self.target.templates);
+        check_globs("target.secrets", &self.target.secrets);
+        check_globs("target.windows.exclude", &self.target.windows.exclude);
+        check_globs("target.linux.exclude", &self.target.linux.exclude);
+        check_globs("target.macos.exclude", &self.target.macos.exclude);
+        if let Some(wsl) = &self.target.wsl {
+            check_globs("target.wsl.exclude", &wsl.exclude);
+        }
+        for (table, host) in self.all_hosts() {
+            check_globs(&format!("{}.exclude", table), &host.exclude);
+        }
+        for (id, distro) in &self.target.linux.distros {
+            check_globs(&format!("target.linux.{}.exclude", id), &distro.exclude);
+        }
+
+        for (table, host) in self.all_hosts() {
+            if host.directory.is_some() && host.file.is_some() {
+                problems.push(format!(
+                    "Cannot use both 'directory' and 'file' targets in '{}'.",
+                    table
+                ));
+            }
+        }
+        if let Some(deploy) = &self.deploy {
+            for hostname in deploy
+                .hosts
+                .keys()
+                .filter(|hostname| self.hosts.contains_key(*hostname))
+            {
+                problems.push(format!(
+                    "Host '{}' is overridden in both 'hosts' and 'deploy.hosts'.",
+                    hostname
+                ));
+            }
+        }
+        for (id, distro) in &self.target.linux.distros {
+            if distro.directory.is_some() && distro.file.is_some() {
+                problems.push(format!(
+                    "Cannot use both 'directory' and 'file' targets in 'target.linux.{}'.",
+                    id
+                ));
+            }
+        }
+        for (os, target) in [
+            ("windows", &self.target.windows),
+            ("macos", &self.target.macos),
+        ] {
+            for key in target.distros.keys() {
+                problems.push(format!(
+                    "Unknown key '{}' in 'target.{}', distribution overrides are only supported on linux.",
+                    key, os
+                ));
+            }
+        }
+
+        for (os, target) in [
+            ("windows", Some(&self.target.windows)),
+            ("linux", Some(&self.target.linux)),
+            ("macos", Some(&self.target.macos)),
+            ("wsl", self.target.wsl.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(os, target)| Some((os, target?)))
+        {
+            if target.directory.is_some() && target.file.is_some() {
+                problems.push(format!(
+                    "Cannot use both 'directory' and 'file' targets in 'target.{}'.",
+                    os
+                ));
+            }
+            if target.source.is_some() && target.file.is_none() {
+                problems.push(format!(
+                    "'source' in 'target.{}' is only used together with 'file'.",
+                    os
+                ));
+            }
+            for mapping in &target.mappings {
+                if mapping.source.is_empty() || mapping.destination.is_empty() {
+                    problems.push(format!(
+                        "Mappings in 'target.{}' need both a 'source' and a 'destination'.",
+                        os
+                    ));
+                }
+            }
+        }
+
+        for (source, destination) in &self.target.files {
+            if source.is_empty() || destination.is_empty() {
+                problems.push(String::from(
+                    "Entries of 'target.files' need both a path and a destination.",
+                ));
+            }
+        }
+        if let Some(deploy) = &self.deploy {
+            for (source, destination) in &deploy.files {
+                if source.is_empty() || destination.is_empty() {
+                    problems.push(String::from(
+                        "Entries of 'deploy.files' need both a path and a destination.",
+                    ));
+                }
+                if self.target.files.contains_key(source) {
+                    problems.push(format!(
+                        "'{}' is mapped in both 'target.files' and 'deploy.files'.",
+                        source
+                    ));
+                }
+            }
+        }
+
+        for (repo, system) in &self.target.rename_rules {
+            if repo.is_empty()
+                || system.is_empty()
+                || [repo, system]
+                    .iter()
+                    .any(|prefix| prefix.contains(['/', '\\']))
+            {
+                problems.push(format!(
+                    "The rule '{}' = '{}' in 'target.rename_rules' needs two non-empty prefixes without path separators.",
+                    repo, system
+                ));
+            }
+        }
+
+        for dir in &self.target.keep_dirs {
+            let path = Path::new(dir);
+            if dir.is_empty()
+                || path.is_absolute()
+                || path
+                    .components()
+                    .any(|component| component == std::path::Component::ParentDir)
+            {
+                problems.push(format!(
+                    "'{}' in 'target.keep_dirs' has to be a path inside of the config.",
+                    dir
+                ));
+            }
+        }
+
+        if let Some(when) = &self.target.when {
+            if let Err(error) = condition::parse(when) {
+                problems.push(format!("Invalid condition in 'target.when': {}", error));
+            }
+        }
+
+        if let Some(deploy) = &self.deploy {
+            for (pattern, mode) in &deploy.permissions {
+                if let Err(error) = Glob::new(pattern) {
+                    problems.push(format!(
+                        "Invalid glob '{}' in 'deploy.permissions': {}",
+                        pattern, error
+                    ));
+                }
+                if parse_mode(mode).is_none() {
+                    problems.push(format!(
+                        "Invalid mode '{}' for '{}' in 'deploy.permissions', expected an octal mode like '600'.",
+                        mode, pattern
+                    ));
+                }
+            }
+        }
+
+        for os in &self.enabled_on {
+            if !SUPPORTED_OS.contains(&os.as_str()) {
+                problems.push(format!(
+                    "Unknown operating system '{}' in 'enabled_on', expected one of {}.",
+                    os,
+                    SUPPORTED_OS.join(", ")
+                ));
+            }
+        }
+
+        // the target of the current operating system only has to be complete if the config is used here
+        if !self.enabled_on.is_empty() && !self.enabled_on.iter().any(|os| os == env::consts::OS) {
+            return problems;
+        }
+
+        match self.target.current() {
+            Some(target) => {
+                let is_empty = |value: &Option<String>| value.as_ref().is_none_or(|v| v.is_empty());
+                if is_empty(&target.directory)
+                    && is_empty(&target.file)
+                    && target.mappings.is_empty()
+                    && self.files().is_empty()
+                {
+                    problems.push(format!(
+                        "'file', 'directory', 'mappings', 'target.files' or 'deploy.files' must be set in 'target.{}'.",
+                        self.target.current_name()
+                    ));
+                }
+            }
+            None => problems.push(format!(
+                "Operating system '{}' is not supported.",
+                env::consts::OS
+            )),
+        }
+
+        problems
+    }
+}
+
 pub fn read_configuration(file: &Path) -> Result<Configuration> {
     let source = read_to_string(file)?;
     let config = toml::from_str(&source)
@@ -416,8 +1336,314 @@ pub fn read_configuration(file: &Path) -> Result<Configuration> {
 
 pub fn read_root_configuration() -> Result<RootConfiguration> {
     assert_root_present()?;
-    let source = read_to_string(&RelativePathBuf::from(ROOT_PATH).to_path("."))?;
-    let config =
+    let source = read_to_string(root_path())?;
+    let mut config: RootConfiguration =
         toml::from_str(&source).context(String::from("Could not parse root configuration."))?;
+
+    let mut exclude_patterns = GlobSetBuilder::new();
+    for pattern in &config.exclude {
+        // a trailing slash marks directories, but the globs are matched against plain paths
+        let pattern = pattern.strip_suffix('/').unwrap_or(pattern);
+        exclude_patterns.add(Glob::new(pattern).with_context(|| {
+            format!(
+                "Invalid glob '{}' in 'exclude' of {}",
+                pattern,
+                root_path().display()
+            )
+        })?);
+    }
+    config.exclude_patterns = exclude_patterns.build()?;
     Ok(config)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(version: &str) -> Version {
+        version.parse().unwrap()
+    }
+
+    /// parses a config whose target for the current operating system is given by `target`
+    fn config(target: &str, rest: &str) -> Configuration {
+        toml::from_str(&format!("[target.{}]\n{target}\n{rest}", env::consts::OS)).unwrap()
+    }
+
+    #[test]
+    fn versions_are_ordered_by_semver_precedence() {
+        // every version has a lower precedence than the next one, taken from the semver specification
+        let ordered = [
+            "1.0.0-alpha",
+            "1.0.0-alpha.1",
+            "1.0.0-alpha.beta",
+            "1.0.0-beta",
+            "1.0.0-beta.2",
+            "1.0.0-beta.11",
+            "1.0.0-rc.1",
+            "1.0.0",
+            "1.0.1",
+            "1.1.0",
+            "1.10.0",
+            "2.0.0-0",
+            "2.0.0",
+        ];
+        for pair in ordered.windows(2) {
+            assert!(
+                version(pair[0]) < version(pair[1]),
+                "{} < {}",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+
+    #[test]
+    fn versions_are_compared_by_major_minor_and_patch() {
+        let cases = [
+            ("1.2.3", "1.2.4", Ordering::Less),
+            ("1.2.10", "1.2.9", Ordering::Greater),
+            ("1.3.0", "1.2.9", Ordering::Greater),
+            ("2.0.0", "1.9.9", Ordering::Greater),
+            ("0.9.9", "1.0.0", Ordering::Less),
+            ("1.2.3", "1.2.3", Ordering::Equal),
+        ];
+        for (a, b, ordering) in cases {
+            assert_eq!(
+                version(a).partial_cmp(&version(b)),
+                Some(ordering),
+                "{} <=> {}",
+                a,
+                b
+            );
+        }
+        // the operators agree with the ordering, `1.9.0 >= 1.2.3` used to fail on the minor version
+        assert!(version("1.9.0") >= version("1.2.3"));
+        assert!(version("2.1.0") > version("1.5.0"));
+        assert!(version("1.5.0") < version("2.1.0"));
+    }
+
+    #[test]
+    fn build_metadata_is_kept_but_not_compared() {
+        for (a, b) in [
+            ("1.0.0+build.5", "1.0.0+build.6"),
+            ("1.0.0+001", "1.0.0"),
+            ("1.0.0-rc.1+linux", "1.0.0-rc.1"),
+        ] {
+            assert!(version(a) == version(b), "{} == {}", a, b);
+        }
+        for text in ["1.0.0-rc.1+linux.x86-64", ">=2.1.0-beta", "~0.3.7+b"] {
+            assert_eq!(version(text).to_string(), text);
+        }
+    }
+
+    #[test]
+    fn invalid_versions_are_rejected() {
+        for text in [
+            "1.0",
+            "01.0.0",
+            "1.0.0-",
+            "1.0.0+",
+            "1.0.0-a..b",
+            "1.0.0-rc.01",
+            "=>1.0.0",
+            "v1.0.0",
+        ] {
+            assert!(text.parse::<Version>().is_err(), "{} was parsed", text);
+        }
+    }
+
+    #[test]
+    fn specifiers_match_versions() {
+        let cases = [
+            ("*", "0.0.1", true),
+            ("1.2.3", "1.9.0", true),
+            ("1.2.3", "2.0.0", false),
+            ("^1.2.3", "1.2.2", false),
+            ("~1.2.3", "1.2.9", true),
+            ("~1.2.3", "1.3.0", false),
+            ("=1.2.3", "1.2.3+build", true),
+            ("=1.2.3", "1.2.3-rc.1", false),
+            (">=1.2.3", "1.2.3", true),
+            (">1.2.3", "1.2.3", false),
+            ("<2.0.0", "2.0.0-rc.1", true),
+            ("<=2.0.0", "2.0.0", true),
+        ];
+        for (requirement, installed, compatible) in cases {
+            assert_eq!(
+                version(requirement).compatible(&version(installed)),
+                compatible,
+                "{} accepts {}",
+                requirement,
+                installed
+            );
+        }
+    }
+
+    #[test]
+    fn version_ranges_require_every_constraint() {
+        let cases = [
+            // overlapping ranges accept the versions in all of them
+            (">=1.2.0, <2.0.0, ^1.4.0", "1.5.0", true),
+            (">=1.2.0, <2.0.0, ^1.4.0", "1.3.0", false),
+            (">=1.2.0, <2.0.0, ^1.4.0", "2.0.0", false),
+            (">1.0.0,<=1.0.5", "1.0.5", true),
+            (">=1.0.0-rc.1, <1.0.0", "1.0.0-rc.2", true),
+            // contradictory ranges accept nothing
+            (">=2.0.0, <1.0.0", "1.5.0", false),
+            (">=2.0.0, <1.0.0", "2.0.0", false),
+            ("=1.0.0, =1.0.1", "1.0.0", false),
+            ("*, <1.0.0", "0.9.0", true),
+        ];
+        for (requirement, installed, compatible) in cases {
+            let requirement: VersionRequirement = requirement.parse().unwrap();
+            assert_eq!(
+                requirement.compatible(&version(installed)),
+                compatible,
+                "{} accepts {}",
+                requirement,
+                installed
+            );
+        }
+    }
+
+    #[test]
+    fn version_ranges_are_parsed_and_printed() {
+        let requirement: VersionRequirement = " >=1.2.0 ,<2.0.0-0".parse().unwrap();
+        assert_eq!(requirement.to_string(), ">=1.2.0, <2.0.0-0");
+        assert!(!requirement.accepts_any());
+        assert!(VersionRequirement::default().accepts_any());
+        assert!("*".parse::<VersionRequirement>().unwrap().accepts_any());
+        assert!(">=1.0.0,".parse::<VersionRequirement>().is_err());
+    }
+
+    #[test]
+    fn hosts_override_the_target() {
+        let config = config(
+            "directory = '~/.config/app'\nexclude = ['cache']",
+            "[hosts.work]\ndirectory = '/work/app'\nexclude = ['secret']\n\
+             [hosts.server]\nfile = '/etc/app.conf'\n[hosts.laptop]\nenabled = false",
+        );
+
+        let target = config.host_target(Some("home")).unwrap();
+        assert_eq!(target.directory.as_deref(), Some("~/.config/app"));
+        let target = config.host_target(None).unwrap();
+        assert_eq!(target.directory.as_deref(), Some("~/.config/app"));
+
+        let target = config.host_target(Some("work")).unwrap();
+        assert_eq!(target.directory.as_deref(), Some("/work/app"));
+        assert_eq!(target.exclude, ["cache", "secret"]);
+
+        let target = config.host_target(Some("server")).unwrap();
+        assert_eq!(target.file.as_deref(), Some("/etc/app.conf"));
+        assert_eq!(target.directory, None);
+
+        assert_eq!(config.skip_reason(Some("work")), None);
+        assert_eq!(
+            config.skip_reason(Some("laptop")).as_deref(),
+            Some("disabled on host 'laptop'")
+        );
+    }
+
+    #[test]
+    fn hosts_can_be_overridden_in_the_deploy_table() {
+        let config = config(
+            "directory = '~/.config/app'",
+            "[deploy.hosts.\"my-laptop\"]\ndirectory = '/home/me/app'\nexclude = ['cache']\n\
+             [deploy.hosts.server]\nenabled = false",
+        );
+
+        let target = config.host_target(Some("my-laptop")).unwrap();
+        assert_eq!(target.directory.as_deref(), Some("/home/me/app"));
+        assert_eq!(target.exclude, ["cache"]);
+        assert_eq!(
+            config.skip_reason(Some("server")).as_deref(),
+            Some("disabled on host 'server'")
+        );
+        assert!(config.validate().is_empty());
+
+        let config = self::config(
+            "directory = '~/.config/app'",
+            "[hosts.work]\ndirectory = '/a'\n[deploy.hosts.work]\ndirectory = '/b'\nfile = '/c'",
+        );
+        assert_eq!(
+            config.validate(),
+            [
+                "Cannot use both 'directory' and 'file' targets in 'deploy.hosts.work'.",
+                "Host 'work' is overridden in both 'hosts' and 'deploy.hosts'."
+            ]
+        );
+    }
+
+    #[test]
+    fn files_can_be_mapped_in_the_deploy_table() {
+        let config = config(
+            "",
+            "[target]\nfiles = { 'a' = '~/a' }\n[deploy.files]\n'b' = '~/b'",
+        );
+        let files: Vec<(&str, &str)> = config
+            .files()
+            .into_iter()
+            .map(|(source, destination)| (source.as_str(), destination.as_str()))
+            .collect();
+        assert_eq!(files, [("a", "~/a"), ("b", "~/b")]);
+        assert!(config.validate().is_empty(), "{:?}", config.validate());
+
+        let config = self::config(
+            "",
+            "[target]\nfiles = { 'a' = '~/a' }\n[deploy.files]\n'a' = '~/b'",
+        );
+        assert_eq!(
+            config.files().get(&String::from("a")).unwrap().as_str(),
+            "~/a"
+        );
+        assert_eq!(
+            config.validate(),
+            ["'a' is mapped in both 'target.files' and 'deploy.files'."]
+        );
+    }
+
+    #[test]
+    fn missing_target_sections_default() {
+        let config = config("directory = '~/.config/app'", "");
+        assert_eq!(
+            config.target.current().unwrap().directory.as_deref(),
+            Some("~/.config/app")
+        );
+        assert!(config.dependencies.local().is_empty());
+        assert!(config.validate().is_empty());
+
+        let empty: Configuration = toml::from_str("").unwrap();
+        assert_eq!(empty.target.current().unwrap().directory, None);
+        // only `target.require_empty` is written, the unset ones of the operating systems aren't
+        let written = toml::to_string(&config).unwrap();
+        assert_eq!(written.matches("require_empty").count(), 1, "{}", written);
+    }
+
+    #[test]
+    fn disabled_configs_are_skipped() {
+        let os = env::consts::OS;
+        let other = if os == "windows" { "linux" } else { "windows" };
+        let cases = [
+            (String::new(), None),
+            (String::from("enabled = true"), None),
+            (
+                String::from("enabled = false"),
+                Some(String::from("disabled")),
+            ),
+            (format!("enabled_on = ['{os}']"), None),
+            (format!("enabled_on = ['{other}', '{os}']"), None),
+            (
+                format!("enabled_on = ['{other}']"),
+                Some(format!("not enabled on {os}")),
+            ),
+            (
+                format!("enabled = false\nenabled_on = ['{os}']"),
+                Some(String::from("disabled")),
+            ),
+        ];
+        for (fields, reason) in cases {
+            let config: Configuration = toml::from_str(&fields).unwrap();
+            assert_eq!(config.skip_reason(None), reason, "{}", fields);
+        }
+    }
+}
@@ -0,0 +1,509 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{anyhow, Result};
+use regex::Regex;
+
+use crate::{
+    config::{Configuration, SystemDependency, Version, VersionSpecifier},
+    style,
+};
+
+/// Orders the selected configurations, so that every configuration comes after
+/// the local dependencies which are part of the selection.
+/// Missing dependencies which aren't required only cause a warning.
+/// # Errors
+/// Returns an error if a required local dependency doesn't exist or the dependencies are circular.
+pub fn deploy_order(
+    configs: &HashMap<String, Configuration>,
+    selected: &[String],
+) -> Result<Vec<String>> {
+    if let Some(cycle) = find_cycle(configs, selected) {
+        return Err(anyhow!(
+            "Circular dependency between configs: {}",
+            cycle.join(" -> ")
+        ));
+    }
+
+    let selection: HashSet<&String> = selected.iter().collect();
+    let mut names: Vec<&String> = selected.iter().collect();
+    names.sort();
+
+    let mut order = Vec::new();
+    let mut visited = HashSet::new();
+    for name in names {
+        visit(name, configs, &selection, &mut visited, &mut order)?;
+    }
+
+    Ok(order)
+}
+
+/// Collects a config together with the local dependencies which are deployed along with it, and their own ones.
+/// With `all`, every local dependency is included, otherwise only the ones with `auto_deploy`.
+/// Dependencies which don't exist are left out, [`deploy_order`] reports them.
+pub fn deployed_with(
+    configs: &HashMap<String, Configuration>,
+    name: &str,
+    all: bool,
+) -> Vec<String> {
+    let mut names = vec![name.to_string()];
+    let mut index = 0;
+    while let Some(name) = names.get(index) {
+        let dependencies: Vec<String> = configs[name]
+            .dependencies
+            .local
+            .iter()
+            .filter(|dependency| all || dependency.auto_deploy)
+            .map(|dependency| &dependency.name)
+            .chain(
+                all.then_some(&configs[name].dependencies.simple.local)
+                    .into_iter()
+                    .flatten(),
+            )
+            .filter(|dependency| configs.contains_key(*dependency) && !names.contains(dependency))
+            .cloned()
+            .collect();
+        names.extend(dependencies);
+        index += 1;
+    }
+    names
+}
+
+/// Groups configs which are in deploy order into stages.
+/// The configs of a stage only depend on configs of earlier stages, so they can be deployed at the same time.
+pub fn deploy_stages(
+    configs: &HashMap<String, Configuration>,
+    order: &[String],
+) -> Vec<Vec<String>> {
+    let mut levels: HashMap<&String, usize> = HashMap::new();
+    let mut stages: Vec<Vec<String>> = Vec::new();
+
+    for name in order {
+        // dependencies always come first in the order, so their level is already known
+        let level = configs[name]
+            .dependencies
+            .local()
+            .iter()
+            .filter_map(|(dependency, _)| levels.get(dependency))
+            .map(|level| level + 1)
+            .max()
+            .unwrap_or(0);
+        levels.insert(name, level);
+
+        if stages.len() <= level {
+            stages.resize_with(level + 1, Vec::new);
+        }
+        stages[level].push(name.clone());
+    }
+
+    stages
+}
+
+/// Returns the first required local dependency of a config which wasn't deployed.
+/// Optional dependencies which weren't deployed only cause a warning.
+pub fn failed_dependency<'a>(
+    name: &str,
+    config: &'a Configuration,
+    failed: &HashSet<String>,
+) -> Option<&'a String> {
+    let mut required = None;
+    for (dependency, is_required) in config.dependencies.local() {
+        if !failed.contains(dependency) {
+            continue;
+        }
+        if is_required {
+            required.get_or_insert(dependency);
+        } else {
+            style::warn(&format!(
+                "Optional dependency '{}' of config '{}' failed.",
+                dependency, name
+            ));
+        }
+    }
+    required
+}
+
+/// depth-first traversal of the dependency graph, adding every config after its dependencies.
+/// The graph must not contain any cycles.
+fn visit<'a>(
+    name: &'a String,
+    configs: &'a HashMap<String, Configuration>,
+    selection: &HashSet<&String>,
+    visited: &mut HashSet<&'a String>,
+    order: &mut Vec<String>,
+) -> Result<()> {
+    if !visited.insert(name) {
+        return Ok(());
+    }
+
+    let mut dependencies = configs[name].dependencies.local();
+    dependencies.sort();
+    for (dependency, required) in dependencies {
+        if !configs.contains_key(dependency) {
+            if required {
+                return Err(anyhow!(
+                    "Config '{}' requires the config '{}', which does not exist.",
+                    name,
+                    dependency
+                ));
+            }
+            style::warn(&format!(
+                "Config '{}' depends on the config '{}', which does not exist.",
+                name, dependency
+            ));
+            continue;
+        }
+
+        if selection.contains(dependency) {
+            visit(dependency, configs, selection, visited, order)?;
+        }
+    }
+
+    order.push(name.clone());
+    Ok(())
+}
+
+/// Searches for circular local dependencies reachable from the given configurations.
+/// Returns the path of the first cycle found, e.g. `["a", "b", "a"]`.
+/// Dependencies which don't exist are ignored.
+pub fn find_cycle(
+    configs: &HashMap<String, Configuration>,
+    start: &[String],
+) -> Option<Vec<String>> {
+    let mut names: Vec<&String> = start.iter().collect();
+    names.sort();
+
+    let mut visited = HashSet::new();
+    let mut stack = Vec::new();
+    names
+        .into_iter()
+        .find_map(|name| search_cycle(name, configs, &mut visited, &mut stack))
+}
+
+fn search_cycle<'a>(
+    name: &'a String,
+    configs: &'a HashMap<String, Configuration>,
+    visited: &mut HashSet<&'a String>,
+    stack: &mut Vec<&'a String>,
+) -> Option<Vec<String>> {
+    if let Some(position) = stack.iter().position(|entry| *entry == name) {
+        let mut cycle: Vec<String> = stack[position..]
+            .iter()
+            .map(|entry| entry.to_string())
+            .collect();
+        cycle.push(name.clone());
+        return Some(cycle);
+    }
+    if !visited.insert(name) {
+        return None;
+    }
+
+    let config = configs.get(name)?;
+    let mut dependencies = config.dependencies.local();
+    dependencies.sort();
+
+    stack.push(name);
+    let cycle = dependencies
+        .into_iter()
+        .find_map(|(dependency, _)| search_cycle(dependency, configs, visited, stack));
+    stack.pop();
+    cycle
+}
+
+/// Checks that the programs a config depends on are installed in a compatible version.
+/// Missing dependencies which aren't required only cause a warning.
+/// # Errors
+/// Returns an error if a required program can't be found or its version doesn't match.
+pub fn check_system(name: &str, config: &Configuration) -> Result<()> {
+    for dependency in config.dependencies.system() {
+        let Err(problem) = system_status(name, &dependency) else {
+            continue;
+        };
+
+        if dependency.required {
+            return Err(anyhow!(problem));
+        }
+        style::warn(&format!(
+            "{} The dependency is optional, continuing.",
+            problem
+        ));
+    }
+    Ok(())
+}
+
+/// The outcome of checking a single dependency of a config.
+pub struct DependencyCheck {
+    /// what was checked, e.g. "program 'git'"
+    pub dependency: String,
+    pub required: bool,
+    /// why the dependency isn't satisfied
+    pub problem: Option<String>,
+}
+
+/// Checks all local and system dependencies of a config without changing anything.
+/// Circular local dependencies are reported as a problem of the config itself.
+pub fn check_all(
+    name: &str,
+    config: &Configuration,
+    configs: &HashMap<String, Configuration>,
+) -> Vec<DependencyCheck> {
+    let mut checks = Vec::new();
+
+    let mut local = config.dependencies.local();
+    local.sort();
+    for (dependency, required) in local {
+        checks.push(DependencyCheck {
+            dependency: format!("config '{}'", dependency),
+            required,
+            problem: (!configs.contains_key(dependency)).then(|| {
+                format!(
+                    "Config '{}' depends on the config '{}', which does not exist.",
+                    name, dependency
+                )
+            }),
+        });
+    }
+    if let Some(cycle) = find_cycle(configs, &[name.to_string()]) {
+        checks.push(DependencyCheck {
+            dependency: String::from("local dependencies"),
+            required: true,
+            problem: Some(format!(
+                "Circular dependency between configs: {}",
+                cycle.join(" -> ")
+            )),
+        });
+    }
+
+    for dependency in config.dependencies.system() {
+        let status = system_status(name, &dependency);
+        let description = match &status {
+            Ok(Some(version)) => format!("program '{}' {}", dependency.name, version),
+            _ => format!("program '{}'", dependency.name),
+        };
+        checks.push(DependencyCheck {
+            dependency: description,
+            required: dependency.required,
+            problem: status.err(),
+        });
+    }
+
+    checks
+}
+
+/// Checks if a program is installed in a compatible version.
+/// Returns the installed version if the dependency has version requirements, or why the dependency isn't satisfied.
+fn system_status(name: &str, dependency: &SystemDependency) -> Result<Option<Version>, String> {
+    let Some(program) = find_program(&dependency.name) else {
+        return Err(format!(
+            "Config '{}' depends on the program '{}', which could not be found.",
+            name, dependency.name
+        ));
+    };
+    if dependency.version.accepts_any() {
+        return Ok(None);
+    }
+
+    match program_version(&program, dependency) {
+        Some(version) if dependency.version.compatible(&version) => Ok(Some(version)),
+        Some(version) => Err(format!(
+            "Config '{}' depends on '{}' in version '{}', but '{}' is installed.",
+            name, dependency.name, dependency.version, version
+        )),
+        None => Err(format!(
+            "Config '{}' depends on '{}' in version '{}', but its version could not be determined.",
+            name, dependency.name, dependency.version
+        )),
+    }
+}
+
+/// searches the program on the path, unless a path to the program is given
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+
+    let extensions: Vec<String> = if cfg!(windows) {
+        env::var("PATHEXT")
+            .unwrap_or_else(|_| String::from(".EXE;.CMD;.BAT"))
+            .split(';')
+            .map(String::from)
+            .chain([String::new()])
+            .collect()
+    } else {
+        vec![String::new()]
+    };
+
+    env::split_paths(&env::var_os("PATH")?).find_map(|dir| {
+        extensions.iter().find_map(|extension| {
+            let candidate = dir.join(format!("{}{}", program, extension));
+            candidate.is_file().then_some(candidate)
+        })
+    })
+}
+
+/// runs the program with its version arguments and parses the first version in the output
+fn program_version(program: &Path, dependency: &SystemDependency) -> Option<Version> {
+    lazy_static::lazy_static! {
+        static ref RE: Regex = Regex::new(r"(\d+)\.(\d+)(?:\.(\d+))?").unwrap();
+    }
+
+    let output = Command::new(program)
+        .args(dependency.version_args.split_whitespace())
+        .output()
+        .ok()?;
+    let output = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let captures = RE.captures(&output)?;
+    let number = |index: usize| {
+        captures
+            .get(index)
+            .map_or(Some(0), |value| value.as_str().parse().ok())
+    };
+    Some(Version::new(
+        VersionSpecifier::None,
+        number(1)?,
+        number(2)?,
+        number(3)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Dependencies, LocalDependency};
+
+    /// builds configs from their names and `(dependency, required)` pairs
+    fn configs(graph: &[(&str, &[(&str, bool)])]) -> HashMap<String, Configuration> {
+        graph
+            .iter()
+            .map(|(name, dependencies)| {
+                let local = dependencies
+                    .iter()
+                    .map(|(dependency, required)| LocalDependency {
+                        name: dependency.to_string(),
+                        required: *required,
+                        ..Default::default()
+                    })
+                    .collect();
+                let config = Configuration {
+                    dependencies: Dependencies {
+                        local,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                (name.to_string(), config)
+            })
+            .collect()
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn chain_is_ordered_by_dependencies() {
+        let configs = configs(&[("a", &[("b", true)]), ("b", &[("c", true)]), ("c", &[])]);
+        let selected = names(&["a", "b", "c"]);
+        assert_eq!(find_cycle(&configs, &selected), None);
+        assert_eq!(
+            deploy_order(&configs, &selected).unwrap(),
+            names(&["c", "b", "a"])
+        );
+        assert_eq!(
+            deploy_stages(&configs, &names(&["c", "b", "a"])),
+            [names(&["c"]), names(&["b"]), names(&["a"])]
+        );
+    }
+
+    #[test]
+    fn dependencies_outside_of_the_selection_are_left_out() {
+        let configs = configs(&[("a", &[("b", true)]), ("b", &[])]);
+        assert_eq!(
+            deploy_order(&configs, &names(&["a"])).unwrap(),
+            names(&["a"])
+        );
+    }
+
+    #[test]
+    fn two_configs_depending_on_each_other_are_a_cycle() {
+        let configs = configs(&[("a", &[("b", true)]), ("b", &[("a", true)])]);
+        let selected = names(&["a", "b"]);
+        assert_eq!(
+            find_cycle(&configs, &selected),
+            Some(names(&["a", "b", "a"]))
+        );
+        let error = deploy_order(&configs, &selected).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Circular dependency between configs: a -> b -> a"
+        );
+    }
+
+    #[test]
+    fn cycle_of_three_configs_is_found_from_any_of_them() {
+        let configs = configs(&[
+            ("a", &[("b", true)]),
+            ("b", &[("c", true)]),
+            ("c", &[("a", true)]),
+        ]);
+        assert_eq!(
+            find_cycle(&configs, &names(&["a"])),
+            Some(names(&["a", "b", "c", "a"]))
+        );
+        assert_eq!(
+            find_cycle(&configs, &names(&["c"])),
+            Some(names(&["c", "a", "b", "c"]))
+        );
+        assert!(deploy_order(&configs, &names(&["b"])).is_err());
+    }
+
+    #[test]
+    fn missing_optional_dependencies_are_skipped() {
+        let configs = configs(&[("a", &[("missing", false)])]);
+        assert_eq!(
+            deploy_order(&configs, &names(&["a"])).unwrap(),
+            names(&["a"])
+        );
+    }
+
+    #[test]
+    fn missing_required_dependencies_fail() {
+        let configs = configs(&[("a", &[("missing", true)])]);
+        let error = deploy_order(&configs, &names(&["a"])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Config 'a' requires the config 'missing', which does not exist."
+        );
+    }
+
+    #[test]
+    fn simple_dependencies_are_required() {
+        let mut configs = configs(&[("a", &[("b", false)])]);
+        configs.get_mut("a").unwrap().dependencies.simple.local = names(&["c"]);
+        let checks = check_all("a", &configs["a"], &configs);
+        let summary: Vec<(&str, bool, bool)> = checks
+            .iter()
+            .map(|check| {
+                (
+                    check.dependency.as_str(),
+                    check.required,
+                    check.problem.is_some(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [("config 'b'", false, true), ("config 'c'", true, true)]
+        );
+    }
+}
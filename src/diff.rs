@@ -0,0 +1,538 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::Result;
+use similar::{ChangeTag, TextDiff};
+
+use crate::{
+    config::LineEnding,
+    io::{as_text, convert_line_endings, line_ending_of, mode, read_for_comparison},
+    pager,
+    structured::{self, KeyChange},
+    style::{self, escape, paint},
+};
+
+/// How changes are printed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// a table with line numbers, for reading in the terminal
+    Table,
+    /// the output of `diff -u`, for other tools
+    Unified,
+    /// the changed keys of TOML, JSON and YAML files, and a table for other files
+    Keys,
+}
+
+/// The values accepted by the `--format` flag.
+pub const FORMAT_CHOICES: [&str; 3] = ["table", "unified", "keys"];
+
+static FORMAT: OnceLock<Format> = OnceLock::new();
+static CONTEXT: OnceLock<usize> = OnceLock::new();
+
+/// Decides once how changes are printed for the rest of the program.
+pub fn set_format(choice: &str) {
+    let format = match choice {
+        "unified" => Format::Unified,
+        "keys" => Format::Keys,
+        _ => Format::Table,
+    };
+    FORMAT.set(format).ok();
+}
+
+/// Sets the number of unchanged lines shown around each change for the rest of the program.
+/// Without a number, every kind of output keeps its own default.
+pub fn set_context(lines: Option<usize>) {
+    if let Some(lines) = lines {
+        CONTEXT.set(lines).ok();
+    }
+}
+
+/// the number of unchanged lines around each change
+fn context(default: usize) -> usize {
+    *CONTEXT.get().unwrap_or(&default)
+}
+
+/// The kind of difference between a file in the repository and its deployed counterpart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    /// the file only exists on the system
+    Added,
+    /// the file exists in both places, but the contents differ
+    Modified,
+    /// the file only exists in the repository
+    Removed,
+    /// the contents are the same, but the file is only executable in one place
+    Mode,
+}
+
+impl ChangeKind {
+    /// the colored symbol which is displayed next to the file name
+    pub fn symbol(&self) -> String {
+        match self {
+            ChangeKind::Added => paint(style::GREEN, "+"),
+            ChangeKind::Modified => paint(style::CYAN, "~"),
+            ChangeKind::Removed => paint(style::RED, "-"),
+            ChangeKind::Mode => paint(style::YELLOW, "m"),
+        }
+    }
+}
+
+/// A difference between a file in the repository and its deployed counterpart.
+#[derive(Debug)]
+pub struct FileChange {
+    pub kind: ChangeKind,
+    /// the path relative to the config and target directory
+    pub path: PathBuf,
+    /// the deployed file
+    pub system: PathBuf,
+    /// the file in the repository
+    pub repo: PathBuf,
+    /// the file was changed in the repository as well as on the system since it was deployed
+    pub conflict: bool,
+    /// the line endings of the config, see [`FileChange::pulled_contents`]
+    pub eol: LineEnding,
+    /// the system version with the changes to the repository since the last deploy merged into it,
+    /// with the line endings of the system
+    pub merged: Option<Vec<u8>>,
+}
+
+impl FileChange {
+    /// Reads the system version of the file the way pulling writes it into the repository,
+    /// or the merged version if there is one.
+    /// Unless the line endings of the config are kept, text files get the line endings of the
+    /// repository version, or `lf` if there is none.
+    pub fn pulled_contents(&self) -> Result<Vec<u8>> {
+        let contents = match &self.merged {
+            Some(merged) => merged.clone(),
+            None => read_for_comparison(&self.system)?,
+        };
+        if self.eol == LineEnding::Keep || self.system.is_symlink() {
+            return Ok(contents);
+        }
+        let eol =
+            read_for_comparison(&self.repo).map_or(LineEnding::Lf, |repo| line_ending_of(&repo));
+        Ok(convert_line_endings(&contents, eol).into_owned())
+    }
+}
+
+/// Prints a change as a table, or as a unified diff if that format was chosen.
+/// For modified files, the diff from the repository to the system is shown.
+/// Changes which don't fit on the screen are shown in the pager.
+pub fn print_change(change: &FileChange) -> Result<()> {
+    pager::page(&format_change(change)?);
+    Ok(())
+}
+
+/// Formats a change like [`print_change`] prints it.
+pub fn format_change(change: &FileChange) -> Result<String> {
+    if FORMAT.get() == Some(&Format::Unified) {
+        return format_unified(change);
+    }
+    let formatted = match change.kind {
+        ChangeKind::Added | ChangeKind::Removed => file_name(
+            &change.path,
+            &change.kind.symbol(),
+            5,
+            style::width(),
+            false,
+        ),
+        // the contents of a link are where it points to, which isn't worth a diff
+        ChangeKind::Modified if change.repo.is_symlink() || change.system.is_symlink() => {
+            let notice = format!(
+                "{} changed to {}",
+                describe_link(&change.repo),
+                describe_link(&change.system)
+            );
+            format_notice(&change.path, &change.kind.symbol(), &notice)
+        }
+        ChangeKind::Modified => {
+            let old = read_for_comparison(&change.repo)?;
+            let new = change.pulled_contents()?;
+            let keys = match (FORMAT.get(), as_text(&old), as_text(&new)) {
+                (Some(Format::Keys), Some(old), Some(new)) => {
+                    structured::changes(&change.path, old, new)
+                }
+                _ => None,
+            };
+            match keys {
+                Some(keys) => format_keys(&change.path, &change.kind.symbol(), &keys),
+                None => format_diff(&change.path, &change.kind.symbol(), &old, &new),
+            }
+        }
+        ChangeKind::Mode => {
+            let notice = format!(
+                "mode changed from {:o} to {:o}",
+                mode(&change.repo).unwrap_or_default(),
+                mode(&change.system).unwrap_or_default()
+            );
+            format_notice(&change.path, &change.kind.symbol(), &notice)
+        }
+    };
+    Ok(formatted)
+}
+
+/// formats a change like `git diff`, colored if colors are enabled
+fn format_unified(change: &FileChange) -> Result<String> {
+    let Some(patch) = unified(change)? else {
+        let path = change.path.to_string_lossy().replace('\\', "/");
+        return Ok(format!("Links a/{0} and b/{0} differ\n", path));
+    };
+    let mut formatted = String::with_capacity(patch.len());
+    for line in patch.lines() {
+        let color = match line.as_bytes().first() {
+            _ if line.starts_with("+++") || line.starts_with("---") => None,
+            Some(b'+') => Some(style::GREEN),
+            Some(b'-') => Some(style::RED),
+            Some(b'@') => Some(style::CYAN),
+            _ => None,
+        };
+        match color {
+            Some(color) => formatted.push_str(&paint(color, line)),
+            None => formatted.push_str(line),
+        }
+        formatted.push('\n');
+    }
+    Ok(formatted)
+}
+
+/// describes a file which may be a symlink for a notice
+fn describe_link(path: &Path) -> String {
+    match std::fs::read_link(path) {
+        Ok(target) => format!("link to '{}'", target.display()),
+        Err(_) => String::from("file"),
+    }
+}
+
+/// Formats the diff between the old and new contents of a file next to the given symbol.
+/// Binary files can't be diffed in a meaningful way, so only a notice is shown for them.
+pub fn format_diff(path: &Path, symbol: &str, old: &[u8], new: &[u8]) -> String {
+    let (Some(old), Some(new)) = (as_text(old), as_text(new)) else {
+        let notice = format!(
+            "binary files differ ({} \u{2192} {})",
+            format_size(old.len()),
+            format_size(new.len())
+        );
+        return format_notice(path, symbol, &notice);
+    };
+
+    let diff = TextDiff::from_lines(old, new);
+
+    // compute the width of the line numbers
+    let ln_width = f32::ceil(f32::log10(
+        usize::max(old.lines().count(), new.lines().count()) as f32,
+    )) as usize;
+    let separator_pos = ln_width * 2 + 4;
+    let total_width = style::width();
+
+    // the file name
+    let mut formatted = file_name(path, symbol, separator_pos, total_width, true);
+
+    // adapted from https://github.com/mitsuhiko/similar/blob/main/examples/terminal-inline.rs
+    for (idx, group) in diff.grouped_ops(context(2)).iter().enumerate() {
+        // separating line between changes
+        if idx > 0 {
+            formatted.push_str(&separator_line(separator_pos, total_width));
+        }
+
+        // iterate over changes
+        for op in group {
+            for change in diff.iter_inline_changes(op) {
+                let (bright_style, line_style, sign) = match change.tag() {
+                    ChangeTag::Delete => (escape(style::BRIGHT_RED), escape(style::RED), '-'),
+                    ChangeTag::Insert => (escape(style::BRIGHT_GREEN), escape(style::GREEN), '+'),
+                    ChangeTag::Equal => (escape(style::DIM), escape(style::DIM), ' '),
+                };
+                let dim = escape(style::DIM);
+                let reset = escape(style::RESET);
+
+                // line numbers
+                formatted.push_str(&format!(
+                    "{dim}{:ln_width$} {:ln_width$} {reset}{line_style}{}{reset}\u{2502}{line_style} ",
+                    change
+                        .old_index()
+                        .map_or(String::new(), |idx| idx.to_string()),
+                    change
+                        .new_index()
+                        .map_or(String::new(), |idx| idx.to_string()),
+                    sign,
+                    ln_width = ln_width
+                ));
+
+                // actual changes
+                for (emphasized, value) in change.iter_strings_lossy() {
+                    if emphasized {
+                        formatted.push_str(escape(style::ITALIC));
+                        formatted.push_str(bright_style);
+                    } else {
+                        formatted.push_str(reset);
+                        formatted.push_str(line_style);
+                    }
+                    formatted.push_str(&value);
+                }
+
+                // reset the style
+                formatted.push_str(reset);
+
+                // a final newline if missing
+                if change.missing_newline() {
+                    formatted.push('\n');
+                }
+            }
+        }
+    }
+
+    // closing line
+    formatted.push_str(&end_line(separator_pos, total_width));
+    formatted
+}
+
+/// formats the changed keys of a structured file, one per line
+fn format_keys(path: &Path, symbol: &str, keys: &[KeyChange]) -> String {
+    let width = style::width();
+    let mut formatted = file_name(path, symbol, 5, width, true);
+    if keys.is_empty() {
+        formatted.push_str(&format!(
+            "{: ^4}\u{2502} {}\n",
+            " ",
+            paint(style::DIM, "only the formatting changed")
+        ));
+    }
+    for key in keys {
+        let (sign, color, text) = match key {
+            KeyChange::Added(key, value) => ('+', style::GREEN, format!("{} = {}", key, value)),
+            KeyChange::Removed(key, value) => ('-', style::RED, format!("{} = {}", key, value)),
+            KeyChange::Changed(key, old, new) => (
+                '~',
+                style::YELLOW,
+                format!("{} = {} \u{2192} {}", key, old, new),
+            ),
+        };
+        formatted.push_str(&format!("{: ^4}\u{2502} {}\n", sign, paint(color, &text)));
+    }
+    formatted.push_str(&end_line(5, width));
+    formatted
+}
+
+/// Formats a file whose contents aren't shown, together with a short notice.
+pub fn format_notice(path: &Path, symbol: &str, notice: &str) -> String {
+    format!(
+        "{}{: ^4}\u{2502} {}\n{}",
+        file_name(path, symbol, 5, style::width(), true),
+        " ",
+        notice,
+        end_line(5, style::width())
+    )
+}
+
+/// formats a number of bytes for humans, e.g. `1.2 KiB`
+fn format_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+fn file_name(
+    name: &Path,
+    modifier_symbol: &str,
+    separator_pos: usize,
+    total_width: usize,
+    continue_table: bool,
+) -> String {
+    format!(
+        "{char:\u{2550}^width_left$}\u{2564}{char:\u{2550}^width_right$}\n{: ^name_left$}{} \u{2502} {}\n{}",
+        " ",
+        modifier_symbol,
+        name.display(),
+        if continue_table {
+            separator_line(separator_pos, total_width)
+        } else {
+            end_line(separator_pos, total_width)
+        },
+        char = "\u{2550}",
+        width_left = separator_pos - 1,
+        width_right = total_width - separator_pos,
+        name_left = separator_pos - 3
+    )
+}
+
+fn separator_line(separator_pos: usize, total_width: usize) -> String {
+    format!(
+        "{char:\u{2500}^ln_width$}\u{253C}{char:\u{2500}^total_width$}\n",
+        char = "\u{2500}",
+        ln_width = separator_pos - 1,
+        total_width = total_width - separator_pos
+    )
+}
+
+fn end_line(separator_pos: usize, total_width: usize) -> String {
+    format!(
+        "{char:\u{2500}^ln_width$}\u{2534}{char:\u{2500}^total_width$}\n",
+        char = "\u{2500}",
+        ln_width = separator_pos - 1,
+        total_width = total_width - separator_pos
+    )
+}
+
+/// A group of adjacent changed lines in a diff, which can be pulled on its own.
+pub struct Hunk<'a> {
+    /// the position of the hunk in the diff, starting at 1
+    pub number: usize,
+    /// the number of hunks in the diff
+    pub count: usize,
+    /// the line in the old contents at which the hunk starts, starting at 1
+    line: usize,
+    /// unchanged lines around the hunk, which are shown for orientation
+    before: &'a [&'a str],
+    after: &'a [&'a str],
+    removed: &'a [&'a str],
+    added: &'a [&'a str],
+}
+
+impl Hunk<'_> {
+    /// Prints the hunk like a diff, below the name of the file.
+    pub fn print(&self, path: &Path) {
+        let width = style::width();
+        let mut formatted = file_name(path, &ChangeKind::Modified.symbol(), 5, width, true);
+        formatted.push_str(&format!(
+            "{: ^4}\u{2502} {}\n",
+            " ",
+            paint(
+                style::CYAN,
+                &format!(
+                    "hunk {} of {} at line {}",
+                    self.number, self.count, self.line
+                )
+            )
+        ));
+        let lines = [
+            (self.before, style::DIM, ' '),
+            (self.removed, style::RED, '-'),
+            (self.added, style::GREEN, '+'),
+            (self.after, style::DIM, ' '),
+        ];
+        for (lines, color, sign) in lines {
+            for line in lines {
+                formatted.push_str(&format!(
+                    "{: ^4}\u{2502} {}\n",
+                    sign,
+                    paint(color, line.trim_end_matches(['\n', '\r']))
+                ));
+            }
+        }
+        formatted.push_str(&end_line(5, width));
+        pager::page(&formatted);
+    }
+}
+
+/// Applies the hunks of the diff from `old` to `new` which `accept` agrees to to `old`.
+/// `accept` is called for every hunk in order.
+pub fn patch(old: &str, new: &str, mut accept: impl FnMut(&Hunk) -> bool) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let old_lines = diff.old_slices();
+    let new_lines = diff.new_slices();
+    // groups without context lines only contain the changed lines of a single hunk
+    let groups = diff.grouped_ops(0);
+
+    let mut patched = String::with_capacity(new.len());
+    let mut position = 0;
+    for (index, group) in groups.iter().enumerate() {
+        let (Some(first), Some(last)) = (group.first(), group.last()) else {
+            continue;
+        };
+        let old_range = first.old_range().start..last.old_range().end;
+        let new_range = first.new_range().start..last.new_range().end;
+        patched.extend(old_lines[position..old_range.start].iter().copied());
+
+        let hunk = Hunk {
+            number: index + 1,
+            count: groups.len(),
+            line: old_range.start + 1,
+            before: &old_lines[old_range.start.saturating_sub(context(2))..old_range.start],
+            after: &old_lines[old_range.end..(old_range.end + context(2)).min(old_lines.len())],
+            removed: &old_lines[old_range.clone()],
+            added: &new_lines[new_range],
+        };
+        let lines = if accept(&hunk) {
+            hunk.added
+        } else {
+            hunk.removed
+        };
+        patched.extend(lines.iter().copied());
+        position = old_range.end;
+    }
+    patched.extend(old_lines[position..].iter().copied());
+    patched
+}
+
+/// Formats a change as a unified diff with git headers, which `git apply` and `patch -p1` can apply in the config.
+/// Returns `None` for links, as their targets can't be expressed in a diff.
+pub fn unified(change: &FileChange) -> Result<Option<String>> {
+    if change.repo.is_symlink() || change.system.is_symlink() {
+        return Ok(None);
+    }
+    let path = change.path.to_string_lossy().replace('\\', "/");
+    let mut patch = format!("diff --git a/{0} b/{0}\n", path);
+    let (old, new) = match change.kind {
+        ChangeKind::Mode => {
+            patch.push_str(&format!(
+                "old mode {}\nnew mode {}\n",
+                git_mode(&change.repo),
+                git_mode(&change.system)
+            ));
+            return Ok(Some(patch));
+        }
+        ChangeKind::Added => {
+            patch.push_str(&format!("new file mode {}\n", git_mode(&change.system)));
+            (Vec::new(), change.pulled_contents()?)
+        }
+        ChangeKind::Removed => {
+            patch.push_str(&format!("deleted file mode {}\n", git_mode(&change.repo)));
+            (read_for_comparison(&change.repo)?, Vec::new())
+        }
+        ChangeKind::Modified => (
+            read_for_comparison(&change.repo)?,
+            change.pulled_contents()?,
+        ),
+    };
+
+    let old_name = match change.kind {
+        ChangeKind::Added => String::from("/dev/null"),
+        _ => format!("a/{}", path),
+    };
+    let new_name = match change.kind {
+        ChangeKind::Removed => String::from("/dev/null"),
+        _ => format!("b/{}", path),
+    };
+    match (as_text(&old), as_text(&new)) {
+        (Some(old), Some(new)) => patch.push_str(
+            &TextDiff::from_lines(old, new)
+                .unified_diff()
+                .context_radius(context(3))
+                .header(&old_name, &new_name)
+                .to_string(),
+        ),
+        _ => patch.push_str(&format!(
+            "Binary files {} and {} differ\n",
+            old_name, new_name
+        )),
+    }
+    Ok(Some(patch))
+}
+
+/// the mode of a file the way git writes it in a patch, only the executable bit is kept
+fn git_mode(path: &Path) -> &'static str {
+    match mode(path) {
+        Some(mode) if mode & 0o111 != 0 => "100755",
+        _ => "100644",
+    }
+}
@@ -0,0 +1,102 @@
+use anyhow::{Context, Result};
+use git2::{Cred, CredentialType, IndexAddOption, PushOptions, RemoteCallbacks, Repository};
+
+use crate::config::SyncRemote;
+
+/// Opens the git repository of the dotfiles in the current directory.
+/// # Errors
+/// Returns an error if the dotfiles aren't a git repository.
+pub fn open() -> Result<Repository> {
+    Repository::open(".").context("The dotfiles repository is not a git repository.")
+}
+
+/// Stages all changes in the repository, including deletions, and commits them.
+/// Files ignored by git stay untracked.
+/// Returns false without creating a commit if nothing changed since the last commit.
+/// # Errors
+/// Returns an error if staging or committing fails, e.g. because no git author is configured.
+pub fn commit_all(repo: &Repository, message: &str) -> Result<bool> {
+    let mut index = repo.index()?;
+    index.add_all(["*"].iter(), IndexAddOption::DEFAULT, None)?;
+    index.update_all(["*"].iter(), None)?;
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+
+    // the repository doesn't have a HEAD before the first commit
+    let parent = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit()?),
+        Err(_) => None,
+    };
+    if parent
+        .as_ref()
+        .is_some_and(|parent| parent.tree_id() == tree.id())
+    {
+        return Ok(false);
+    }
+
+    let signature = repo
+        .signature()
+        .context("Could not determine the git author. Please set 'user.name' and 'user.email'.")?;
+    let parents: Vec<_> = parent.iter().collect();
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &parents,
+    )
+    .context("Could not create the commit.")?;
+    Ok(true)
+}
+
+/// Pushes a branch to a remote of the dotfiles.
+/// If the remote has a repository, it is pushed to directly, otherwise the git remote is used.
+/// Credentials are taken from the ssh agent or the git credential helper.
+/// # Errors
+/// Returns an error if the remote doesn't exist, authentication fails or the push is rejected.
+pub fn push(repo: &Repository, remote: &SyncRemote) -> Result<()> {
+    let branch = remote.branch.as_deref().unwrap_or("main");
+    let mut git_remote = match remote.url() {
+        Some(url) => repo.remote_anonymous(&url)?,
+        None => {
+            let name = remote.remote.as_deref().unwrap_or(&remote.name);
+            repo.find_remote(name)
+                .with_context(|| format!("There is no git remote '{}'", name))?
+        }
+    };
+
+    let config = repo.config()?;
+    // libgit2 asks again after failed attempts, so every kind of credential is only tried once
+    let mut tried = CredentialType::empty();
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(|url, username, allowed| {
+        let remaining = allowed - tried;
+        if remaining.contains(CredentialType::SSH_KEY) {
+            tried |= CredentialType::SSH_KEY;
+            Cred::ssh_key_from_agent(username.unwrap_or("git"))
+        } else if remaining.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            tried |= CredentialType::USER_PASS_PLAINTEXT;
+            Cred::credential_helper(&config, url, username)
+        } else if remaining.contains(CredentialType::DEFAULT) {
+            tried |= CredentialType::DEFAULT;
+            Cred::default()
+        } else {
+            Err(git2::Error::from_str("no usable credentials found"))
+        }
+    });
+    callbacks.push_update_reference(|reference, status| match status {
+        Some(message) => Err(git2::Error::from_str(&format!(
+            "'{}' was rejected: {}",
+            reference, message
+        ))),
+        None => Ok(()),
+    });
+
+    let mut options = PushOptions::new();
+    options.remote_callbacks(callbacks);
+    let refspec = format!("refs/heads/{0}:refs/heads/{0}", branch);
+    git_remote
+        .push(&[refspec.as_str()], Some(&mut options))
+        .with_context(|| format!("Could not push branch '{}'", branch))
+}
@@ -0,0 +1,38 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{io::shell, progress};
+
+/// The information about a deployed config which its hooks get as environment variables.
+pub struct HookEnv<'a> {
+    /// `DOTTOR_CONFIG_NAME`
+    pub name: &'a str,
+    /// `DOTTOR_TARGET`, the location the config was deployed to
+    pub target: Option<PathBuf>,
+}
+
+/// Runs the hooks of a config one after another with the shell, in the directory of the config in the repository.
+/// # Errors
+/// Returns an error if a hook can't be started or fails, the remaining hooks aren't run then.
+pub fn run(hooks: &[String], config_dir: &Path, env: &HookEnv) -> Result<()> {
+    let _terminal = progress::terminal();
+    for hook in hooks {
+        let mut command = shell(hook);
+        command
+            .current_dir(config_dir)
+            .env("DOTTOR_CONFIG_NAME", env.name);
+        if let Some(target) = &env.target {
+            command.env("DOTTOR_TARGET", target);
+        }
+        let status = progress::suspend(|| {
+            println!("Running '{}' for config '{}'.", hook, env.name);
+            command.status()
+        })
+        .with_context(|| format!("Could not run hook '{}'", hook))?;
+        if !status.success() {
+            return Err(anyhow!("Hook '{}' failed with {}", hook, status));
+        }
+    }
+    Ok(())
+}
@@ -1,25 +1,32 @@
 use anyhow::{anyhow, Context, Result};
 use std::{
-    env::current_dir,
+    borrow::Cow,
+    env::{self, current_dir},
     error,
     fmt::Display,
     fs::{self, File, ReadDir},
-    io::{self, stdin, stdout, Read, Write},
+    io::{self, stdin, stdout, BufRead, IsTerminal, Read, Write},
     path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::Duration,
 };
 
-use relative_path::{RelativePath, RelativePathBuf};
+use regex::{Captures, Regex};
+use relative_path::RelativePath;
 use walkdir::WalkDir;
 
 use crate::config;
 
 #[derive(Debug, Clone)]
 pub enum ExpectedType {
+    #[allow(dead_code)]
     File,
     Directory,
 }
 
 impl ExpectedType {
+    #[allow(dead_code)]
     fn invert(&self) -> Self {
         match self {
             ExpectedType::File => ExpectedType::Directory,
@@ -61,7 +68,7 @@ impl Display for IOError {
             IOError::MissingRoot => write!(
                 f,
                 "Directory doesn't contain root configuration '{}'",
-                config::ROOT_PATH
+                config::root_path().display()
             ),
             IOError::IO(err) => write!(f, "{}", err),
             IOError::DirectoryNotEmpty(name) => {
@@ -79,9 +86,7 @@ impl From<io::Error> for IOError {
 
 /// Checks if the root configuration is present in the current directory.
 pub fn is_root_present() -> bool {
-    RelativePathBuf::from(config::ROOT_PATH)
-        .to_path(".")
-        .is_file()
+    config::root_path().is_file()
 }
 
 /// Asserts that the root configuration is present in the current directory.
@@ -114,7 +119,7 @@ pub fn assert_empty(dir: &Path) -> Result<()> {
 /// Ensures that the passed directory doesn't exist or is empty
 pub fn check_dir_null_or_empty(dir: &Path) -> Result<()> {
     if dir.is_dir() {
-        assert_empty(&dir)?;
+        assert_empty(dir)?;
     }
     Ok(())
 }
@@ -128,8 +133,73 @@ pub fn check_valid_dir(dir: &Path) -> Result<()> {
     }
 }
 
+/// How often an operation on a file which is locked by another program is tried.
+const LOCK_ATTEMPTS: u32 = 5;
+
+/// Runs a file operation again with increasing delays while the file is locked by another program.
+/// Only windows locks files, e.g. while a running program has its config file open.
+pub fn retry_locked<T>(mut operation: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut delay = Duration::from_millis(100);
+    for _ in 1..LOCK_ATTEMPTS {
+        match operation() {
+            Err(error) if is_locked(&error) => {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    operation()
+}
+
+/// checks for ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+fn is_locked(error: &io::Error) -> bool {
+    cfg!(windows) && matches!(error.raw_os_error(), Some(32 | 33))
+}
+
+/// Writes a file which only the current user can read, like a decrypted secret.
+/// An existing file is made private before its contents are replaced.
+#[cfg(unix)]
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    if path.is_file() && !path.is_symlink() {
+        set_mode(path, 0o600)?;
+    }
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents))
+        .with_context(|| format!("Could not write to file '{}'", path.display()))
+}
+
+/// Writes a file which only the current user can read. Modes only exist on unix, so this is a plain write.
+#[cfg(not(unix))]
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    write(path, contents)
+}
+
+/// Creates a command which runs `command` with the shell of the platform.
+#[cfg(unix)]
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.args(["-c", command]);
+    shell
+}
+
+/// Creates a command which runs `command` with the shell of the platform.
+#[cfg(windows)]
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.args(["/C", command]);
+    shell
+}
+
 pub fn write(path: &Path, contents: &[u8]) -> Result<()> {
-    let mut write = File::create(path)?;
+    let mut write = retry_locked(|| File::create(path))?;
     match write.write_all(contents) {
         Ok(_) => Ok(()),
         Err(_) => Err(anyhow!(format!(
@@ -141,17 +211,193 @@ pub fn write(path: &Path, contents: &[u8]) -> Result<()> {
 
 pub fn copy_dir(from: &Path, to: &Path) -> Result<()> {
     for entry in WalkDir::new(from) {
-        let entry = entry.unwrap();
+        let entry =
+            entry.with_context(|| format!("Could not read directory '{}'", from.display()))?;
         let path = entry.path();
-        let relative_path = RelativePath::from_path(path.strip_prefix(&from).unwrap()).unwrap();
+        let relative_path = RelativePath::from_path(path.strip_prefix(from)?)?;
 
-        if path.is_file() {
-            fs::copy(path, relative_path.to_path(to));
+        if path.is_file() || path.is_symlink() {
+            copy_file(path, &relative_path.to_path(to))?;
         } else if path.is_dir() {
-            fs::create_dir_all(relative_path.to_path(to));
+            let dir = relative_path.to_path(to);
+            fs::create_dir_all(&dir)
+                .with_context(|| format!("Could not create directory '{}'", dir.display()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes `dir` and its parents as long as they are empty.
+/// `stop` and its parents are never removed.
+pub fn remove_empty_dirs(dir: &Path, stop: &Path) -> Result<()> {
+    let mut dir = dir;
+    while dir != stop && dir.starts_with(stop) && dir.read_dir()?.next().is_none() {
+        fs::remove_dir(dir)
+            .with_context(|| format!("Could not remove directory '{}'", dir.display()))?;
+        match dir.parent() {
+            Some(parent) => dir = parent,
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+/// Copies a single file, creating the parent directories of the destination if necessary.
+/// On unix, the permissions of the source file are applied to the destination after copying,
+/// so that modes like `0600` on ssh configs or the executable bit on scripts survive.
+/// Symlinks are copied as links, pointing to the same target as the original.
+/// On btrfs, xfs, apfs and refs, new files are reflinked instead of copied, so they take no time and space.
+/// # Errors
+/// Returns an error naming the file if any of the steps fail.
+pub fn copy_file(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Could not create directory '{}'", parent.display()))?;
+    }
+    if from.is_symlink() {
+        return copy_symlink(from, to).with_context(|| {
+            format!(
+                "Could not copy the link '{}' to '{}'",
+                from.display(),
+                to.display()
+            )
+        });
+    }
+    // copying onto a link would change the file it points to instead of the link
+    if to.is_symlink() {
+        retry_locked(|| fs::remove_file(to))
+            .with_context(|| format!("Could not remove the link '{}'", to.display()))?;
+    }
+    // new files share their data with the source on file systems with copy-on-write,
+    // existing files are overwritten in place so that they keep their owner and hard links
+    if to.exists() || reflink_copy::reflink(from, to).is_err() {
+        retry_locked(|| fs::copy(from, to)).with_context(|| {
+            format!("Could not copy '{}' to '{}'", from.display(), to.display())
+        })?;
+    }
+    copy_permissions(from, to)
+        .with_context(|| format!("Could not set permissions of '{}'", to.display()))
+}
+
+/// Creates a link at `to` which points to the same target as the link `from`.
+/// An existing file at `to` is replaced.
+fn copy_symlink(from: &Path, to: &Path) -> Result<()> {
+    let target = fs::read_link(from)?;
+    if to.is_symlink() || to.is_file() {
+        retry_locked(|| fs::remove_file(to))?;
+    }
+    create_symlink(&target, to)
+}
+
+/// Creates a symlink at `link` pointing to `target`.
+#[cfg(unix)]
+pub fn create_symlink(target: &Path, link: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(target, link)?;
+    Ok(())
+}
+
+/// Creates a symlink at `link` pointing to `target`.
+/// Windows distinguishes between links to files and to directories.
+#[cfg(windows)]
+pub fn create_symlink(target: &Path, link: &Path) -> Result<()> {
+    let resolved = link.parent().unwrap_or(Path::new(".")).join(target);
+    if resolved.is_dir() {
+        std::os::windows::fs::symlink_dir(target, link)?;
+    } else {
+        std::os::windows::fs::symlink_file(target, link)?;
+    }
+    Ok(())
+}
+
+/// Reads a file for comparing it with another one.
+/// For symlinks, the path they point to is returned instead of the contents of their target.
+pub fn read_for_comparison(file: &Path) -> Result<Vec<u8>> {
+    if file.is_symlink() {
+        let target = fs::read_link(file)
+            .with_context(|| format!("Could not read the link '{}'", file.display()))?;
+        return Ok(format!("symlink to {}", target.display()).into_bytes());
+    }
+    fs::read(file).with_context(|| format!("Could not read file '{}'", file.display()))
+}
+
+/// Checks if `path` is a file with exactly these contents, so that writing them can be skipped.
+/// Files of a different size aren't read.
+pub fn has_contents(path: &Path, contents: &[u8]) -> bool {
+    !path.is_symlink()
+        && fs::metadata(path)
+            .is_ok_and(|metadata| metadata.is_file() && metadata.len() == contents.len() as u64)
+        && fs::read(path).is_ok_and(|existing| existing == contents)
+}
+
+/// Checks if `to` is a copy of `from`, see [`has_contents`]. Links are copies if they point to the same path.
+pub fn is_copy(from: &Path, to: &Path) -> bool {
+    if from.is_symlink() || to.is_symlink() {
+        return from.is_symlink()
+            && to.is_symlink()
+            && fs::read_link(from).ok() == fs::read_link(to).ok();
+    }
+    match (fs::metadata(from), fs::metadata(to)) {
+        (Ok(source), Ok(copy)) if source.is_file() && source.len() == copy.len() => {
+            fs::read(from).is_ok_and(|contents| has_contents(to, &contents))
         }
+        _ => false,
     }
+}
 
+/// Applies the permissions of `from` to `to`.
+#[cfg(unix)]
+pub fn copy_permissions(from: &Path, to: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(from)?.permissions().mode();
+    fs::set_permissions(to, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+/// Applies the permissions of `from` to `to`.
+#[cfg(not(unix))]
+pub fn copy_permissions(_from: &Path, _to: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Returns the permission bits of a file, e.g. `0o644`. Links are followed.
+#[cfg(unix)]
+pub fn mode(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path)
+        .ok()
+        .map(|metadata| metadata.permissions().mode() & 0o777)
+}
+
+/// Returns the permission bits of a file. Modes only exist on unix, so there are none.
+#[cfg(not(unix))]
+pub fn mode(_path: &Path) -> Option<u32> {
+    None
+}
+
+/// Checks if one of two files is executable and the other one isn't.
+/// Like git, only the executable bits are compared, the other bits depend on the umask.
+pub fn executable_differs(a: &Path, b: &Path) -> bool {
+    match (mode(a), mode(b)) {
+        (Some(a), Some(b)) => a & 0o111 != b & 0o111,
+        _ => false,
+    }
+}
+
+/// Sets the mode of a file, e.g. `0o600`.
+#[cfg(unix)]
+pub fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .with_context(|| format!("Could not set permissions of '{}'", path.display()))
+}
+
+/// Sets the mode of a file. Modes only exist on unix, so this does nothing.
+#[cfg(not(unix))]
+pub fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
     Ok(())
 }
 
@@ -164,20 +410,425 @@ pub fn read_to_string(file: &Path) -> Result<String> {
     Ok(str)
 }
 
-pub fn prompt_bool(message: &str, default: bool) -> bool {
-    if default {
-        print!("{message} Proceed? [Y/n]: ");
+/// Expands `~` and environment variables like `$HOME` or `${XDG_CONFIG_HOME}` in a path.
+/// On Windows, `%APPDATA%`-style variables are expanded as well.
+/// # Errors
+/// Returns an error naming the variable if an environment variable is not defined.
+pub fn expand_path(path: &str) -> Result<PathBuf> {
+    let path = if cfg!(windows) {
+        expand_windows_variables(path)?
     } else {
-        print!("{message} Proceed? [y/N]: ");
+        path.to_string()
+    };
+
+    match shellexpand::full(&path) {
+        Ok(expanded) => Ok(PathBuf::from(expanded.into_owned())),
+        Err(error) => Err(anyhow!(
+            "Could not expand path '{}': environment variable '{}' is not defined",
+            path,
+            error.var_name
+        )),
     }
+}
+
+/// Expands `%VARIABLE%` references in a path.
+fn expand_windows_variables(path: &str) -> Result<String> {
+    lazy_static::lazy_static! {
+        static ref RE: Regex = Regex::new(r"%([A-Za-z_][A-Za-z0-9_()]*)%").unwrap();
+    }
+
+    let mut missing = None;
+    let expanded = RE.replace_all(path, |captures: &Captures| match env::var(&captures[1]) {
+        Ok(value) => value,
+        Err(_) => {
+            missing.get_or_insert_with(|| captures[1].to_string());
+            String::new()
+        }
+    });
+
+    match missing {
+        Some(name) => Err(anyhow!(
+            "Could not expand path '{}': environment variable '{}' is not defined",
+            path,
+            name
+        )),
+        None => Ok(expanded.into_owned()),
+    }
+}
+
+/// Interprets the passed contents as text.
+/// Returns `None` if the contents are binary, i.e. they are not valid UTF-8 or contain NUL bytes.
+pub fn as_text(contents: &[u8]) -> Option<&str> {
+    if contents.contains(&0) {
+        return None;
+    }
+    std::str::from_utf8(contents).ok()
+}
 
+/// Converts the line endings of text contents. Binary contents are returned as they are, just like
+/// everything with [`LineEnding::Keep`](config::LineEnding::Keep).
+pub fn convert_line_endings(contents: &[u8], eol: config::LineEnding) -> Cow<'_, [u8]> {
+    let Some(text) = as_text(contents) else {
+        return Cow::Borrowed(contents);
+    };
+    match eol.resolve() {
+        config::LineEnding::Lf if text.contains("\r\n") => {
+            Cow::Owned(text.replace("\r\n", "\n").into_bytes())
+        }
+        config::LineEnding::Crlf if text.replace("\r\n", "").contains('\n') => Cow::Owned(
+            text.replace("\r\n", "\n")
+                .replace('\n', "\r\n")
+                .into_bytes(),
+        ),
+        _ => Cow::Borrowed(contents),
+    }
+}
+
+/// Returns the line endings used by text contents, files without any `\r\n` count as `lf`.
+pub fn line_ending_of(contents: &[u8]) -> config::LineEnding {
+    if contents.windows(2).any(|pair| pair == b"\r\n") {
+        config::LineEnding::Crlf
+    } else {
+        config::LineEnding::Lf
+    }
+}
+
+/// Asks a yes/no question.
+/// Accepts `y`, `yes`, `n` and `no` in any case. An empty answer or a closed stdin returns `default`.
+/// Unrecognized answers are asked again if stdin is a terminal, otherwise they return `default`.
+pub fn prompt_bool(message: &str, default: bool) -> bool {
+    ask_bool(&mut stdin().lock(), message, default)
+}
+
+/// [`prompt_bool`] with the answers read from `reader`
+fn ask_bool(reader: &mut impl BufRead, message: &str, default: bool) -> bool {
+    loop {
+        if default {
+            print!("{message} Proceed? [Y/n]: ");
+        } else {
+            print!("{message} Proceed? [y/N]: ");
+        }
+
+        let Some(input) = read_answer(reader) else {
+            // there won't be an answer, so the default is used
+            println!();
+            return default;
+        };
+        match parse_bool(&input, default) {
+            Some(answer) => return answer,
+            None if stdin().is_terminal() => println!("Please answer 'yes' or 'no'."),
+            None => return default,
+        }
+    }
+}
+
+/// interprets a trimmed and lowercased answer to a yes/no question, `None` if it isn't one
+fn parse_bool(input: &str, default: bool) -> Option<bool> {
+    match input {
+        "" => Some(default),
+        "y" | "yes" => Some(true),
+        "n" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Reads a line from `input`, usually stdin, trimmed and lowercased.
+/// Returns `None` if `input` is closed.
+fn read_answer(input: &mut impl BufRead) -> Option<String> {
     stdout().flush().ok();
 
-    let mut input = String::new();
-    stdin().read_line(&mut input).unwrap();
-    if input.trim().len() == 0 {
-        default
+    let mut line = String::new();
+    match input.read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim().to_lowercase()),
+    }
+}
+
+/// The possible answers when confirming one of a series of changes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Answer {
+    Yes,
+    No,
+    /// accept this and all following changes
+    All,
+    /// reject this and all following changes
+    Quit,
+}
+
+/// Asks for confirmation of one of a series of changes.
+/// Defaults to [`Answer::Yes`]. Unrecognized answers are asked again if stdin is a terminal,
+/// otherwise they reject the change.
+pub fn prompt_answer(message: &str) -> Answer {
+    loop {
+        print!("{message} Proceed? [Y/n/a(ll)/q(uit)]: ");
+
+        let Some(input) = read_answer(&mut stdin().lock()) else {
+            println!();
+            return Answer::Yes;
+        };
+        match parse_answer(&input) {
+            Some(answer) => return answer,
+            None if stdin().is_terminal() => {
+                println!("Please answer 'yes', 'no', 'all' or 'quit'.")
+            }
+            None => return Answer::No,
+        }
+    }
+}
+
+/// interprets a trimmed and lowercased answer to [`prompt_answer`], `None` if it isn't one
+fn parse_answer(input: &str) -> Option<Answer> {
+    match input {
+        "" | "y" | "yes" => Some(Answer::Yes),
+        "n" | "no" => Some(Answer::No),
+        "a" | "all" => Some(Answer::All),
+        "q" | "quit" => Some(Answer::Quit),
+        _ => None,
+    }
+}
+
+/// Resolves a path like [`fs::canonicalize`], but also works for paths which don't exist yet.
+/// The longest existing prefix is canonicalized and the rest of the path is appended to it.
+pub fn canonicalize_lenient(path: &Path) -> Result<PathBuf> {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
     } else {
-        input.to_lowercase().trim() == "y"
+        current_dir()?.join(path)
+    };
+
+    let mut missing = Vec::new();
+    let mut existing = path.as_path();
+    loop {
+        if let Ok(canonical) = fs::canonicalize(existing) {
+            return Ok(missing
+                .iter()
+                .rev()
+                .fold(canonical, |path, name| path.join(name)));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name.to_os_string());
+                existing = parent;
+            }
+            _ => return Err(anyhow!("Could not resolve '{}'", path.display())),
+        }
+    }
+}
+
+/// Computes the path a symlink at `link` has to contain in order to point to `source`.
+/// Both `source` and the parent directory of `link` have to exist.
+pub fn link_target(source: &Path, link: &Path, style: config::SymlinkStyle) -> Result<PathBuf> {
+    let source = fs::canonicalize(source)
+        .with_context(|| format!("Could not resolve '{}'", source.display()))?;
+    if style == config::SymlinkStyle::Absolute {
+        return Ok(source);
+    }
+
+    let parent = link.parent().unwrap_or(Path::new("."));
+    let parent = fs::canonicalize(parent)
+        .with_context(|| format!("Could not resolve '{}'", parent.display()))?;
+    Ok(relative_path(&parent, &source))
+}
+
+/// Replaces `link` with a symlink to `source`, creating its parent directories.
+/// Links which already point to `source` are left as they are.
+/// # Errors
+/// Returns an error if there is a directory at `link` or the link can't be created.
+pub fn link_file(source: &Path, link: &Path, style: config::SymlinkStyle) -> Result<()> {
+    if let Some(parent) = link.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Could not create directory '{}'", parent.display()))?;
+    }
+    if points_to(link, source, style) {
+        return Ok(());
+    }
+    if link.is_symlink() || link.is_file() {
+        retry_locked(|| fs::remove_file(link))
+            .with_context(|| format!("Could not remove '{}'", link.display()))?;
+    } else if link.exists() {
+        return Err(anyhow!(
+            "Could not link '{}', there is a directory in its place.",
+            link.display()
+        ));
+    }
+
+    let target = link_target(source, link, style)?;
+    create_symlink(&target, link).with_context(|| {
+        format!(
+            "Could not link '{}' to '{}'",
+            link.display(),
+            source.display()
+        )
+    })
+}
+
+/// Checks if `link` is a symlink to `source` which was written in the given style.
+/// A link of the other style resolves to the same file, but still has to be replaced.
+pub fn points_to(link: &Path, source: &Path, style: config::SymlinkStyle) -> bool {
+    match (fs::read_link(link), link_target(source, link, style)) {
+        (Ok(target), Ok(expected)) => target == expected,
+        _ => false,
+    }
+}
+
+/// Checks if `link` is a symlink which resolves to `source`, no matter how it was written.
+pub fn resolves_to(link: &Path, source: &Path) -> bool {
+    link.is_symlink()
+        && match (fs::canonicalize(link), fs::canonicalize(source)) {
+            (Ok(link), Ok(source)) => link == source,
+            _ => false,
+        }
+}
+
+/// Computes a `..`-based path leading from the directory `from` to `to`.
+/// Both paths have to be absolute and normalized.
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<_> = from.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from
+        .iter()
+        .zip(&to)
+        .take_while(|(from, to)| from == to)
+        .count();
+
+    let mut path = PathBuf::new();
+    for _ in common..from.len() {
+        path.push("..");
+    }
+    for component in &to[common..] {
+        path.push(component);
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variables_in_paths_are_expanded() {
+        env::set_var("DOTTOR_TEST_EXPAND", "/opt/dottor");
+        assert_eq!(
+            expand_path("$DOTTOR_TEST_EXPAND/config").unwrap(),
+            Path::new("/opt/dottor/config")
+        );
+        assert_eq!(
+            expand_path("${DOTTOR_TEST_EXPAND}.d/x").unwrap(),
+            Path::new("/opt/dottor.d/x")
+        );
+        #[cfg(windows)]
+        assert_eq!(
+            expand_path("%DOTTOR_TEST_EXPAND%/config").unwrap(),
+            Path::new("/opt/dottor/config")
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            expand_path("~/.config").unwrap(),
+            Path::new(&env::var("HOME").unwrap()).join(".config")
+        );
+        assert_eq!(expand_path("/etc/hosts").unwrap(), Path::new("/etc/hosts"));
+    }
+
+    #[test]
+    fn yes_and_no_answers_are_recognized() {
+        let cases = [
+            ("", true, Some(true)),
+            ("", false, Some(false)),
+            ("y", false, Some(true)),
+            ("yes", false, Some(true)),
+            ("n", true, Some(false)),
+            ("no", true, Some(false)),
+            ("yess", true, None),
+            ("j", false, None),
+        ];
+        for (input, default, answer) in cases {
+            assert_eq!(parse_bool(input, default), answer, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn yes_and_no_answers_are_read_from_lines() {
+        assert_eq!(read_answer(&mut "YES\n".as_bytes()).as_deref(), Some("yes"));
+        assert_eq!(read_answer(&mut " n \r\n".as_bytes()).as_deref(), Some("n"));
+        assert_eq!(read_answer(&mut "\n".as_bytes()).as_deref(), Some(""));
+        assert_eq!(read_answer(&mut "".as_bytes()), None);
+
+        assert!(ask_bool(&mut "YES\n".as_bytes(), "Continue?", false));
+        assert!(!ask_bool(&mut "no\n".as_bytes(), "Continue?", true));
+        assert!(ask_bool(&mut "\n".as_bytes(), "Continue?", true));
+        assert!(!ask_bool(&mut "\n".as_bytes(), "Continue?", false));
+        // a closed stdin can't answer, so the default is used
+        assert!(ask_bool(&mut "".as_bytes(), "Continue?", true));
+        assert!(!ask_bool(&mut "".as_bytes(), "Continue?", false));
+    }
+
+    #[test]
+    fn answers_to_a_series_of_changes_are_recognized() {
+        let cases = [
+            ("", Some(Answer::Yes)),
+            ("yes", Some(Answer::Yes)),
+            ("n", Some(Answer::No)),
+            ("a", Some(Answer::All)),
+            ("all", Some(Answer::All)),
+            ("q", Some(Answer::Quit)),
+            ("quit", Some(Answer::Quit)),
+            ("nope", None),
+        ];
+        for (input, answer) in cases {
+            assert_eq!(parse_answer(input), answer, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn relative_paths_lead_through_the_common_ancestor() {
+        let cases = [
+            (
+                "/home/user/.config",
+                "/home/user/dotfiles/app/a",
+                "../dotfiles/app/a",
+            ),
+            ("/home/user", "/home/user/a", "a"),
+            (
+                "/home/user/.config/app",
+                "/srv/dotfiles/a",
+                "../../../../srv/dotfiles/a",
+            ),
+        ];
+        for (from, to, path) in cases {
+            assert_eq!(
+                relative_path(Path::new(from), Path::new(to)),
+                Path::new(path)
+            );
+        }
+    }
+
+    #[test]
+    fn link_targets_resolve_to_the_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("repo").join("app").join("a");
+        let link = dir.path().join("home").join(".config").join("a");
+        fs::create_dir_all(source.parent().unwrap()).unwrap();
+        fs::create_dir_all(link.parent().unwrap()).unwrap();
+        fs::write(&source, "a\n").unwrap();
+        let canonical = fs::canonicalize(&source).unwrap();
+
+        let absolute = link_target(&source, &link, config::SymlinkStyle::Absolute).unwrap();
+        assert_eq!(absolute, canonical);
+
+        let relative = link_target(&source, &link, config::SymlinkStyle::Relative).unwrap();
+        assert_eq!(relative, Path::new("../../repo/app/a"));
+        assert_eq!(
+            fs::canonicalize(link.parent().unwrap().join(relative)).unwrap(),
+            canonical
+        );
+    }
+
+    #[test]
+    fn undefined_variables_are_named() {
+        let error = expand_path("$DOTTOR_TEST_UNDEFINED/config").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Could not expand path '$DOTTOR_TEST_UNDEFINED/config': environment variable 'DOTTOR_TEST_UNDEFINED' is not defined"
+        );
     }
 }
@@ -1,58 +1,164 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::env;
 use std::env::current_dir;
 use std::fs;
-use std::fs::File;
-use std::io::Read;
+use std::io::ErrorKind;
+use std::io::IsTerminal;
+use std::path::Component;
 use std::path::Path;
 use std::path::PathBuf;
+use std::process;
+use std::sync::Mutex;
 
 use anyhow::anyhow;
+use anyhow::Context;
 use anyhow::Result;
 use clap::arg;
+use clap::value_parser;
+use clap::Arg;
+use clap::ArgAction;
 use clap::ArgMatches;
 use clap::{command, Command};
+use clap_complete::Shell;
 use config::Configuration;
+use config::DeployStrategy;
+use config::LineEnding;
+use config::OnConflict;
 use config::RootConfiguration;
-use config::ROOT_PATH;
+use config::SymlinkStyle;
+use dialoguer::theme::{ColorfulTheme, SimpleTheme, Theme};
+use dialoguer::MultiSelect;
+use diff::ChangeKind;
+use diff::FileChange;
 use git2::Repository;
 use globset::Glob;
-use globset::GlobMatcher;
 use globset::GlobSet;
 use globset::GlobSetBuilder;
+use hooks::HookEnv;
+use io::as_text;
 use io::assert_empty;
 use io::assert_root_present;
 use io::check_dir_null_or_empty;
+use io::check_valid_dir;
+use io::copy_dir;
+use io::copy_file;
+use io::copy_permissions;
+use io::expand_path;
+use io::prompt_answer;
 use io::prompt_bool;
+use io::remove_empty_dirs;
 use io::write;
+use io::Answer;
+use manifest::{Manifest, ManifestEntry};
+use mapping::ResolvedMapping;
+use rayon::iter::{
+    IntoParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator,
+};
 use relative_path::RelativePathBuf;
-use similar::ChangeTag;
-use similar::TextDiff;
 use structure::Structure;
+use style::paint;
+use transaction::Transaction;
 use walkdir::WalkDir;
 
+mod backup;
+mod condition;
 mod config;
+mod dependencies;
+mod diff;
+mod git;
+mod hooks;
 mod io;
+mod manifest;
+mod mapping;
+mod merge;
+mod pager;
+mod patch;
+mod privileged;
+mod progress;
+mod secret;
 mod structure;
+mod structured;
+mod style;
+mod template;
+mod time;
+mod transaction;
+mod watch;
+
+#[cfg(test)]
+mod tests;
 
 mod subcommands {
+    pub const COMPLETIONS: &str = "completions";
     pub const CONFIG: &str = "config";
     pub const INIT: &str = "init";
     pub const NEW: &str = "new";
+    pub const SYNC: &str = "sync";
     pub mod config {
+        pub const ADOPT: &str = "adopt";
+        pub const APPLY: &str = "apply";
+        pub const CHECK: &str = "check";
         pub const CREATE: &str = "create";
         pub const DELETE: &str = "delete";
         pub const DEPLOY: &str = "deploy";
+        pub const DIFF: &str = "diff";
+        pub const LIST: &str = "list";
+        pub const RESTORE: &str = "restore";
         pub const PULL: &str = "pull";
+        pub const RENAME: &str = "rename";
+        pub const UNDEPLOY: &str = "undeploy";
+        pub const VALIDATE: &str = "validate";
     }
 }
 
 fn main() {
-    let structure = structure::Structure::resolve().unwrap();
+    let matches = cli().get_matches();
+
+    style::init(matches.get_one::<String>("color").unwrap());
+    let root_config = matches.get_one::<String>("config").map(String::as_str);
+
+    if let Err(error) = match matches.subcommand() {
+        Some((subcommands::INIT, _)) => {
+            config::set_root_path(root_config);
+            init(current_dir().unwrap())
+        }
+        Some((subcommands::NEW, sub_matches)) => {
+            config::set_root_path(root_config);
+            new(sub_matches.get_one("NAME").unwrap() as &String)
+        }
+        Some((subcommands::CONFIG, sub_matches)) => config::locate_root(root_config)
+            .and_then(|_| config(sub_matches, structure::Structure::resolve())),
+        Some((subcommands::SYNC, sub_matches)) => {
+            config::locate_root(root_config).and_then(|_| sync(sub_matches))
+        }
+        Some((subcommands::COMPLETIONS, sub_matches)) => {
+            completions(sub_matches);
+            Ok(())
+        }
+        _ => Ok(()),
+    } {
+        eprintln!("{:#}\n Aborting!", error);
+        process::exit(1);
+    }
+}
 
-    let matches = command!()
+/// builds the command line interface
+fn cli() -> Command {
+    command!()
         .propagate_version(true)
         .subcommand_required(true)
         .arg_required_else_help(true)
+        .arg(
+            arg!(--color <WHEN> "Controls when colors are used in the output")
+                .value_parser(style::COLOR_CHOICES)
+                .default_value("auto")
+                .global(true),
+        )
+        .arg(
+            arg!(--config <PATH> "The location of the root configuration, relative to the repository [default: dottor.toml]")
+                .env("DOTTOR_CONFIG")
+                .global(true),
+        )
         .subcommand(
             Command::new(subcommands::CONFIG)
                 .arg_required_else_help(true)
@@ -61,7 +167,15 @@ fn main() {
                     Command::new(subcommands::config::CREATE)
                         .about("Create a new configuration")
                         .arg_required_else_help(true)
-                        .arg(arg!(<NAME> "The name of the configuration")),
+                        .arg(arg!(<NAME> "The name of the configuration"))
+                        .arg(arg!(--from <PATH> "Copy the files of an existing directory into the configuration and use it as the target")),
+                )
+                .subcommand(
+                    Command::new(subcommands::config::ADOPT)
+                        .about("Import existing files from the system into a configuration")
+                        .arg_required_else_help(true)
+                        .arg(arg!(<NAME> "The name of the configuration"))
+                        .arg(arg!([PATH] "The file or directory to import, defaults to the target of the configuration")),
                 )
                 .subcommand(
                     Command::new(subcommands::config::DELETE)
@@ -69,22 +183,189 @@ fn main() {
                         .arg_required_else_help(true)
                         .arg(arg!(<NAME> "The name of the configuration")),
                 )
+                .subcommand(
+                    Command::new(subcommands::config::RENAME)
+                        .about("Rename a configuration")
+                        .arg_required_else_help(true)
+                        .arg(arg!(<OLD> "The current name of the configuration"))
+                        .arg(arg!(<NEW> "The new name of the configuration")),
+                )
                 .subcommand(
                     Command::new(subcommands::config::DEPLOY)
                         .about("Deploy your configurations to the system")
+                        .arg(arg!([name] "The name of the configuration"))
+                        .arg(
+                            arg!([path] "Only deploy this file or directory, relative to the configuration")
+                                .requires("name")
+                                .conflicts_with("watch"),
+                        )
+                        .arg(arg!(-a --all "Deploy all configurations"))
+                        .arg(arg!(-y --yes "Don't ask for confirmation before deploying"))
+                        .arg(arg!(-w --watch "Keep running and redeploy the configurations whenever their files change"))
+                        .arg(arg!(-q --quiet "Don't show the progress of the deployment"))
+                        .arg(arg!(--prune "Remove deployed files which are no longer part of the configuration"))
+                        .arg(arg!(--"no-backup" "Don't back up the files which are overwritten"))
+                        .arg(arg!(-f --force "Deploy into target directories which aren't empty, even if the configuration requires them to be"))
+                        .arg(arg!(--changed "Only deploy the files which changed in the repository since the last deploy"))
+                        .arg(arg!(--relative "Deploy links which point to the repository with relative paths"))
+                        .arg(
+                            arg!(--"with-deps" "Also deploy the local dependencies of the configuration")
+                                .conflicts_with_all(["all", "path"]),
+                        )
+                        .arg(
+                            arg!(--diff "Show the changes to the files on the system before deploying")
+                                .conflicts_with("watch"),
+                        )
+                        .arg(
+                            arg!(-U --context <LINES> "The number of unchanged lines shown around each change")
+                                .value_parser(value_parser!(usize)),
+                        )
+                        .arg(arg!(--"no-pager" "Print long diffs directly instead of showing them in a pager"))
+                        .arg(
+                            arg!(--"dry-run" "Only print which files would be created, overwritten or skipped")
+                                .conflicts_with("watch"),
+                        )
+                        .arg(
+                            arg!(--only <GLOB> "Only deploy the configurations whose names match the glob, requires --all")
+                                .action(ArgAction::Append)
+                                .requires("all"),
+                        )
+                        .arg(
+                            arg!(--exclude <GLOB> "Don't deploy the configurations whose names match the glob, requires --all")
+                                .action(ArgAction::Append)
+                                .requires("all"),
+                        ),
+                )
+                .subcommand(
+                    Command::new(subcommands::config::VALIDATE)
+                        .about("Check your configurations for errors")
                         .arg_required_else_help(true)
                         .arg(arg!([name] "The name of the configuration"))
-                        .arg(arg!(-a --all "Deploy all configurations")),
+                        .arg(arg!(-a --all "Validate all configurations")),
+                )
+                .subcommand(
+                    Command::new(subcommands::config::LIST)
+                        .about("List your configurations and whether they are deployed")
+                        .arg(
+                            arg!(--sort <BY> "The column the configurations are sorted by")
+                                .value_parser(["name", "status"])
+                                .default_value("name"),
+                        ),
+                )
+                .subcommand(
+                    Command::new(subcommands::config::CHECK)
+                        .about("Check if the dependencies of your configurations are satisfied, without changing any files")
+                        .arg_required_else_help(true)
+                        .arg(arg!([name] "The name of the configuration"))
+                        .arg(arg!(-a --all "Check the dependencies of all configurations")),
+                )
+                .subcommand(
+                    Command::new(subcommands::config::UNDEPLOY)
+                        .about("Remove deployed configurations from the system")
+                        .arg_required_else_help(true)
+                        .arg(arg!([name] "The name of the configuration"))
+                        .arg(arg!(-a --all "Undeploy all configurations"))
+                        .arg(arg!(-f --force "Don't ask for confirmation before removing files")),
+                )
+                .subcommand(
+                    Command::new(subcommands::config::DIFF)
+                        .about("Show the differences between the deployed configurations and the dotfiles repo")
+                        .arg_required_else_help(true)
+                        .arg(arg!([name] "The name of the configuration"))
+                        .arg(arg!(-a --all "Show the differences of all configurations"))
+                        .arg(
+                            arg!(--format <FORMAT> "How the differences are shown")
+                                .value_parser(diff::FORMAT_CHOICES)
+                                .default_value("table"),
+                        )
+                        .arg(
+                            arg!(-U --context <LINES> "The number of unchanged lines shown around each change")
+                                .value_parser(value_parser!(usize)),
+                        )
+                        .arg(arg!(--"no-pager" "Print long diffs directly instead of showing them in a pager")),
                 )
                 .subcommand(
                     Command::new(subcommands::config::PULL)
                         .about(
                             "Pull changes from the deployed configuration into the dotfiles repo",
                         )
-                        .arg_required_else_help(true)
                         .arg(arg!([name] "The name of the configuration"))
+                        .arg(
+                            arg!([path] "Only pull this file or directory, relative to the configuration")
+                                .requires("name"),
+                        )
                         .arg(arg!(-a --all "Pull in changes from all configurations"))
-                        .arg(arg!(-f --force "Don't ask for confirmation when pulling in changes")),
+                        .arg(
+                            arg!(--only <GLOB> "Only pull the configurations whose names match the glob, requires --all")
+                                .action(ArgAction::Append)
+                                .requires("all"),
+                        )
+                        .arg(
+                            arg!(--exclude <GLOB> "Don't pull the configurations whose names match the glob, requires --all")
+                                .action(ArgAction::Append)
+                                .requires("all"),
+                        )
+                        .arg(arg!(-f --force "Don't ask for confirmation when pulling in changes"))
+                        .arg(
+                            arg!(-n --"dry-run" "Only show the changes which would be pulled, without changing the repository")
+                                .conflicts_with_all(["force", "commit"]),
+                        )
+                        .arg(
+                            arg!(-s --select "Choose the changes to pull from a list instead of confirming them one by one")
+                                .conflicts_with_all(["force", "dry-run"]),
+                        )
+                        .arg(
+                            arg!(-p --patch "Confirm every changed part of a modified file on its own")
+                                .conflicts_with_all(["force", "dry-run", "select"]),
+                        )
+                        .arg(
+                            arg!(--format <FORMAT> "How the changes are shown")
+                                .value_parser(diff::FORMAT_CHOICES)
+                                .default_value("table"),
+                        )
+                        .arg(
+                            arg!(-U --context <LINES> "The number of unchanged lines shown around each change")
+                                .value_parser(value_parser!(usize)),
+                        )
+                        .arg(arg!(--"no-pager" "Print long diffs directly instead of showing them in a pager"))
+                        .arg(
+                            arg!(-o --output <FILE> "Write the changes to a patch file instead of pulling them, '-' prints them")
+                                .requires("name")
+                                .conflicts_with_all(["force", "dry-run", "select", "patch", "commit"]),
+                        )
+                        .arg(
+                            arg!(--theirs "Pull in files which were changed in the repository as well since they were deployed, discarding the changes in the repository")
+                                .conflicts_with("ours"),
+                        )
+                        .arg(arg!(--ours "Skip files which were changed in the repository as well since they were deployed"))
+                        .arg(arg!(-q --quiet "Don't show the progress or list the changes which are pulled in without confirmation"))
+                        .arg(
+                            Arg::new("commit")
+                                .long("commit")
+                                .value_name("MESSAGE")
+                                .num_args(0..=1)
+                                .require_equals(true)
+                                .default_missing_value("")
+                                .help("Commit the pulled changes with git, the message defaults to a summary of the pulled configs"),
+                        ),
+                )
+                .subcommand(
+                    Command::new(subcommands::config::APPLY)
+                        .about("Apply a patch created with 'pull --output' to a configuration")
+                        .arg(arg!(<name> "The name of the configuration"))
+                        .arg(arg!(<patch> "The patch file, '-' reads it from the standard input"))
+                        .arg(arg!(--check "Only check whether the patch applies, without changing any files")),
+                )
+                .subcommand(
+                    Command::new(subcommands::config::RESTORE)
+                        .about("Restore the deployed files of a configuration from a backup")
+                        .arg(arg!([name] "The name of the configuration"))
+                        .arg(
+                            arg!(--at <TIMESTAMP> "The backup to restore, defaults to the most recent one")
+                                .visible_alias("from"),
+                        )
+                        .arg(arg!(-l --list "Only list the backups of the configuration").conflicts_with_all(["at", "force"]))
+                        .arg(arg!(-f --force "Don't ask for confirmation when restoring files")),
                 )
                 .arg(arg!([NAME] "The name of the configuration")),
         )
@@ -97,18 +378,26 @@ fn main() {
                 .about("Initialize a new dotfiles repository in a subdirectory")
                 .arg(arg!(<FOLDER> "The folder where the dotfiles repository will be created")),
         )
-        .get_matches();
+        .subcommand(
+            Command::new(subcommands::SYNC)
+                .about("Push the dotfiles repository to its remotes")
+                .arg(arg!(-r --remote <NAME> "Only push to the remote with this name")),
+        )
+        .subcommand(
+            Command::new(subcommands::COMPLETIONS)
+                .about("Print a completion script for your shell")
+                .arg(arg!(<SHELL> "The shell to generate the completions for").value_parser(value_parser!(Shell))),
+        )
+}
 
-    if let Err(error) = match matches.subcommand() {
-        Some((subcommands::INIT, _)) => init(current_dir().unwrap()),
-        Some((subcommands::NEW, sub_matches)) => {
-            new(sub_matches.get_one("NAME").unwrap() as &String)
-        }
-        Some((subcommands::CONFIG, sub_matches)) => config(sub_matches, structure),
-        _ => Ok(()),
-    } {
-        eprintln!("{}\n Aborting!", error);
-    }
+/// prints the completion script for a shell to stdout
+fn completions(matches: &ArgMatches) {
+    let shell = *matches
+        .get_one::<Shell>("SHELL")
+        .expect("shell not provided");
+    let mut command = cli();
+    let name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
 }
 
 /// Initialize a new dottor repository in the given directory
@@ -118,8 +407,12 @@ fn init<P: AsRef<Path>>(path: P) -> Result<()> {
     assert_empty(path)?;
 
     // create the default root configuration
+    let root_config = path.join(config::root_path());
+    if let Some(parent) = root_config.parent() {
+        fs::create_dir_all(parent)?;
+    }
     write(
-        &RelativePathBuf::from(ROOT_PATH).to_path(path),
+        &root_config,
         toml::to_string_pretty(&RootConfiguration::default())?.as_bytes(),
     )?;
 
@@ -130,6 +423,41 @@ fn init<P: AsRef<Path>>(path: P) -> Result<()> {
     }
 }
 
+/// pushes the dotfiles to all remotes, or only the selected one
+fn sync(matches: &ArgMatches) -> Result<()> {
+    assert_root_present()?;
+    let root = config::read_root_configuration()?;
+    let repo = git::open()?;
+
+    let mut remotes = root.synchronization.remotes();
+    if let Some(name) = matches.get_one::<String>("remote") {
+        remotes.retain(|remote| &remote.name == name);
+        if remotes.is_empty() {
+            return Err(anyhow!("There is no remote with the name '{}'.", name));
+        }
+    }
+
+    let mut failed = 0;
+    for remote in &remotes {
+        match git::push(&repo, remote) {
+            Ok(_) => println!("Pushed to '{}'.", remote.name),
+            Err(error) => {
+                failed += 1;
+                println!("Could not push to '{}': {:#}", remote.name, error);
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(anyhow!(
+            "Could not push to {} of {} remote(s).",
+            failed,
+            remotes.len()
+        ));
+    }
+    Ok(())
+}
+
 fn new(name: &str) -> Result<()> {
     let path = RelativePathBuf::from(name).to_path(".");
     init(&path)
@@ -145,15 +473,28 @@ fn verify_structure(structure: Option<Structure>) -> Result<Structure> {
 }
 
 /// runs the config command
-fn config(matches: &ArgMatches, structure: Option<Structure>) -> Result<()> {
+fn config(matches: &ArgMatches, structure: Result<Option<Structure>>) -> Result<()> {
     assert_root_present()?;
-    let structure = verify_structure(structure)?;
+    let structure = verify_structure(structure?)?;
 
     match matches.subcommand() {
         Some((subcommands::config::CREATE, sub_matches)) => config_create(sub_matches, structure),
+        Some((subcommands::config::ADOPT, sub_matches)) => config_adopt(sub_matches, structure),
         Some((subcommands::config::DELETE, sub_matches)) => config_delete(sub_matches, structure),
+        Some((subcommands::config::RENAME, sub_matches)) => config_rename(sub_matches, structure),
         Some((subcommands::config::DEPLOY, sub_matches)) => config_deploy(sub_matches, structure),
+        Some((subcommands::config::UNDEPLOY, sub_matches)) => {
+            config_undeploy(sub_matches, structure)
+        }
+        Some((subcommands::config::DIFF, sub_matches)) => config_diff(sub_matches, structure),
+        Some((subcommands::config::CHECK, sub_matches)) => config_check(sub_matches, structure),
+        Some((subcommands::config::LIST, sub_matches)) => config_list(sub_matches, structure),
+        Some((subcommands::config::RESTORE, sub_matches)) => config_restore(sub_matches, structure),
+        Some((subcommands::config::VALIDATE, sub_matches)) => {
+            config_validate(sub_matches, structure)
+        }
         Some((subcommands::config::PULL, sub_matches)) => config_pull(sub_matches, structure),
+        Some((subcommands::config::APPLY, sub_matches)) => config_apply(sub_matches, structure),
         _ => Err(anyhow!("Invalid subcommand")),
     }
 }
@@ -161,24 +502,153 @@ fn config(matches: &ArgMatches, structure: Option<Structure>) -> Result<()> {
 /// creates a new config
 fn config_create(matches: &ArgMatches, structure: Structure) -> Result<()> {
     let name: &String = matches.get_one("NAME").expect("name not provided");
-    if structure.configs.contains_key(name) {
+    if let Some(existing) = structure.find(name) {
         return Err(anyhow!(format!(
             "There already exists a config with the name '{}'",
-            name
+            existing
         )));
     }
-    config::create_config(name)
+
+    let Some(from) = matches.get_one::<String>("from") else {
+        return config::create_config(name, &Configuration::default());
+    };
+
+    // check the source before anything is created
+    let source = expand_cli_path(from)?;
+    check_valid_dir(&source)?;
+    if source.join(config::CONFIG_PATH).exists() {
+        return Err(anyhow!(
+            "'{}' contains a '{}' file, which would overwrite the configuration file.",
+            source.display(),
+            config::CONFIG_PATH
+        ));
+    }
+
+    let mut config = Configuration::default();
+    if let Some(target) = config.target.current_mut() {
+        target.directory = Some(target_path(from, &source));
+    }
+    config::create_config(name, &config)?;
+    copy_dir(&source, &RelativePathBuf::from(name).to_path("."))
+}
+
+/// expands a path which was passed on the command line.
+/// Relative paths are resolved against the directory dottor was started in, not the repository.
+fn expand_cli_path(path: &str) -> Result<PathBuf> {
+    Ok(config::invocation_path(expand_path(path)?))
+}
+
+/// the path which is stored as the target of a config for a path from the command line.
+/// Relative paths are stored as the absolute `expanded` path, as targets are resolved against the repository,
+/// while paths like `~/.config/nvim` are kept as they were written.
+fn target_path(path: &str, expanded: &Path) -> String {
+    match expand_path(path) {
+        Ok(relative) if relative.is_relative() => {
+            let mut normalized = PathBuf::new();
+            for component in expanded.components() {
+                match component {
+                    Component::ParentDir => {
+                        normalized.pop();
+                    }
+                    Component::CurDir => {}
+                    component => normalized.push(component),
+                }
+            }
+            normalized.to_string_lossy().into_owned()
+        }
+        _ => String::from(path),
+    }
+}
+
+/// imports files from the system into a config, creating the config if it doesn't exist yet
+fn config_adopt(matches: &ArgMatches, mut structure: Structure) -> Result<()> {
+    let name: &String = matches.get_one("NAME").expect("name not provided");
+    let path: Option<&String> = matches.get_one("PATH");
+    // files are adopted into an existing config whose name only differs in case,
+    // as a second config would occupy the same directory on case-insensitive file systems
+    let name = &structure.find(name).unwrap_or(name).to_string();
+
+    let config_dir = RelativePathBuf::from(name).to_path(".");
+    // the configuration file of a broken config must not be replaced
+    if let Some(error) = structure.errors.remove(name) {
+        return Err(error.context(format!("Config '{}' could not be read", name)));
+    }
+    let existing = structure.configs.remove(name);
+    let is_new = existing.is_none();
+    if is_new {
+        check_dir_null_or_empty(&config_dir)?;
+    }
+
+    let mut config = existing.unwrap_or_default();
+    let target = config
+        .target
+        .current_mut()
+        .ok_or_else(|| anyhow!("Operating system '{}' is not supported.", env::consts::OS))?;
+    let has_target = target.directory.is_some() || target.file.is_some();
+
+    let (path, source) = match path {
+        Some(path) => {
+            let source = expand_cli_path(path)?;
+            (target_path(path, &source), source)
+        }
+        None => {
+            let path = target
+                .directory
+                .clone()
+                .or_else(|| target.file.clone())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Config '{}' has no target to adopt files from. Please specify a path.",
+                        name
+                    )
+                })?;
+            let source = expand_path(&path)?;
+            (path, source)
+        }
+    };
+
+    if source.is_dir() {
+        if source.join(config::CONFIG_PATH).exists() {
+            return Err(anyhow!(
+                "'{}' contains a '{}' file, which would overwrite the configuration file.",
+                source.display(),
+                config::CONFIG_PATH
+            ));
+        }
+        copy_dir(&source, &config_dir)?;
+        if !has_target {
+            target.directory = Some(path);
+        }
+    } else if source.is_file() {
+        // the file of the target keeps the path it is deployed from
+        let file = match &target.source {
+            Some(file) if target.file.as_ref() == Some(&path) => PathBuf::from(file),
+            _ => PathBuf::from(source.file_name().unwrap()),
+        };
+        copy_file(&source, &config_dir.join(file))?;
+        if !has_target {
+            target.file = Some(path);
+        }
+    } else {
+        return Err(anyhow!("'{}' does not exist.", source.display()));
+    }
+
+    // only rewrite the configuration if something changed, so that comments are preserved
+    if is_new || !has_target {
+        config::write_configuration(&config_dir.join(config::CONFIG_PATH), &config)?;
+    }
+    Ok(())
 }
 
 /// deletes a config
 fn config_delete(matches: &ArgMatches, structure: Structure) -> Result<()> {
     let name: &String = matches.get_one("NAME").expect("name not provided");
-    if structure.configs.contains_key(name) {
+    let Some(name) = structure.find(name) else {
         return Err(anyhow!(format!(
             "There is no config with the name '{}'",
             name
         )));
-    }
+    };
 
     if prompt_bool("Do you want to delete this configuration? ", false) {
         config::delete_config(name)
@@ -187,471 +657,2927 @@ fn config_delete(matches: &ArgMatches, structure: Structure) -> Result<()> {
     }
 }
 
-fn config_pull(matches: &ArgMatches, mut structure: Structure) -> Result<()> {
+/// renames a config
+fn config_rename(matches: &ArgMatches, structure: Structure) -> Result<()> {
+    let old: &String = matches.get_one("OLD").expect("old name not provided");
+    let new: &String = matches.get_one("NEW").expect("new name not provided");
+    let Some(old) = structure.find(old) else {
+        return Err(anyhow!(format!(
+            "There is no config with the name '{}'",
+            old
+        )));
+    };
+    // changing only the case of a name is fine, the config collides with itself
+    if let Some(existing) = structure.find(new).filter(|existing| *existing != old) {
+        return Err(anyhow!(format!(
+            "There already exists a config with the name '{}'",
+            existing
+        )));
+    }
+    if old == new {
+        return Err(anyhow!("The config is already called '{}'", new));
+    }
+    config::rename_config(old, new)?;
+    manifest::rename(old, new)?;
+    backup::rename(old, new)
+}
+
+/// checks one or all configs for errors
+fn config_validate(matches: &ArgMatches, structure: Structure) -> Result<()> {
     let name: Option<&String> = matches.get_one("name");
     let all = matches.get_flag("all");
-    let force = matches.get_flag("force");
 
-    if let Some(name) = name {
+    // configs which can't be read are reported as invalid as well
+    let mut names: Vec<&String> = if let Some(name) = name {
         if all {
             return Err(anyhow!("You cannot use the all flag in combination with a specific configuration. Try removing \"--all\" or the configuration name."));
         }
-        let config = structure.configs.remove(name);
-        match config {
-            Some(config) => pull_single(&String::from(name), config, force),
-            None => Err(anyhow!(format!("Config '{name}' does not exist."))),
-        }
+        structure
+            .configs
+            .keys()
+            .chain(structure.errors.keys())
+            .filter(|key| structure::same_name(key, name))
+            .collect()
     } else if all {
-        for (name, config) in structure.configs {
-            if !force {
-                println!("Pulling config '{}'", name);
+        structure
+            .configs
+            .keys()
+            .chain(structure.errors.keys())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    if names.is_empty() {
+        return Err(anyhow!("No configurations matched the query."));
+    }
+    names.sort();
+
+    let mut invalid = 0;
+    for name in names {
+        let problems = match structure.configs.get(name) {
+            Some(config) => {
+                let mut problems = config.validate();
+                if let Some(cycle) =
+                    dependencies::find_cycle(&structure.configs, std::slice::from_ref(name))
+                {
+                    problems.push(format!(
+                        "Circular dependency between configs: {}",
+                        cycle.join(" -> ")
+                    ));
+                }
+                problems
             }
-            match pull_single(&name, config, force) {
-                Ok(_) => {}
-                Err(error) => println!("Could not pull config '{}': {}", name, error),
+            None => vec![format!("{:#}", structure.errors[name])],
+        };
+
+        if problems.is_empty() {
+            println!("{} {}", paint(style::GREEN, "\u{2713}"), name);
+        } else {
+            invalid += 1;
+            println!("{} {}", paint(style::RED, "\u{2717}"), name);
+            for problem in problems {
+                println!("    {}", problem.trim_end().replace('\n', "\n    "));
             }
         }
-        Ok(())
+    }
+
+    if invalid > 0 {
+        Err(anyhow!("{} configuration(s) are invalid.", invalid))
     } else {
-        Err(anyhow!("No configurations matched the query."))
+        Ok(())
     }
 }
 
-/// pull local changes from a config into the repository
-fn pull_single(name: &String, config: Configuration, force: bool) -> Result<()> {
-    fn print_file_name(
-        name: &Path,
-        modifier_symbol: &'static str,
-        separator_pos: usize,
-        total_width: usize,
-        continue_table: bool,
-    ) {
-        println!(
-            "{char:\u{2550}^width_left$}\u{2564}{char:\u{2550}^width_right$}",
-            char = "\u{2550}",
-            width_left = separator_pos - 1,
-            width_right = total_width - separator_pos
-        );
-        println!(
-            "{: ^width_left$}{} \u{2502} {}",
-            " ",
-            modifier_symbol,
-            name.display(),
-            width_left = separator_pos - 3
-        );
+/// Whether a config is deployed on this machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum DeployStatus {
+    /// the deployed files differ from the repository
+    Drifted,
+    Deployed,
+    NotDeployed,
+    /// the config is disabled on this machine
+    Disabled,
+    /// the config couldn't be read or compared
+    Error,
+}
 
-        if continue_table {
-            print_separator_line(separator_pos, total_width);
-        } else {
-            print_end_line(separator_pos, total_width);
+impl DeployStatus {
+    fn label(&self) -> &'static str {
+        match self {
+            DeployStatus::Drifted => "drifted",
+            DeployStatus::Deployed => "deployed",
+            DeployStatus::NotDeployed => "not deployed",
+            DeployStatus::Disabled => "disabled",
+            DeployStatus::Error => "error",
         }
     }
 
-    fn print_separator_line(separator_pos: usize, total_width: usize) {
-        println!(
-            "{char:\u{2500}^ln_width$}\u{253C}{char:\u{2500}^total_width$}",
-            char = "\u{2500}",
-            ln_width = separator_pos - 1,
-            total_width = total_width - separator_pos
-        );
+    fn color(&self) -> &'static str {
+        match self {
+            DeployStatus::Drifted => style::YELLOW,
+            DeployStatus::Deployed => style::GREEN,
+            DeployStatus::NotDeployed | DeployStatus::Disabled => style::DIM,
+            DeployStatus::Error => style::RED,
+        }
     }
+}
 
-    fn print_end_line(separator_pos: usize, total_width: usize) {
-        println!(
-            "{char:\u{2500}^ln_width$}\u{2534}{char:\u{2500}^total_width$}",
-            char = "\u{2500}",
-            ln_width = separator_pos - 1,
-            total_width = total_width - separator_pos
-        );
+/// compares the repository with the system, like `config diff` does
+fn deploy_status(name: &str, config: &Configuration, root: &RootConfiguration) -> DeployStatus {
+    if config.skip_reason(config::hostname().as_deref()).is_some() {
+        return DeployStatus::Disabled;
     }
-
-    // get correct deploy and pull configuration
-    let target = match env::consts::OS {
-        "windows" => config.target.windows,
-        "linux" => config.target.linux,
-        value => {
-            return Err(anyhow!(format!(
-                "Operating system '{value}' is not supported."
-            )))
-        }
+    let Ok(files) = deployed_files(name, config, root, None) else {
+        return DeployStatus::Error;
     };
+    let deployed = manifest::path(name).is_file()
+        || files
+            .iter()
+            .any(|file| file.destination.exists() || file.destination.is_symlink());
+    if !deployed {
+        return DeployStatus::NotDeployed;
+    }
 
-    let to_dir = RelativePathBuf::from(name).to_path(".");
-    let dotconfig = to_dir.clone().join(config::CONFIG_PATH);
+    match pull_changes(name, config, root, None) {
+        Ok(changes) if changes.is_empty() => DeployStatus::Deployed,
+        Ok(_) => DeployStatus::Drifted,
+        Err(_) => DeployStatus::Error,
+    }
+}
 
-    // resolve exclude glob patterns
-    let mut exclude_patterns = GlobSetBuilder::new();
-    config.target.exclude.iter().for_each(|pattern| {
-        exclude_patterns.add(Glob::new(pattern.as_str()).unwrap());
-    });
-    target.exclude.iter().for_each(|pattern| {
-        exclude_patterns.add(Glob::new(pattern.as_str()).unwrap());
-    });
-    let exclude_patterns = exclude_patterns.build().unwrap();
+/// lists all configs with their target and deployment status in a table
+fn config_list(matches: &ArgMatches, structure: Structure) -> Result<()> {
+    let mut rows: Vec<(String, DeployStatus, String)> = Vec::new();
+    for (name, config) in &structure.configs {
+        let target = match mapping::resolve(config) {
+            Ok(mappings) => mappings
+                .iter()
+                .map(|mapping| mapping.destination.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            Err(_) => String::from("-"),
+        };
+        rows.push((
+            name.clone(),
+            deploy_status(name, config, &structure.root),
+            target,
+        ));
+    }
+    for name in structure.errors.keys() {
+        rows.push((name.clone(), DeployStatus::Error, String::from("-")));
+    }
 
-    // check 'file' and 'directory'
-    if target.directory.is_some() && target.file.is_some() {
-        Err(anyhow!("Cannot use both 'directory' and 'file' targets.",))
-    } else if let Some(from) = target.file {
-        let from_file = PathBuf::from(shellexpand::tilde(&from).into_owned());
+    match matches.get_one::<String>("sort").map(String::as_str) {
+        Some("status") => rows.sort_by(|a, b| (a.1, &a.0).cmp(&(b.1, &b.0))),
+        _ => rows.sort_by(|a, b| a.0.cmp(&b.0)),
+    }
+
+    let name_width = rows
+        .iter()
+        .map(|row| row.0.chars().count())
+        .chain(["name".len()])
+        .max()
+        .unwrap_or_default();
+    let status_width = rows
+        .iter()
+        .map(|row| row.1.label().len())
+        .chain(["status".len()])
+        .max()
+        .unwrap_or_default();
+    // the target gets whatever space is left, but never less than a few columns
+    let target_width = style::width()
+        .saturating_sub(name_width + status_width + 8)
+        .max(10);
+
+    let line = |middle: char, fill: char| {
+        let fill = |width: usize| fill.to_string().repeat(width + 2);
+        println!(
+            "{}{middle}{}{middle}{}",
+            fill(name_width),
+            fill(status_width),
+            fill(target_width),
+        );
+    };
 
-        pull_file(
-            from_file.parent().unwrap(),
-            &from_file,
+    line('\u{2564}', '\u{2550}');
+    println!(
+        " {:name_width$} \u{2502} {:status_width$} \u{2502} target",
+        "name", "status"
+    );
+    line('\u{253C}', '\u{2500}');
+    for (name, status, target) in &rows {
+        let target = if target.chars().count() > target_width {
+            let mut shortened: String = target.chars().take(target_width - 1).collect();
+            shortened.push('\u{2026}');
+            shortened
+        } else {
+            target.clone()
+        };
+        println!(
+            " {:name_width$} \u{2502} {} \u{2502} {}",
             name,
-            &exclude_patterns,
-            force,
-        )?;
-        Ok(())
-    } else if let Some(from) = target.directory {
-        let from_dir = PathBuf::from(shellexpand::tilde(&from).into_owned());
+            paint(status.color(), &format!("{:status_width$}", status.label())),
+            target
+        );
+    }
+    line('\u{2534}', '\u{2500}');
 
-        let from_paths = get_paths_in(&from_dir, "**/*")?;
-        let to_paths = get_paths_in(&to_dir, "**/*")?;
+    Ok(())
+}
 
-        // pull file from deployed configuration
-        // there are four cases for this:
-        //  1) from exists, to exists && unchanged -> do nothing
-        //  2) from exists, to exists && modified -> display diff
-        //  3) from exists, to doesn't exist -> display addition
-        //  4) from doesn't exist, to exists -> display removal
-        for from_abs in from_paths {
-            pull_file(&from_dir, &from_abs, name, &exclude_patterns, force)?;
-        }
+/// checks the dependencies of one or all configs and reports which of them aren't satisfied
+fn config_check(matches: &ArgMatches, structure: Structure) -> Result<()> {
+    let selected = select_configs(matches, &structure)?;
 
-        // check for case 4) file was deleted
-        for to_abs in to_paths {
-            // resolve relative path
-            let path_rel = to_abs
-                .strip_prefix(&to_dir)
-                .map_err(|_| anyhow!("could not resolve relative path"))?;
-            // get source
-            let from_abs = from_dir.join(path_rel);
+    let mut failed = 0;
+    for name in selected {
+        let checks = dependencies::check_all(&name, &structure.configs[&name], &structure.configs);
+        let satisfied = checks
+            .iter()
+            .all(|check| check.problem.is_none() || !check.required);
 
-            if !exclude_patterns.is_match(path_rel) && to_abs.clone() != dotconfig {
-                // check if file was deleted
-                if !from_abs.exists() {
-                    if force {
-                        fs::remove_file(to_abs)?;
-                        continue;
-                    }
-                    print_file_name(path_rel, "\x1b[31m-\x1b[0m", 5, 80, false);
-                    if prompt_bool("Do you want to continue? ", true) {
-                        fs::remove_file(to_abs)?;
-                    }
+        if satisfied {
+            println!("{} {}", paint(style::GREEN, "\u{2713}"), name);
+        } else {
+            failed += 1;
+            println!("{} {}", paint(style::RED, "\u{2717}"), name);
+        }
+        for check in checks {
+            match check.problem {
+                None => println!(
+                    "    {} {}",
+                    paint(style::GREEN, "\u{2713}"),
+                    check.dependency
+                ),
+                Some(problem) if check.required => {
+                    println!("    {} {}", paint(style::RED, "\u{2717}"), problem)
                 }
+                Some(problem) => println!(
+                    "    {} {} The dependency is optional.",
+                    paint(style::YELLOW, "!"),
+                    problem
+                ),
             }
         }
+    }
 
-        Ok(())
+    if failed > 0 {
+        Err(anyhow!(
+            "{} configuration(s) have unsatisfied dependencies.",
+            failed
+        ))
     } else {
-        Err(anyhow!("'file' or 'directory' target must be set"))
+        Ok(())
     }
 }
 
-fn pull_file(
-    from_dir: &Path,
-    from: &Path,
-    to: &String,
-    exclude: &GlobSet,
-    force: bool,
-) -> Result<()> {
-    // pull file from deployed configuration
-    // there are four cases for this:
-    //  1) from exists, to exists && unchanged -> do nothing
-    //  2) from exists, to exists && modified -> display diff
-    //  3) from exists, to doesn't exist -> display addition
-    //  4) from doesn't exist, to exists -> display removal
-
-    let to_dir = PathBuf::from(to);
-    let dotconfig = to_dir.join(config::CONFIG_PATH);
-    // resolve relative path
-    let path_rel = from
-        .strip_prefix(from_dir)
-        .map_err(|_| anyhow!("could not resolve relative path"))?;
-    let from_abs = from;
-    // get destination
-    let to_abs = to_dir.join(path_rel);
+/// resolves the configurations selected by the `name` argument or the `--all` flag
+fn select_configs(matches: &ArgMatches, structure: &Structure) -> Result<Vec<String>> {
+    let name: Option<&String> = matches.get_one("name");
+    let all = matches.get_flag("all");
 
-    if !exclude.is_match(path_rel) {
-        // ensure that we aren't accidentally overwriting the dotconfig
-        if to_abs == dotconfig {
-            return Err(
-                anyhow!("Trying to overwrite dotconfig.toml configuration file. Please add 'dotconfig.toml' to your excludes in the target configuration."),
-            );
+    let mut selected: Vec<String> = if let Some(name) = name {
+        if all {
+            return Err(anyhow!("You cannot use the all flag in combination with a specific configuration. Try removing \"--all\" or the configuration name."));
         }
+        structure.config(name)?;
+        vec![name.clone()]
+    } else if all {
+        structure.warn_errors();
+        filter_configs(matches, structure.configs.keys())?
+    } else {
+        Vec::new()
+    };
 
-        // if the file exists, we check if any changes were made to it
-        if to_abs.exists() {
-            let mut from = File::open(from)?;
-            let mut to = File::open(&to_abs)?;
-
-            let mut buf = Vec::new();
-            from.read_to_end(&mut buf).unwrap();
-            let from_contents = String::from_utf8(buf);
-            let mut buf = Vec::new();
-            to.read_to_end(&mut buf)?;
-            let to_contents = String::from_utf8(buf);
+    if selected.is_empty() {
+        return Err(anyhow!("No configurations matched the query."));
+    }
+    selected.sort();
+    Ok(selected)
+}
 
-            if let (Ok(from_contents), Ok(to_contents)) = (from_contents, to_contents) {
-                // check for case 1) files are the same
-                if from_contents == to_contents {
-                    return Ok(());
-                }
+/// lets the user pick configs from a list if neither a name nor `--all` is given.
+/// Without a terminal, nothing can be picked, so the usual error is returned.
+fn pick_configs(structure: &Structure, action: &str) -> Result<Vec<String>> {
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return Err(anyhow!("No configurations matched the query."));
+    }
 
-                if force {
-                    fs::create_dir_all(to_abs.parent().unwrap())?;
-                    fs::copy(from_abs, to_abs)?;
-                    return Ok(());
-                }
+    let mut names: Vec<&String> = structure.configs.keys().collect();
+    names.sort();
 
-                // case 2) compute diff
-                let diff = TextDiff::from_lines(&to_contents, &from_contents);
-
-                // compute the width of the line numbers
-                let ln_width = f32::ceil(f32::log10(usize::max(
-                    from_contents.lines().count(),
-                    to_contents.lines().count(),
-                ) as f32)) as usize;
-                let separator_pos = ln_width * 2 + 4;
-                let total_width = 80;
-
-                // print the file name
-                print_file_name(
-                    path_rel,
-                    "\x1b[36m~\x1b[0m",
-                    separator_pos,
-                    total_width,
-                    true,
-                );
+    let picked = MultiSelect::with_theme(prompt_theme().as_ref())
+        .with_prompt(format!(
+            "Which configurations do you want to {}? (space to select, enter to confirm)",
+            action
+        ))
+        .items(&names)
+        .interact_opt()?
+        .unwrap_or_default();
 
-                // adapted from https://github.com/mitsuhiko/similar/blob/main/examples/terminal-inline.rs
-                for (idx, group) in diff.grouped_ops(2).iter().enumerate() {
-                    // print separating line between changes
-                    if idx > 0 {
-                        print_separator_line(separator_pos, total_width);
-                    }
+    if picked.is_empty() {
+        return Err(anyhow!("No configurations matched the query."));
+    }
+    Ok(picked
+        .into_iter()
+        .map(|index| names[index].clone())
+        .collect())
+}
 
-                    // iterate over changes
-                    for op in group {
-                        for change in diff.iter_inline_changes(op) {
-                            let (bright_style, style, sign) = match change.tag() {
-                                ChangeTag::Delete => ("\x1b[91m", "\x1b[31m", '-'),
-                                ChangeTag::Insert => ("\x1b[92m", "\x1b[32m", '+'),
-                                ChangeTag::Equal => ("\x1b[2m", "\x1b[2m", ' '),
-                            };
-
-                            // print line numbers
-                            print!(
-                                    "\x1b[2m{:ln_width$} {:ln_width$} \x1b[0m{style}{}\x1b[0m\u{2502}{style} ",
-                                    change
-                                        .old_index()
-                                        .map_or(String::new(), |idx| idx.to_string()),
-                                    change
-                                        .new_index()
-                                        .map_or(String::new(), |idx| idx.to_string()),
-                                        sign,
-                                    style=style,
-                                    ln_width = ln_width
-                                );
-
-                            // print actual changes
-                            for (emphasized, value) in change.iter_strings_lossy() {
-                                if emphasized {
-                                    print!("\x1b[0;3m{}{}", bright_style, &value);
-                                } else {
-                                    print!("\x1b[0m{}{}", style, &value);
-                                }
-                            }
-
-                            // reset the style
-                            print!("\x1b[0m");
-
-                            // print a final newline if missing
-                            if change.missing_newline() {
-                                println!();
-                            }
-                        }
-                    }
-                }
+/// the theme of interactive lists, without colors if they are disabled
+fn prompt_theme() -> Box<dyn Theme> {
+    if style::enabled() {
+        Box::new(ColorfulTheme::default())
+    } else {
+        Box::new(SimpleTheme)
+    }
+}
 
-                // print closing line
-                print_end_line(separator_pos, total_width);
-            } else {
-                // print modification if file could not be read
-                print_file_name(path_rel, "\x1b[36m~\x1b[0m", 5, 80, false);
-            }
-        }
-        // case 3) file doesn't exist yet
-        else {
-            // print addition
-            print_file_name(path_rel, "\x1b[32m+\x1b[0m", 5, 80, false);
+/// applies the `--only` and `--exclude` globs of the subcommand to the names of the configs.
+/// Excludes are applied after `--only`.
+fn filter_configs<'a>(
+    matches: &ArgMatches,
+    names: impl Iterator<Item = &'a String>,
+) -> Result<Vec<String>> {
+    let build = |id: &str| -> Result<Option<GlobSet>> {
+        // not every subcommand has the filter options
+        let Some(patterns) = matches.try_get_many::<String>(id).ok().flatten() else {
+            return Ok(None);
+        };
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            builder.add(
+                Glob::new(pattern)
+                    .with_context(|| format!("Invalid glob '{}' in '--{}'", pattern, id))?,
+            );
         }
+        Ok(Some(builder.build()?))
+    };
+    let only = build("only")?;
+    let exclude = build("exclude")?;
+
+    Ok(names
+        .filter(|name| only.as_ref().is_none_or(|only| only.is_match(name)))
+        .filter(|name| {
+            !exclude
+                .as_ref()
+                .is_some_and(|exclude| exclude.is_match(name))
+        })
+        .cloned()
+        .collect())
+}
 
-        // copy the file
-        if prompt_bool("Do you want to continue? ", true) {
-            fs::create_dir_all(to_abs.parent().unwrap())?;
-            fs::copy(from_abs, to_abs)?;
+/// shows the changes which would be pulled in, without modifying anything
+fn config_diff(matches: &ArgMatches, structure: Structure) -> Result<()> {
+    diff::set_format(matches.get_one::<String>("format").unwrap());
+    diff::set_context(matches.get_one("context").copied());
+    pager::init(!matches.get_flag("no-pager"));
+    let mut changed = false;
+    let mut output = String::new();
+    for name in select_configs(matches, &structure)? {
+        let changes = pull_changes(&name, &structure.configs[&name], &structure.root, None)
+            .with_context(|| format!("Could not compare config '{}'", name))?;
+        for change in &changes {
+            output.push_str(&diff::format_change(change)?);
         }
+        changed |= !changes.is_empty();
     }
-    Ok(())
-}
+    pager::page(&output);
 
-fn print_file_name(
-    name: &Path,
-    modifier_symbol: &'static str,
-    separator_pos: usize,
-    total_width: usize,
-    continue_table: bool,
-) {
-    println!(
-        "{char:\u{2550}^width_left$}\u{2564}{char:\u{2550}^width_right$}",
-        char = "\u{2550}",
-        width_left = separator_pos - 1,
-        width_right = total_width - separator_pos
-    );
-    println!(
-        "{: ^width_left$}{} \u{2502} {}",
-        " ",
-        modifier_symbol,
-        name.display(),
-        width_left = separator_pos - 3
-    );
-
-    if continue_table {
-        print_separator_line(separator_pos, total_width);
-    } else {
-        print_end_line(separator_pos, total_width);
+    // like diff, the exit code signals if there are any differences
+    if changed {
+        process::exit(1);
     }
+    Ok(())
 }
 
-fn print_separator_line(separator_pos: usize, total_width: usize) {
-    println!(
-        "{char:\u{2500}^ln_width$}\u{253C}{char:\u{2500}^total_width$}",
-        char = "\u{2500}",
-        ln_width = separator_pos - 1,
-        total_width = total_width - separator_pos
-    );
+fn config_pull(matches: &ArgMatches, structure: Structure) -> Result<()> {
+    let name: Option<&String> = matches.get_one("name");
+    let all = matches.get_flag("all");
+    let force = matches.get_flag("force");
+    let quiet = matches.get_flag("quiet");
+    let dry_run = matches.get_flag("dry-run");
+    let commit: Option<&String> = matches.get_one("commit");
+    diff::set_format(matches.get_one::<String>("format").unwrap());
+    diff::set_context(matches.get_one("context").copied());
+    pager::init(!matches.get_flag("no-pager"));
+    let resolution = Resolution::new(matches, structure.root.pull.on_conflict);
+    progress::init(quiet);
+    let mode = if dry_run {
+        PullMode::DryRun
+    } else if matches.get_flag("select") {
+        PullMode::Select
+    } else if matches.get_flag("patch") {
+        PullMode::Patch
+    } else {
+        PullMode::new(force)
+    };
+
+    // fail before pulling anything if the changes can't be committed
+    let repository = match commit {
+        Some(_) => Some(git::open()?),
+        None => None,
+    };
+
+    let pulled: Vec<String> = if let Some(name) = name {
+        if all {
+            return Err(anyhow!("You cannot use the all flag in combination with a specific configuration. Try removing \"--all\" or the configuration name."));
+        }
+        let config = structure.config(name)?;
+        let path = match matches.get_one::<String>("path") {
+            Some(path) => Some(config_path(name, config, &structure.root, path)?),
+            None => None,
+        };
+        if let Some(output) = matches.get_one::<String>("output") {
+            return export_patch(name, config, &structure.root, path.as_deref(), output);
+        }
+        match pull_single(
+            name,
+            config,
+            &structure.root,
+            mode,
+            quiet,
+            path.as_deref(),
+            resolution,
+        )? {
+            0 => Vec::new(),
+            _ => vec![name.clone()],
+        }
+    } else {
+        let selected = if all {
+            select_configs(matches, &structure)?
+        } else {
+            pick_configs(&structure, "pull")?
+        };
+        let (names, skipped) = partition_skipped(selected, &structure);
+
+        let results: Vec<(String, Result<usize>)> = if force && quiet {
+            // nothing is asked and only failures are printed, so the configs can be pulled in parallel
+            names
+                .into_par_iter()
+                .map(|name| {
+                    let result = pull_single(
+                        &name,
+                        &structure.configs[&name],
+                        &structure.root,
+                        PullMode::AcceptAll,
+                        quiet,
+                        None,
+                        resolution,
+                    );
+                    (name, result)
+                })
+                .collect()
+        } else {
+            // prompts have to be answered one after another, and the pulled files of a config are listed together
+            names
+                .into_iter()
+                .map(|name| {
+                    println!("Pulling config '{}'", name);
+                    let result = pull_single(
+                        &name,
+                        &structure.configs[&name],
+                        &structure.root,
+                        mode,
+                        quiet,
+                        None,
+                        resolution,
+                    );
+                    (name, result)
+                })
+                .collect()
+        };
+
+        let mut pulled = Vec::new();
+        let mut failed = false;
+        for (name, result) in results {
+            match result {
+                Ok(0) => {}
+                Ok(_) => pulled.push(name),
+                Err(error) => {
+                    println!("Could not pull config '{}': {:#}", name, error);
+                    failed = true;
+                }
+            }
+        }
+        print_skipped(&skipped);
+        if failed && repository.is_some() {
+            return Err(anyhow!(
+                "Not all configs could be pulled, so the changes weren't committed."
+            ));
+        }
+        pulled
+    };
+
+    if let (Some(repository), Some(message)) = (repository, commit) {
+        if pulled.is_empty() {
+            println!("Nothing was pulled, so no commit was created.");
+            return Ok(());
+        }
+        let message = match message.as_str() {
+            "" => format!("Pull changes of {}", pulled.join(", ")),
+            message => message.to_string(),
+        };
+        if git::commit_all(&repository, &message)? {
+            println!("Committed the changes of {}.", pulled.join(", "));
+        } else {
+            println!("Nothing changed in the repository, so no commit was created.");
+        }
+    }
+    Ok(())
+}
+
+/// applies a patch with paths relative to a config to the files of the config in the repository
+fn config_apply(matches: &ArgMatches, structure: Structure) -> Result<()> {
+    let name: &String = matches.get_one("name").unwrap();
+    let file: &String = matches.get_one("patch").unwrap();
+    let check = matches.get_flag("check");
+    structure.config(name)?;
+
+    let text = if file == "-" {
+        std::io::read_to_string(std::io::stdin()).context("Could not read the patch")?
+    } else {
+        let path = config::invocation_path(file);
+        fs::read_to_string(&path).with_context(|| format!("Could not read '{}'", path.display()))?
+    };
+    let config_dir = RelativePathBuf::from(name).to_path(".");
+    let changed = patch::apply(&config_dir, &patch::parse(&text)?, check)?;
+
+    for (kind, path) in &changed {
+        println!("{} {}", kind.symbol(), Path::new(name).join(path).display());
+        // directories which only contained removed files are removed as well
+        if *kind == ChangeKind::Removed && !check {
+            if let Some(parent) = config_dir.join(path).parent() {
+                remove_empty_dirs(parent, &config_dir)?;
+            }
+        }
+    }
+    if check {
+        println!("The patch applies to config '{}'.", name);
+    } else {
+        println!("Applied {} change(s) to config '{}'.", changed.len(), name);
+    }
+    Ok(())
 }
 
-fn print_end_line(separator_pos: usize, total_width: usize) {
+/// writes the changes which pulling a config would apply to a patch file with paths relative to the config.
+/// Secrets and links are left out, as they can't be put into a patch.
+fn export_patch(
+    name: &str,
+    config: &Configuration,
+    root: &RootConfiguration,
+    only: Option<&Path>,
+    output: &str,
+) -> Result<()> {
+    let secret_patterns = mapping::secret_patterns(name, config)?;
+    let changes = pull_changes(name, config, root, only)?;
+    let mut patch = String::new();
+    let mut exported = 0;
+    for change in &changes {
+        let path = Path::new(name).join(&change.path);
+        if secret_patterns.is_match(&change.path) {
+            style::warn(&format!(
+                "'{}' is a secret and isn't written to the patch.",
+                path.display()
+            ));
+            continue;
+        }
+        match diff::unified(change)? {
+            Some(diff) => {
+                patch.push_str(&diff);
+                exported += 1;
+            }
+            None => style::warn(&format!(
+                "'{}' is a link and isn't written to the patch.",
+                path.display()
+            )),
+        }
+    }
+
+    if output == "-" {
+        print!("{}", patch);
+        return Ok(());
+    }
+    if exported == 0 {
+        println!("Nothing to pull from config '{}'.", name);
+        return Ok(());
+    }
+    let file = config::invocation_path(output);
+    fs::write(&file, patch).with_context(|| format!("Could not write '{}'", file.display()))?;
     println!(
-        "{char:\u{2500}^ln_width$}\u{2534}{char:\u{2500}^total_width$}",
-        char = "\u{2500}",
-        ln_width = separator_pos - 1,
-        total_width = total_width - separator_pos
+        "Wrote {} change(s) of config '{}' to '{}'.",
+        exported,
+        name,
+        file.display()
     );
+    Ok(())
+}
+
+/// How the changes of a config are handled while pulling.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PullMode {
+    /// ask for confirmation for every change
+    Ask,
+    /// accept all remaining changes without asking
+    AcceptAll,
+    /// skip all remaining changes
+    Quit,
+    /// only show the changes without pulling them
+    DryRun,
+    /// choose the changes from a list, the chosen ones are pulled without asking
+    Select,
+    /// like `Ask`, but every hunk of a modified text file is confirmed on its own
+    Patch,
+}
+
+impl PullMode {
+    fn new(force: bool) -> Self {
+        if force {
+            PullMode::AcceptAll
+        } else {
+            PullMode::Ask
+        }
+    }
+
+    /// asks whether a change should be pulled in and remembers the choice for the remaining changes
+    fn confirm(&mut self) -> bool {
+        self.ask("Do you want to continue? ")
+    }
+
+    /// asks with the given message, see [`PullMode::confirm`]
+    fn ask(&mut self, message: &str) -> bool {
+        match prompt_answer(message) {
+            Answer::Yes => true,
+            Answer::No => false,
+            Answer::All => {
+                *self = PullMode::AcceptAll;
+                true
+            }
+            Answer::Quit => {
+                *self = PullMode::Quit;
+                false
+            }
+        }
+    }
+}
+
+fn config_restore(matches: &ArgMatches, structure: Structure) -> Result<()> {
+    let names = match matches.get_one::<String>("name") {
+        Some(name) => vec![name.clone()],
+        None => pick_configs(&structure, "restore")?,
+    };
+    let force = matches.get_flag("force");
+    let at: Option<&String> = matches.get_one("at");
+    let list = matches.get_flag("list");
+
+    for name in names {
+        let snapshots = backup::snapshots(&name)?;
+        if snapshots.is_empty() {
+            return Err(anyhow!("There are no backups of config '{}'.", name));
+        }
+        let snapshot = match at {
+            Some(at) => snapshots.iter().find(|s| *s == at).ok_or_else(|| {
+                anyhow!(
+                    "There is no backup of config '{}' from '{}'. Available backups are: {}",
+                    name,
+                    at,
+                    snapshots.join(", ")
+                )
+            })?,
+            None => snapshots.last().unwrap(),
+        };
+
+        println!("Backups of config '{}':", name);
+        for other in &snapshots {
+            let marker = if other == snapshot && !list { "*" } else { " " };
+            println!(
+                "  {} {} ({} file(s))",
+                marker,
+                other,
+                backup::files(&name, other)?.len()
+            );
+        }
+        if list {
+            continue;
+        }
+
+        let restored = restore_single(&name, snapshot, PullMode::new(force))?;
+        if restored.is_empty() {
+            println!("Nothing to restore, the files already match the backup.");
+        } else {
+            println!(
+                "Restored {} file(s) of config '{}' from '{}':",
+                restored.len(),
+                name,
+                snapshot
+            );
+            for file in restored {
+                println!("  {}", file.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// copies the files of a backup snapshot back to their locations on the system.
+/// Files which are unchanged since the backup are left alone.
+/// Returns the files which were restored.
+fn restore_single(name: &str, snapshot: &str, mut mode: PullMode) -> Result<Vec<PathBuf>> {
+    let mut restored = Vec::new();
+    for file in backup::files(name, snapshot)? {
+        let exists = file.original.exists() || file.original.is_symlink();
+        if exists
+            && io::read_for_comparison(&file.original)? == io::read_for_comparison(&file.backup)?
+        {
+            continue;
+        }
+
+        let change = FileChange {
+            kind: if exists {
+                ChangeKind::Modified
+            } else {
+                ChangeKind::Added
+            },
+            path: file.original.clone(),
+            system: file.backup.clone(),
+            repo: file.original.clone(),
+            conflict: false,
+            eol: LineEnding::Keep,
+            merged: None,
+        };
+        match mode {
+            PullMode::Quit => break,
+            PullMode::AcceptAll => {}
+            PullMode::Ask | PullMode::Select | PullMode::Patch => {
+                diff::print_change(&change)?;
+                if !mode.confirm() {
+                    continue;
+                }
+            }
+            PullMode::DryRun => {
+                diff::print_change(&change)?;
+                continue;
+            }
+        }
+
+        copy_file(&file.backup, &file.original)?;
+        restored.push(file.original);
+    }
+    Ok(restored)
+}
+
+/// How files which were changed in the repository as well as on the system since they were deployed are pulled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Resolution {
+    /// pull in the file from the system
+    Theirs,
+    /// keep the file in the repository
+    Ours,
+    /// pull in the file with conflict markers, see [`merge_conflicts`]
+    Merge,
+}
+
+impl Resolution {
+    /// The flags take precedence over `on_conflict` in the `[pull]` table of `dottor.toml`.
+    fn new(matches: &ArgMatches, on_conflict: OnConflict) -> Option<Self> {
+        if matches.get_flag("theirs") {
+            Some(Resolution::Theirs)
+        } else if matches.get_flag("ours") {
+            Some(Resolution::Ours)
+        } else {
+            match on_conflict {
+                OnConflict::Prompt => None,
+                OnConflict::Ours => Some(Resolution::Ours),
+                OnConflict::Theirs => Some(Resolution::Theirs),
+                OnConflict::Merge => Some(Resolution::Merge),
+            }
+        }
+    }
+}
+
+/// backs up a file of the system before it is replaced, unless its contents stay the same.
+/// Without `contents`, existing files are always backed up.
+fn back_up(
+    snapshot: Option<&backup::Snapshot>,
+    destination: &Path,
+    contents: Option<&[u8]>,
+) -> Result<()> {
+    let Some(snapshot) = snapshot else {
+        return Ok(());
+    };
+    if destination.is_dir() || (!destination.exists() && !destination.is_symlink()) {
+        return Ok(());
+    }
+    if contents.is_some_and(|contents| {
+        io::read_for_comparison(destination).is_ok_and(|existing| existing == contents)
+    }) {
+        return Ok(());
+    }
+    snapshot.store(destination)
+}
+
+/// writes generated contents of a file of the repository to its location on the system, with the permissions of `from`.
+/// Returns false if the file already had these contents and was left as it is.
+fn deploy_contents(
+    from: &Path,
+    to: &Path,
+    contents: &[u8],
+    snapshot: Option<&backup::Snapshot>,
+) -> Result<bool> {
+    let changed = write_deployed(to, contents, snapshot, false)?;
+    copy_permissions(from, to)
+        .with_context(|| format!("Could not set permissions of '{}'", to.display()))?;
+    Ok(changed)
+}
+
+/// writes deployed contents unless the file on the system already has them, see [`deploy_contents`].
+/// `private` files are only readable by the user, even if they already existed.
+fn write_deployed(
+    to: &Path,
+    contents: &[u8],
+    snapshot: Option<&backup::Snapshot>,
+    private: bool,
+) -> Result<bool> {
+    let changed = !io::has_contents(to, contents);
+    if changed {
+        back_up(snapshot, to, Some(contents))?;
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Could not create directory '{}'", parent.display()))?;
+        }
+        // writing to a link would change the file it points to
+        if to.is_symlink() {
+            fs::remove_file(to).with_context(|| format!("Could not remove '{}'", to.display()))?;
+        }
+        if private {
+            io::write_private(to, contents)?;
+        } else {
+            write(to, contents)?;
+        }
+    } else if private {
+        io::set_mode(to, 0o600)?;
+    }
+    Ok(changed)
+}
+
+/// copies a file of the repository to the system, unless the file on the system already is a copy of it.
+/// Returns whether the file was copied.
+fn deploy_copy(from: &Path, to: &Path, snapshot: Option<&backup::Snapshot>) -> Result<bool> {
+    if io::is_copy(from, to) {
+        // the mode may still have been changed on the system
+        if !from.is_symlink() {
+            copy_permissions(from, to)
+                .with_context(|| format!("Could not set permissions of '{}'", to.display()))?;
+        }
+        return Ok(false);
+    }
+    if snapshot.is_some() && (to.exists() || to.is_symlink()) {
+        let contents = io::read_for_comparison(from)?;
+        back_up(snapshot, to, Some(&contents))?;
+    }
+    copy_file(from, to)?;
+    Ok(true)
+}
+
+/// decrypts a secret of the repository into its location on the system.
+/// Returns false if the file on the system was already up to date.
+fn deploy_secret(from: &Path, to: &Path, snapshot: Option<&backup::Snapshot>) -> Result<bool> {
+    let plaintext = secret::decrypt(from)?;
+    // the mode of the encrypted file doesn't matter, the plaintext is only readable by the user
+    // unless `deploy.permissions` says otherwise
+    write_deployed(to, &plaintext, snapshot, true)
+}
+
+/// pull local changes from a config into the repository.
+/// Changes which are accepted without asking are listed with one line each, unless `quiet` is set.
+/// With `only`, just the file or directory at this path relative to the config is pulled.
+/// Conflicting files are only pulled without confirmation if `resolution` is set.
+/// Returns the number of changes which were applied.
+fn pull_single(
+    name: &str,
+    config: &Configuration,
+    root: &RootConfiguration,
+    mut mode: PullMode,
+    quiet: bool,
+    only: Option<&Path>,
+    resolution: Option<Resolution>,
+) -> Result<usize> {
+    let config_dir = RelativePathBuf::from(name).to_path(".");
+    let secret_patterns = mapping::secret_patterns(name, config)?;
+    let mut applied = 0;
+    let mut conflicts = Vec::new();
+    let mut pending: HashMap<&str, usize> = HashMap::new();
+    // a dry run doesn't ask anything, so all of its output is paged at once
+    let mut preview = String::new();
+    let mut changes = pull_changes(name, config, root, only)?;
+    if resolution == Some(Resolution::Merge) {
+        merge_conflicts(name, &mut changes)?;
+    }
+    if mode == PullMode::Select {
+        changes = select_changes(name, changes, resolution)?;
+        mode = PullMode::AcceptAll;
+    }
+    for change in changes {
+        if change.conflict && mode != PullMode::Quit {
+            match resolution {
+                Some(Resolution::Ours) => continue,
+                Some(Resolution::Theirs) => {}
+                // pulling without asking would silently discard the changes in the repository.
+                // Conflicts which couldn't be merged are handled the same way.
+                None | Some(Resolution::Merge) if mode == PullMode::AcceptAll => {
+                    conflicts.push(change.path.display().to_string());
+                    continue;
+                }
+                None | Some(Resolution::Merge) => style::warn(&format!(
+                    "'{}' was changed in the repository as well as on the system since it was deployed. Pulling it discards the changes in the repository.",
+                    Path::new(name).join(&change.path).display()
+                )),
+            }
+        }
+        match mode {
+            PullMode::Quit => break,
+            PullMode::AcceptAll | PullMode::Select => {
+                if !quiet {
+                    progress::suspend(|| {
+                        println!(
+                            "{} {}",
+                            change.kind.symbol(),
+                            Path::new(name).join(&change.path).display()
+                        )
+                    });
+                }
+            }
+            PullMode::Patch => match patch_change(name, &change, &secret_patterns, &mut mode)? {
+                Some(pulled) => {
+                    applied += usize::from(pulled);
+                    continue;
+                }
+                // files which can't be split into hunks are confirmed as a whole
+                None => {
+                    diff::print_change(&change)?;
+                    if !mode.confirm() {
+                        continue;
+                    }
+                }
+            },
+            PullMode::Ask => {
+                diff::print_change(&change)?;
+                if !mode.confirm() {
+                    continue;
+                }
+            }
+            PullMode::DryRun => {
+                preview.push_str(&diff::format_change(&change)?);
+                let kind = match change.kind {
+                    ChangeKind::Added => "addition(s)",
+                    ChangeKind::Modified => "modification(s)",
+                    ChangeKind::Removed => "deletion(s)",
+                    ChangeKind::Mode => "mode change(s)",
+                };
+                *pending.entry(kind).or_default() += 1;
+                continue;
+            }
+        }
+
+        match change.kind {
+            ChangeKind::Added | ChangeKind::Modified if secret_patterns.is_match(&change.path) => {
+                pull_secret(&change.system, &change.repo)?;
+                // a secret which was committed in plain text is replaced by the encrypted one
+                let plaintext = config_dir.join(&change.path);
+                if plaintext != change.repo && plaintext.is_file() {
+                    fs::remove_file(&plaintext)
+                        .with_context(|| format!("Could not remove '{}'", plaintext.display()))?;
+                }
+            }
+            ChangeKind::Added | ChangeKind::Modified
+                if change.merged.is_some()
+                    || (change.eol != LineEnding::Keep && !change.system.is_symlink()) =>
+            {
+                pull_text(&change)?
+            }
+            ChangeKind::Added | ChangeKind::Modified => copy_file(&change.system, &change.repo)?,
+            ChangeKind::Mode => {
+                copy_permissions(&change.system, &change.repo).with_context(|| {
+                    format!("Could not set permissions of '{}'", change.repo.display())
+                })?
+            }
+            ChangeKind::Removed => {
+                fs::remove_file(&change.repo)
+                    .with_context(|| format!("Could not remove '{}'", change.repo.display()))?;
+                // directories which still contain excluded files aren't empty and are kept
+                if let Some(parent) = change.repo.parent() {
+                    remove_empty_dirs(parent, &config_dir)?;
+                }
+            }
+        }
+        applied += 1;
+    }
+
+    if mode == PullMode::DryRun {
+        let summary: Vec<String> = [
+            "addition(s)",
+            "modification(s)",
+            "deletion(s)",
+            "mode change(s)",
+        ]
+        .iter()
+        .filter_map(|kind| Some(format!("{} {}", pending.get(kind)?, kind)))
+        .collect();
+        if summary.is_empty() {
+            preview.push_str(&format!("Nothing to pull from config '{}'.\n", name));
+        } else {
+            preview.push_str(&format!(
+                "Pulling config '{}' would apply {}.\n",
+                name,
+                summary.join(", ")
+            ));
+        }
+        pager::page(&preview);
+    }
+
+    if !conflicts.is_empty() {
+        return Err(anyhow!(
+            "{} file(s) were changed in the repository as well as on the system and weren't pulled: {}. Use --theirs to pull them in or --ours to keep the repository version.",
+            conflicts.len(),
+            conflicts.join(", ")
+        ));
+    }
+    Ok(applied)
+}
+
+/// asks for every hunk of a modified text file whether it is pulled, like `git add --patch`.
+/// The accepted hunks are applied to the file in the repository.
+/// Returns `None` if the change can't be split into hunks, otherwise whether the file was changed.
+fn patch_change(
+    name: &str,
+    change: &FileChange,
+    secret_patterns: &GlobSet,
+    mode: &mut PullMode,
+) -> Result<Option<bool>> {
+    if change.kind != ChangeKind::Modified
+        || secret_patterns.is_match(&change.path)
+        || change.repo.is_symlink()
+        || change.system.is_symlink()
+    {
+        return Ok(None);
+    }
+    let old = fs::read(&change.repo)
+        .with_context(|| format!("Could not read file '{}'", change.repo.display()))?;
+    let new = change.pulled_contents()?;
+    let (Some(old), Some(new)) = (as_text(&old), as_text(&new)) else {
+        return Ok(None);
+    };
+
+    let path = Path::new(name).join(&change.path);
+    let patched = diff::patch(old, new, |hunk| match mode {
+        PullMode::AcceptAll => true,
+        PullMode::Quit => false,
+        _ => {
+            progress::suspend(|| hunk.print(&path));
+            mode.ask(&format!("Hunk {} of {}.", hunk.number, hunk.count))
+        }
+    });
+    if patched == old {
+        return Ok(Some(false));
+    }
+    write(&change.repo, patched.as_bytes())?;
+    Ok(Some(true))
+}
+
+/// merges conflicting text files with conflict markers around the lines which were changed in the repository as well as
+/// on the system, so that they can be pulled and resolved in the repository later.
+/// Files without kept contents of the last deployment and binary files stay conflicts.
+fn merge_conflicts(name: &str, changes: &mut [FileChange]) -> Result<()> {
+    let bases = manifest::hashes(name)?;
+    for change in changes
+        .iter_mut()
+        .filter(|change| change.conflict && change.kind == ChangeKind::Modified)
+    {
+        let base = bases
+            .get(&change.system)
+            .and_then(|hash| manifest::read_base(name, hash));
+        let Some(base) = base.filter(|_| !change.system.is_symlink()) else {
+            continue;
+        };
+        // the deployed contents had the line endings of the config
+        let repo = io::read_for_comparison(&change.repo)?;
+        let repo = io::convert_line_endings(&repo, change.eol);
+        let system = io::read_for_comparison(&change.system)?;
+        let (Some(repo), Some(system)) = (as_text(&repo), as_text(&system)) else {
+            continue;
+        };
+        change.merged = Some(merge::merge_with_markers(&base, repo, system).into_bytes());
+        change.conflict = false;
+        style::warn(&format!(
+            "'{}' was changed in the repository as well as on the system. The conflicting lines are marked in the pulled file.",
+            Path::new(name).join(&change.path).display()
+        ));
+    }
+    Ok(())
+}
+
+/// lets the user choose the changes of a config which are pulled from a list.
+/// Conflicts are marked in the list, so the chosen ones are pulled like with `--theirs`.
+fn select_changes(
+    name: &str,
+    changes: Vec<FileChange>,
+    resolution: Option<Resolution>,
+) -> Result<Vec<FileChange>> {
+    let changes: Vec<FileChange> = changes
+        .into_iter()
+        .filter(|change| !change.conflict || resolution != Some(Resolution::Ours))
+        .collect();
+    if changes.is_empty() {
+        return Ok(changes);
+    }
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return Err(anyhow!("Choosing the changes to pull requires a terminal."));
+    }
+
+    let items: Vec<String> = changes
+        .iter()
+        .map(|change| {
+            format!(
+                "{} {}{}",
+                change.kind.symbol(),
+                Path::new(name).join(&change.path).display(),
+                if change.conflict {
+                    " (also changed in the repository)"
+                } else {
+                    ""
+                }
+            )
+        })
+        .collect();
+    let picked = progress::suspend(|| {
+        MultiSelect::with_theme(prompt_theme().as_ref())
+            .with_prompt(format!(
+                "Which changes of config '{}' do you want to pull? (space to select, enter to confirm)",
+                name
+            ))
+            .items(&items)
+            .interact_opt()
+    })?
+    .unwrap_or_default();
+
+    Ok(changes
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| picked.contains(index))
+        .map(|(_, mut change)| {
+            change.conflict = false;
+            change
+        })
+        .collect())
+}
+
+/// pulls a file whose line endings are converted back to the ones of the repository
+fn pull_text(change: &FileChange) -> Result<()> {
+    let contents = change.pulled_contents()?;
+    if let Some(parent) = change.repo.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Could not create directory '{}'", parent.display()))?;
+    }
+    // writing to a link would change the file it points to
+    if change.repo.is_symlink() {
+        fs::remove_file(&change.repo)
+            .with_context(|| format!("Could not remove '{}'", change.repo.display()))?;
+    }
+    write(&change.repo, &contents)?;
+    copy_permissions(&change.system, &change.repo)
+        .with_context(|| format!("Could not set permissions of '{}'", change.repo.display()))
+}
+
+/// encrypts a deployed secret into the repository
+fn pull_secret(from: &Path, to: &Path) -> Result<()> {
+    let plaintext =
+        fs::read(from).with_context(|| format!("Could not read file '{}'", from.display()))?;
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Could not create directory '{}'", parent.display()))?;
+    }
+    write(to, &secret::encrypt(&plaintext)?)
+}
+
+/// collects the differences between the deployed files of a config and the repository
+/// With `only`, just the file or directory at this path relative to the config is compared.
+fn pull_changes(
+    name: &str,
+    config: &Configuration,
+    root: &RootConfiguration,
+    only: Option<&Path>,
+) -> Result<Vec<FileChange>> {
+    if skipped(name, config) {
+        return Ok(Vec::new());
+    }
+    let mappings = mapping::resolve(config)?;
+    mapping::check_outside_repository(name, &mappings)?;
+    let exclude_patterns = mapping::exclude_patterns(name, config, root)?;
+    let config_dir = RelativePathBuf::from(name).to_path(".");
+    let dotconfig = config_dir.join(config::CONFIG_PATH);
+
+    let template_patterns = mapping::template_patterns(name, config)?;
+    let secret_patterns = mapping::secret_patterns(name, config)?;
+    let permissions = mapping::permissions(name, config)?;
+    let follow_symlinks = config.target.follow_symlinks;
+    let eol = config.target.eol.unwrap_or_default();
+    let respect_gitignore = config
+        .pull
+        .as_ref()
+        .is_some_and(|pull| pull.respect_gitignore);
+    let variables = template::variables(root);
+    // the warnings about changed templates are printed at the end, as the files are compared in parallel
+    let changed_templates = Mutex::new(Vec::new());
+    let compare = |path_rel: &Path, from: &Path| -> Result<Option<FileChange>> {
+        if secret_patterns.is_match(path_rel) {
+            return compare_secret(&config_dir, path_rel, from);
+        }
+        // pulling a rendered template would replace its placeholders, so changes are only reported
+        if template_patterns.is_match(path_rel) {
+            let template = config_dir.join(path_rel);
+            if template.exists() && template_changed(&template, from, &variables, eol)? {
+                changed_templates
+                    .lock()
+                    .unwrap_or_else(|error| error.into_inner())
+                    .push(template);
+            }
+            return Ok(None);
+        }
+        // a linked file is the file in the repository
+        if io::resolves_to(from, &config_dir.join(path_rel)) {
+            return Ok(None);
+        }
+        let change = compare_file(&config_dir, path_rel, from, eol)?;
+        // modes from the permissions table are expected to differ from the repository
+        Ok(change.filter(|change| {
+            change.kind != ChangeKind::Mode || permissions.mode(path_rel).is_none()
+        }))
+    };
+
+    let mut changes = Vec::new();
+
+    for mapping in &mappings {
+        if only.is_some_and(|only| {
+            !only.starts_with(&mapping.source) && !mapping.source.starts_with(only)
+        }) {
+            continue;
+        }
+        if !mapping.destination.exists() {
+            return Err(anyhow!(
+                "Target '{}' does not exist. Has the config been deployed?",
+                mapping.destination.display()
+            ));
+        }
+
+        if !mapping.is_directory(&config_dir) {
+            if !exclude_patterns.is_match(&mapping.source) {
+                changes.extend(compare(&mapping.source, &mapping.destination)?);
+            }
+            continue;
+        }
+
+        // pull file from deployed configuration
+        // there are four cases for this:
+        //  1) from exists, to exists && unchanged -> do nothing
+        //  2) from exists, to exists && modified -> display diff
+        //  3) from exists, to doesn't exist -> display addition
+        //  4) from doesn't exist, to exists -> display removal
+        // a path inside of the mapping restricts the walk to it
+        let (system_dir, repo_dir) = match only {
+            Some(only) if only.starts_with(&mapping.source) => {
+                (mapping.destination_of(only), config_dir.join(only))
+            }
+            _ => (
+                mapping.destination.clone(),
+                config_dir.join(&mapping.source),
+            ),
+        };
+        let mut paths = get_paths_under(&system_dir, follow_symlinks)?;
+        if respect_gitignore {
+            paths = filter_gitignored(&system_dir, paths, follow_symlinks)?;
+        }
+        let bar = progress::bar(paths.len(), name);
+        // the files are compared in parallel, the changes keep the order of the paths
+        let compared = paths
+            .par_iter()
+            .map(|from_abs| -> Result<Option<FileChange>> {
+                bar.inc(1);
+                // resolve the path relative to the config
+                let path_rel = mapping
+                    .source_of(from_abs)
+                    .ok_or_else(|| anyhow!("could not resolve relative path"))?;
+                // files of more specific mappings are handled by those
+                if exclude_patterns.is_match(&path_rel)
+                    || !mapping::owner(&mappings, &path_rel)
+                        .is_some_and(|m| std::ptr::eq(m, mapping))
+                {
+                    return Ok(None);
+                }
+                bar.set_message(path_rel.display().to_string());
+                compare(&path_rel, from_abs)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        changes.extend(compared.into_iter().flatten());
+        bar.finish_and_clear();
+
+        // check for case 4) file was deleted
+        let mut repo_paths = get_paths_under(&repo_dir, follow_symlinks)?;
+        // a single secret is stored with the extension of encrypted files
+        if only.is_some() {
+            repo_paths.extend(get_paths_under(
+                &secret::encrypted_path(&repo_dir),
+                follow_symlinks,
+            )?);
+        }
+        for to_abs in repo_paths {
+            // resolve relative path
+            let path_rel = to_abs
+                .strip_prefix(&config_dir)
+                .map_err(|_| anyhow!("could not resolve relative path"))?;
+            let path_rel = &match secret::decrypted_path(path_rel) {
+                Some(decrypted) if secret_patterns.is_match(&decrypted) => decrypted,
+                _ => path_rel.to_path_buf(),
+            };
+            if exclude_patterns.is_match(path_rel)
+                || to_abs == dotconfig
+                || !mapping::owner(&mappings, path_rel).is_some_and(|m| std::ptr::eq(m, mapping))
+            {
+                continue;
+            }
+            // get source
+            let from_abs = mapping.destination_of(path_rel);
+
+            if !from_abs.exists() && !from_abs.is_symlink() {
+                changes.push(FileChange {
+                    kind: ChangeKind::Removed,
+                    path: path_rel.to_path_buf(),
+                    system: from_abs,
+                    repo: to_abs,
+                    conflict: false,
+                    eol,
+                    merged: None,
+                });
+            }
+        }
+    }
+
+    let mut changed_templates = changed_templates
+        .into_inner()
+        .unwrap_or_else(|error| error.into_inner());
+    changed_templates.sort();
+    for template in changed_templates {
+        style::warn(&format!(
+            "The templated file '{}' was changed on the system and has to be updated manually.",
+            template.display()
+        ));
+    }
+
+    merge_with_base(name, &secret_patterns, &mut changes)?;
+    Ok(changes)
+}
+
+/// compares the changes with the contents of the last deployment, which is the common base of the repository and the system.
+/// Files which were only changed in the repository since then have nothing to pull and are dropped, pulling them would discard
+/// the changes in the repository. Text files which were changed in both places are merged if the contents of the last deployment
+/// were kept and the changes don't overlap, the others are marked as conflicts.
+/// Configs without a manifest have no base. Secrets aren't checked, as comparing them would require decrypting them once more.
+fn merge_with_base(
+    name: &str,
+    secret_patterns: &GlobSet,
+    changes: &mut Vec<FileChange>,
+) -> Result<()> {
+    let base = manifest::hashes(name)?;
+    if base.is_empty() {
+        return Ok(());
+    }
+    let keep: Vec<bool> = changes
+        .par_iter_mut()
+        .map(|change| -> Result<bool> {
+            // pulling a mode doesn't discard any contents
+            if matches!(change.kind, ChangeKind::Added | ChangeKind::Mode)
+                || secret_patterns.is_match(&change.path)
+            {
+                return Ok(true);
+            }
+            let Some(base) = base.get(&change.system) else {
+                return Ok(true);
+            };
+            // the deployed contents had the line endings of the config
+            let repo = io::read_for_comparison(&change.repo)?;
+            let repo = io::convert_line_endings(&repo, change.eol);
+            if manifest::hash(&repo) == *base {
+                return Ok(true);
+            }
+            // a removed file is a conflict if its contents in the repository were changed
+            if change.kind == ChangeKind::Removed {
+                change.conflict = true;
+                return Ok(true);
+            }
+            let system = io::read_for_comparison(&change.system)?;
+            if manifest::hash(&system) == *base {
+                return Ok(false);
+            }
+            change.merged = match (
+                manifest::read_base(name, base),
+                as_text(&repo),
+                as_text(&system),
+            ) {
+                (Some(base), Some(repo), Some(system)) if !change.system.is_symlink() => {
+                    merge::merge(&base, repo, system).map(String::into_bytes)
+                }
+                _ => None,
+            };
+            // the changes on the system may already be in the repository
+            if change.merged.as_deref() == Some(&*repo) {
+                return Ok(false);
+            }
+            change.conflict = change.merged.is_none();
+            Ok(true)
+        })
+        .collect::<Result<_>>()?;
+    let mut keep = keep.into_iter();
+    changes.retain(|_| keep.next().unwrap_or(true));
+    Ok(())
+}
+
+/// compares a deployed secret with its decrypted counterpart in the repository
+fn compare_secret(config_dir: &Path, path_rel: &Path, from: &Path) -> Result<Option<FileChange>> {
+    let to_abs = config_dir.join(secret::encrypted_path(path_rel));
+
+    let kind = if to_abs.exists() {
+        if fs::read(from)? == secret::decrypt(&to_abs)? {
+            return Ok(None);
+        }
+        ChangeKind::Modified
+    } else {
+        ChangeKind::Added
+    };
+    // secrets are pulled byte for byte
+    Ok(Some(FileChange {
+        kind,
+        path: path_rel.to_path_buf(),
+        system: from.to_path_buf(),
+        repo: to_abs,
+        conflict: false,
+        eol: LineEnding::Keep,
+        merged: None,
+    }))
+}
+
+/// compares a deployed file with its counterpart in the repository.
+/// Unless `eol` is `keep`, differences in the line endings of text files are ignored.
+fn compare_file(
+    config_dir: &Path,
+    path_rel: &Path,
+    from: &Path,
+    eol: LineEnding,
+) -> Result<Option<FileChange>> {
+    let dotconfig = config_dir.join(config::CONFIG_PATH);
+    // get destination
+    let to_abs = config_dir.join(path_rel);
+
+    // ensure that we aren't accidentally overwriting the dotconfig
+    if to_abs == dotconfig {
+        return Err(
+            anyhow!("Trying to overwrite dotconfig.toml configuration file. Please add 'dotconfig.toml' to your excludes in the target configuration."),
+        );
+    }
+
+    let mut change = FileChange {
+        // case 3) file doesn't exist yet
+        kind: ChangeKind::Added,
+        path: path_rel.to_path_buf(),
+        system: from.to_path_buf(),
+        repo: to_abs,
+        conflict: false,
+        eol,
+        merged: None,
+    };
+    if change.repo.exists() || change.repo.is_symlink() {
+        // check for case 1) files are the same
+        // the raw bytes are compared, so that unchanged binary files aren't reported.
+        // Files of different sizes can't be the same, which saves reading large binary files.
+        let sizes_differ = eol == LineEnding::Keep
+            && !from.is_symlink()
+            && !change.repo.is_symlink()
+            && fs::metadata(from)?.len() != fs::metadata(&change.repo)?.len();
+        change.kind = if !sizes_differ
+            && change.pulled_contents()? == io::read_for_comparison(&change.repo)?
+        {
+            // scripts which were made executable on the system only differ in their mode
+            if !from.is_symlink()
+                && !change.repo.is_symlink()
+                && io::executable_differs(from, &change.repo)
+            {
+                ChangeKind::Mode
+            } else {
+                return Ok(None);
+            }
+        } else {
+            // case 2) file was modified
+            ChangeKind::Modified
+        };
+    }
+    Ok(Some(change))
+}
+
+/// checks if a deployed template differs from the rendered template in the repository
+fn template_changed(
+    template: &Path,
+    deployed: &Path,
+    variables: &HashMap<String, String>,
+    eol: LineEnding,
+) -> Result<bool> {
+    let source = fs::read(template)?;
+    let deployed = fs::read(deployed)?;
+    match as_text(&source) {
+        Some(text) => {
+            let rendered = template::render(text, variables)
+                .with_context(|| format!("Could not render template '{}'", template.display()))?;
+            Ok(*io::convert_line_endings(rendered.as_bytes(), eol) != *deployed)
+        }
+        None => Ok(source != deployed),
+    }
 }
 
 /// deploy one or all configs to the local system
-fn config_deploy(matches: &ArgMatches, mut structure: Structure) -> Result<()> {
+fn config_deploy(matches: &ArgMatches, structure: Structure) -> Result<()> {
     let name: Option<&String> = matches.get_one("name");
     let all = matches.get_flag("all");
+    let args = DeployArgs::new(matches);
+    progress::init(matches.get_flag("quiet"));
+    diff::set_context(matches.get_one("context").copied());
+    pager::init(!matches.get_flag("no-pager"));
 
     if let Some(name) = name {
         if all {
             return Err(anyhow!("You cannot use the all flag in combination with a specific configuration. Try removing \"--all\" or the configuration name."),
                 );
         }
-        let config = structure.configs.remove(name);
-        match config {
-            Some(config) => deploy_single(&String::from(name), config),
-            None => Err(anyhow!(format!("Config '{name}' does not exist."))),
-        }
-    } else if all {
-        for (name, config) in structure.configs {
-            match deploy_single(&name, config) {
-                Ok(_) => {}
-                Err(error) => println!("Could not deploy config '{}': {}", name, error),
+        structure.config(name)?;
+        let config = &structure.configs[name];
+        let path = match matches.get_one::<String>("path") {
+            Some(path) => Some(config_path(name, config, &structure.root, path)?),
+            None => None,
+        };
+        // dependencies are only deployed along with the whole config
+        if path.is_none() {
+            let names = dependencies::deployed_with(
+                &structure.configs,
+                name,
+                matches.get_flag("with-deps"),
+            );
+            if names.len() > 1 {
+                let (names, skipped) = partition_skipped(names, &structure);
+                return deploy_configs(matches, &structure, &names, &skipped, args);
             }
         }
+        // ensures that the local dependencies of the config are present
+        dependencies::deploy_order(&structure.configs, std::slice::from_ref(name))?;
+
+        if matches.get_flag("diff") {
+            print_deploy_diffs(
+                &structure,
+                std::slice::from_ref(name),
+                path.as_deref(),
+                args,
+            )?;
+        }
+        if !confirm_deploy(
+            matches,
+            &structure,
+            std::slice::from_ref(name),
+            path.as_deref(),
+        ) {
+            return Ok(());
+        }
+        deploy_single(name, config, &structure.root, path.as_deref(), args)?;
+        if matches.get_flag("watch") {
+            return watch_deploy(std::slice::from_ref(name), &structure.root, args);
+        }
         Ok(())
     } else {
-        Err(anyhow!("No configurations matched the query."))
+        let selected = if all {
+            select_configs(matches, &structure)?
+        } else {
+            pick_configs(&structure, "deploy")?
+        };
+        let (names, skipped) = partition_skipped(selected, &structure);
+        deploy_configs(matches, &structure, &names, &skipped, args)
     }
 }
 
-fn deploy_single(name: &String, config: Configuration) -> Result<()> {
-    let target = match env::consts::OS {
-        "windows" => config.target.windows,
-        "linux" => config.target.linux,
-        value => {
-            return Err(anyhow!(format!(
-                "Operating system '{value}' is not supported."
-            )))
+/// deploys several configs in the order of their local dependencies.
+/// Configs which don't depend on each other are deployed in parallel.
+fn deploy_configs(
+    matches: &ArgMatches,
+    structure: &Structure,
+    names: &[String],
+    skipped: &[String],
+    args: DeployArgs,
+) -> Result<()> {
+    let order = dependencies::deploy_order(&structure.configs, names)?;
+    if matches.get_flag("diff") {
+        print_deploy_diffs(structure, &order, None, args)?;
+    }
+    if !confirm_deploy(matches, structure, &order, None) {
+        return Ok(());
+    }
+
+    // configs which weren't deployed, configs which require them aren't deployed either
+    let mut failed: HashSet<String> = HashSet::new();
+    // configs of the same stage don't depend on each other and are deployed in parallel
+    for stage in dependencies::deploy_stages(&structure.configs, &order) {
+        let mut deployable = Vec::new();
+        for name in stage {
+            match dependencies::failed_dependency(&name, &structure.configs[&name], &failed) {
+                Some(dependency) => {
+                    eprintln!(
+                        "Skipped config '{}' because dependency '{}' failed.",
+                        name, dependency
+                    );
+                    failed.insert(name);
+                }
+                None => deployable.push(name),
+            }
         }
-    };
 
-    let target_path = PathBuf::from(shellexpand::tilde(&target.directory.unwrap()).into_owned());
+        let results: Vec<(String, Result<()>)> = deployable
+            .into_par_iter()
+            .map(|name| {
+                let config = &structure.configs[&name];
+                let result = deploy_single(&name, config, &structure.root, None, args);
+                (name, result)
+            })
+            .collect();
+
+        for (name, result) in results {
+            if let Err(error) = result {
+                eprintln!("Could not deploy config '{}': {:#}", name, error);
+                failed.insert(name);
+            }
+        }
+    }
+    print_skipped(skipped);
+    if !failed.is_empty() {
+        let mut failed: Vec<String> = failed.into_iter().collect();
+        failed.sort();
+        return Err(anyhow!(
+            "{} config(s) could not be deployed: {}",
+            failed.len(),
+            failed.join(", ")
+        ));
+    }
+    if matches.get_flag("watch") {
+        return watch_deploy(&order, &structure.root, args);
+    }
+    Ok(())
+}
+
+/// redeploys configs whenever one of their files changes, until the program is stopped.
+/// The configuration is read again before each deploy, so that changes to it are picked up.
+fn watch_deploy(names: &[String], root: &RootConfiguration, args: DeployArgs) -> Result<()> {
+    println!(
+        "Watching {} for changes, press Ctrl-C to stop.",
+        names.join(", ")
+    );
+
+    watch::watch(names, |name, paths| {
+        let config_dir = RelativePathBuf::from(name).to_path(".");
+        let result =
+            config::read_configuration(&config_dir.join(config::CONFIG_PATH)).and_then(|config| {
+                // edits of excluded files don't change the deployed files
+                let exclude_patterns = mapping::exclude_patterns(name, &config, root)?;
+                let dotconfig = Path::new(config::CONFIG_PATH);
+                if paths
+                    .iter()
+                    .all(|path| path != dotconfig && exclude_patterns.is_match(path))
+                {
+                    return Ok(false);
+                }
+                deploy_single(name, &config, root, None, args)?;
+                Ok(true)
+            });
+
+        match result {
+            Ok(true) => println!("Redeployed config '{}'.", name),
+            Ok(false) => {}
+            Err(error) => println!("Could not deploy config '{}': {:#}", name, error),
+        }
+    })
+}
 
-    // checks if the target directory already has files in it
-    match &target.require_empty {
-        Some(value) => {
-            if *value {
-                check_dir_null_or_empty(&target_path)?;
+/// shows the changes deploying makes to the files on the system, from the system to the repository version.
+/// New files are shown in full, links and secrets only with a notice.
+fn print_deploy_diffs(
+    structure: &Structure,
+    names: &[String],
+    only: Option<&Path>,
+    args: DeployArgs,
+) -> Result<()> {
+    let hostname = config::hostname();
+    // all diffs are paged together, so that they can be read before the question
+    let mut output = String::new();
+    for name in names {
+        let config = &structure.configs[name];
+        if config.skip_reason(hostname.as_deref()).is_some() {
+            continue;
+        }
+        let files = files_to_deploy(name, config, &structure.root, only, args.changed)?;
+        let template_patterns = mapping::template_patterns(name, config)?;
+        let variables = template::variables(&structure.root);
+        let strategy = config.target.strategy.unwrap_or_default();
+        let symlink_style = args.symlink_style(config);
+        let eol = config.target.eol.unwrap_or_default();
+
+        for file in &files {
+            let action = deploy_action(
+                file,
+                &template_patterns,
+                &variables,
+                strategy,
+                symlink_style,
+                eol,
+            )?;
+            let symbol = match action {
+                DeployAction::Create => ChangeKind::Added.symbol(),
+                DeployAction::Overwrite => ChangeKind::Modified.symbol(),
+                DeployAction::Unchanged => continue,
+            };
+            let path = Path::new(name).join(&file.path);
+            if strategy == DeployStrategy::Symlink && !file.source.is_symlink() && !file.secret {
+                let notice = format!("link to '{}'", file.source.display());
+                output.push_str(&diff::format_notice(&path, &symbol, &notice));
+                continue;
+            }
+            if file.secret {
+                output.push_str(&diff::format_notice(&path, &symbol, "secret changed"));
+                continue;
             }
+
+            let new = if template_patterns.is_match(&file.path) {
+                render_file(&file.source, &variables)?
+            } else {
+                io::read_for_comparison(&file.source)?
+            };
+            let new = io::convert_line_endings(&new, eol);
+            let old = if file.destination.exists() || file.destination.is_symlink() {
+                io::read_for_comparison(&file.destination)?
+            } else {
+                Vec::new()
+            };
+            output.push_str(&diff::format_diff(&path, &symbol, &old, &new));
         }
-        None => {
-            if config.target.require_empty {
-                check_dir_null_or_empty(&target_path)?;
+    }
+    pager::page(&output);
+    Ok(())
+}
+
+/// Lists how many files of each config will be created and overwritten and asks once whether to continue.
+/// The question is skipped with `--yes`, `--dry-run`, if stdin isn't a terminal or if no file would change.
+fn confirm_deploy(
+    matches: &ArgMatches,
+    structure: &Structure,
+    names: &[String],
+    only: Option<&Path>,
+) -> bool {
+    if matches.get_flag("yes") || matches.get_flag("dry-run") || !std::io::stdin().is_terminal() {
+        return true;
+    }
+
+    let args = DeployArgs::new(matches);
+    let hostname = config::hostname();
+    let mut total = 0;
+    for name in names {
+        let config = &structure.configs[name];
+        if config.skip_reason(hostname.as_deref()).is_some() {
+            continue;
+        }
+        // errors are reported when the config is actually deployed
+        let Ok(files) = files_to_deploy(name, config, &structure.root, only, args.changed) else {
+            continue;
+        };
+        let template_patterns = mapping::template_patterns(name, config).unwrap_or_default();
+        let variables = template::variables(&structure.root);
+        let strategy = config.target.strategy.unwrap_or_default();
+        let symlink_style = args.symlink_style(config);
+        let eol = config.target.eol.unwrap_or_default();
+        let (mut created, mut overwritten, mut unchanged) = (0, 0, 0);
+        for file in &files {
+            // a file which can't be compared is counted as overwritten, to be safe
+            match deploy_action(
+                file,
+                &template_patterns,
+                &variables,
+                strategy,
+                symlink_style,
+                eol,
+            ) {
+                Ok(DeployAction::Create) => created += 1,
+                Ok(DeployAction::Unchanged) => unchanged += 1,
+                Ok(DeployAction::Overwrite) | Err(_) => overwritten += 1,
             }
         }
+        total += created + overwritten;
+
+        // overwriting files is highlighted, since it can't be undone
+        let overwritten = match overwritten {
+            0 => String::from("0"),
+            count => paint(style::YELLOW, &count.to_string()),
+        };
+        println!(
+            "  {}: {} new, {} overwritten, {} unchanged",
+            name,
+            paint(style::GREEN, &created.to_string()),
+            overwritten,
+            unchanged
+        );
+    }
+
+    // there is nothing to confirm if no file is created or overwritten
+    if total == 0 {
+        return true;
+    }
+    prompt_bool(&format!("{} file(s) will be deployed.", total), true)
+}
+
+/// Checks a path given on the command line, which selects a single file or directory of a config.
+/// The path has to stay inside of the config, must not be excluded and has to exist either in the
+/// config or at its deployed location.
+fn config_path(
+    name: &str,
+    config: &Configuration,
+    root: &RootConfiguration,
+    path: &str,
+) -> Result<PathBuf> {
+    let path = PathBuf::from(path);
+    if path.is_absolute()
+        || path
+            .components()
+            .any(|component| component == std::path::Component::ParentDir)
+    {
+        return Err(anyhow!(
+            "'{}' is outside of config '{}'.",
+            path.display(),
+            name
+        ));
+    }
+    let exclude_patterns = mapping::exclude_patterns(name, config, root)?;
+    let is_dir = RelativePathBuf::from(name)
+        .to_path(".")
+        .join(&path)
+        .is_dir();
+    if path == Path::new(config::CONFIG_PATH)
+        || (is_dir && exclude_patterns.is_dir_match(&path))
+        || (!is_dir && exclude_patterns.is_match(&path))
+    {
+        return Err(anyhow!(
+            "'{}' is excluded in config '{}'.",
+            path.display(),
+            name
+        ));
+    }
+
+    let config_dir = RelativePathBuf::from(name).to_path(".");
+    let mappings = mapping::resolve(config)?;
+    let in_config =
+        config_dir.join(&path).exists() || config_dir.join(secret::encrypted_path(&path)).exists();
+    let deployed = mapping::owner(&mappings, &path)
+        .is_some_and(|mapping| mapping.destination_of(&path).exists());
+    if !in_config && !deployed {
+        return Err(anyhow!(
+            "'{}' does not exist in config '{}'.",
+            path.display(),
+            name
+        ));
     }
-    // create target
-    fs::create_dir_all(&target_path)?;
+    Ok(path)
+}
+
+/// A file of a config and the location it is deployed to.
+struct DeployedFile {
+    /// the file in the repository
+    source: PathBuf,
+    /// the location of the file on the system
+    destination: PathBuf,
+    /// the path relative to the config
+    path: PathBuf,
+    /// the directory the file is deployed into, empty parent directories are only removed up to here
+    root: PathBuf,
+    /// whether the file is stored encrypted in the repository
+    secret: bool,
+}
+
+/// A directory of the `keep_dirs` of a config and the location it is created at.
+struct KeptDir {
+    /// the path relative to the config
+    path: PathBuf,
+    /// the location of the directory on the system
+    destination: PathBuf,
+    /// the directory of the mapping, empty parent directories are only removed up to here
+    root: PathBuf,
+}
+
+/// resolves the locations of the `keep_dirs` of a config on the system.
+/// Excluded directories are left out. With `only`, just the directories inside of this path are resolved.
+fn kept_dirs(
+    name: &str,
+    config: &Configuration,
+    root: &RootConfiguration,
+    only: Option<&Path>,
+) -> Result<Vec<KeptDir>> {
+    let mappings = mapping::resolve(config)?;
+    let exclude_patterns = mapping::exclude_patterns(name, config, root)?;
+    let mut dirs = Vec::new();
+    for dir in &config.target.keep_dirs {
+        let path = PathBuf::from(dir);
+        if exclude_patterns.is_dir_match(&path) || only.is_some_and(|only| !path.starts_with(only))
+        {
+            continue;
+        }
+        let mapping = mapping::owner(&mappings, &path).ok_or_else(|| {
+            anyhow!(
+                "'{}' in 'target.keep_dirs' of config '{}' isn't deployed by any mapping.",
+                dir,
+                name
+            )
+        })?;
+        dirs.push(KeptDir {
+            destination: mapping.destination_of(&path),
+            root: mapping.destination.clone(),
+            path,
+        });
+    }
+    Ok(dirs)
+}
+
+/// A file of a config which isn't deployed.
+struct SkippedFile {
+    /// the path relative to the config
+    path: PathBuf,
+    /// why the file isn't deployed
+    reason: &'static str,
+}
+
+/// resolves the files of a config and the locations they are deployed to.
+/// With `only`, just the file or directory at this path relative to the config is resolved.
+fn deployed_files(
+    name: &str,
+    config: &Configuration,
+    root: &RootConfiguration,
+    only: Option<&Path>,
+) -> Result<Vec<DeployedFile>> {
+    resolve_files(name, config, root, only).map(|(files, _)| files)
+}
+
+/// splits the files of a config into the ones which have to be deployed with `--changed` and the manifest entries
+/// of the ones which are unchanged since the last deploy. Files whose hash in the repository differs from the manifest,
+/// files which are missing on the system and templates, whose variables may have changed, are deployed.
+fn split_changed(
+    name: &str,
+    files: Vec<DeployedFile>,
+    template_patterns: &GlobSet,
+) -> Result<(Vec<DeployedFile>, Vec<ManifestEntry>)> {
+    let mut previous: HashMap<PathBuf, ManifestEntry> = manifest::read(name)?
+        .map(|manifest| manifest.files)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|entry| (entry.destination.clone(), entry))
+        .collect();
+    let unchanged: Vec<bool> = files
+        .par_iter()
+        .map(|file| {
+            let Some(source_hash) = previous
+                .get(&file.destination)
+                .and_then(|entry| entry.source_hash.as_ref())
+            else {
+                return false;
+            };
+            !template_patterns.is_match(&file.path)
+                && (file.destination.exists() || file.destination.is_symlink())
+                && manifest::hash_file(&file.source).is_ok_and(|hash| hash == *source_hash)
+        })
+        .collect();
+
+    let mut changed = Vec::new();
+    let mut entries = Vec::new();
+    for (file, unchanged) in files.into_iter().zip(unchanged) {
+        match previous.remove(&file.destination) {
+            Some(entry) if unchanged => entries.push(entry),
+            _ => changed.push(file),
+        }
+    }
+    Ok((changed, entries))
+}
+
+/// resolves the files of a config which are deployed, like [`deployed_files`].
+/// With `changed`, only the files which changed since the last deploy are returned, see [`split_changed`].
+fn files_to_deploy(
+    name: &str,
+    config: &Configuration,
+    root: &RootConfiguration,
+    only: Option<&Path>,
+    changed: bool,
+) -> Result<Vec<DeployedFile>> {
+    let files = deployed_files(name, config, root, only)?;
+    if !changed {
+        return Ok(files);
+    }
+    let template_patterns = mapping::template_patterns(name, config)?;
+    Ok(split_changed(name, files, &template_patterns)?.0)
+}
+
+/// resolves the files of a config like [`deployed_files`], and also returns the files of the config
+/// which aren't deployed together with the reason.
+fn resolve_files(
+    name: &str,
+    config: &Configuration,
+    root: &RootConfiguration,
+    only: Option<&Path>,
+) -> Result<(Vec<DeployedFile>, Vec<SkippedFile>)> {
+    let mappings = mapping::resolve(config)?;
+    let exclude_patterns = mapping::exclude_patterns(name, config, root)?;
+    let secret_patterns = mapping::secret_patterns(name, config)?;
 
     // the source directoy
     let config_dir = RelativePathBuf::from(name).to_path(".");
     let dotconfig = config_dir.join(config::CONFIG_PATH);
 
-    let mut exclude_patterns = GlobSetBuilder::new();
-    config.target.exclude.iter().for_each(|pattern| {
-        exclude_patterns.add(Glob::new(pattern.as_str()).unwrap());
-    });
-    target.exclude.iter().for_each(|pattern| {
-        exclude_patterns.add(Glob::new(pattern.as_str()).unwrap());
+    for mapping in &mappings {
+        if !config_dir.join(&mapping.source).exists() {
+            return Err(anyhow!(
+                "'{}' does not exist in config '{}'.",
+                mapping.source.display(),
+                name
+            ));
+        }
+    }
+
+    let mut files = Vec::new();
+    let mut skipped = Vec::new();
+    let follow_symlinks = config.target.follow_symlinks;
+    let paths = match only {
+        Some(only) => {
+            let path = config_dir.join(only);
+            let mut paths = get_paths_under(&path, follow_symlinks)?;
+            paths.extend(get_paths_under(
+                &secret::encrypted_path(&path),
+                follow_symlinks,
+            )?);
+            paths
+        }
+        None => get_paths_in(&config_dir, "**/*", follow_symlinks)?,
+    };
+    for from in paths {
+        if dotconfig == from {
+            continue;
+        }
+
+        let path_rel = from
+            .strip_prefix(&config_dir)
+            .map_err(|_| anyhow!("could not resolve relative path"))?
+            .to_path_buf();
+        // secrets are deployed without their extension
+        let (path_rel, secret) = match secret::decrypted_path(&path_rel) {
+            Some(decrypted) if secret_patterns.is_match(&decrypted) => (decrypted, true),
+            _ => (path_rel, false),
+        };
+        if exclude_patterns.is_match(&path_rel) {
+            skipped.push(SkippedFile {
+                path: path_rel,
+                reason: "excluded",
+            });
+            continue;
+        }
+        // files which aren't covered by any mapping aren't deployed
+        let Some(mapping) = mapping::owner(&mappings, &path_rel) else {
+            skipped.push(SkippedFile {
+                path: path_rel,
+                reason: "not mapped",
+            });
+            continue;
+        };
+
+        let destination = mapping.destination_of(&path_rel);
+        let root = if mapping.is_directory(&config_dir) {
+            mapping.destination.clone()
+        } else {
+            destination
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default()
+        };
+        files.push(DeployedFile {
+            destination,
+            source: from,
+            path: path_rel,
+            root,
+            secret,
+        });
+    }
+
+    Ok((files, skipped))
+}
+
+/// The options of `config deploy` which apply to every deployed config.
+#[derive(Debug, Clone, Copy, Default)]
+struct DeployArgs {
+    /// remove the files of earlier deployments which are no longer part of the config
+    prune: bool,
+    /// back up the files which are overwritten to `.dottor/backups`
+    backup: bool,
+    /// only print what would be done, without changing any files
+    dry_run: bool,
+    /// don't show the progress or the summary of the deployment
+    quiet: bool,
+    /// only deploy the files which changed in the repository since the last deploy
+    changed: bool,
+    /// deploy into target directories which aren't empty although `require_empty` is set
+    force: bool,
+    /// deploy relative links, whatever `symlink_style` the config uses
+    relative: bool,
+}
+
+impl DeployArgs {
+    fn new(matches: &ArgMatches) -> Self {
+        DeployArgs {
+            prune: matches.get_flag("prune"),
+            backup: !matches.get_flag("no-backup"),
+            dry_run: matches.get_flag("dry-run"),
+            quiet: matches.get_flag("quiet"),
+            changed: matches.get_flag("changed"),
+            force: matches.get_flag("force"),
+            relative: matches.get_flag("relative"),
+        }
+    }
+
+    /// the style of the links which are deployed for a config
+    fn symlink_style(&self, config: &Configuration) -> SymlinkStyle {
+        if self.relative {
+            SymlinkStyle::Relative
+        } else {
+            config.target.symlink_style.unwrap_or_default()
+        }
+    }
+}
+
+/// What deploying does to a single file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DeployAction {
+    Create,
+    Overwrite,
+    Unchanged,
+}
+
+/// checks what deploying a file would do, without changing it
+fn deploy_action(
+    file: &DeployedFile,
+    template_patterns: &GlobSet,
+    variables: &HashMap<String, String>,
+    strategy: DeployStrategy,
+    symlink_style: SymlinkStyle,
+    eol: LineEnding,
+) -> Result<DeployAction> {
+    if !file.destination.exists() && !file.destination.is_symlink() {
+        return Ok(DeployAction::Create);
+    }
+    let changed = if file.destination.is_dir() {
+        true
+    } else if file.secret {
+        secret::decrypt(&file.source)? != io::read_for_comparison(&file.destination)?
+    } else if template_patterns.is_match(&file.path) {
+        template_changed(&file.source, &file.destination, variables, eol)?
+    } else if strategy == DeployStrategy::Symlink && !file.source.is_symlink() {
+        !io::points_to(&file.destination, &file.source, symlink_style)
+    } else {
+        let contents = io::read_for_comparison(&file.source)?;
+        *io::convert_line_endings(&contents, eol) != *io::read_for_comparison(&file.destination)?
+    };
+    Ok(if changed {
+        DeployAction::Overwrite
+    } else {
+        DeployAction::Unchanged
+    })
+}
+
+/// prints what deploying a config would do to each of its files.
+/// The lines of a config are printed at once, since configs are deployed in parallel.
+fn print_deploy_plan(
+    name: &str,
+    files: &[DeployedFile],
+    skipped: &[SkippedFile],
+    template_patterns: &GlobSet,
+    variables: &HashMap<String, String>,
+    config: &Configuration,
+    symlink_style: SymlinkStyle,
+) -> Result<()> {
+    let strategy = config.target.strategy.unwrap_or_default();
+    let eol = config.target.eol.unwrap_or_default();
+    let mut lines = Vec::new();
+    for file in files {
+        let path = Path::new(name).join(&file.path);
+        let action = deploy_action(
+            file,
+            template_patterns,
+            variables,
+            strategy,
+            symlink_style,
+            eol,
+        )?;
+        let line = match action {
+            DeployAction::Create => format!(
+                "{} {} -> {}",
+                paint(style::GREEN, "+"),
+                path.display(),
+                file.destination.display()
+            ),
+            DeployAction::Overwrite => format!(
+                "{} {} -> {}",
+                paint(style::YELLOW, "~"),
+                path.display(),
+                file.destination.display()
+            ),
+            DeployAction::Unchanged => format!(
+                "  {}",
+                paint(style::DIM, &format!("{} (unchanged)", path.display()))
+            ),
+        };
+        lines.push(line);
+    }
+    for file in skipped {
+        let path = Path::new(name).join(&file.path);
+        lines.push(format!(
+            "  {}",
+            paint(
+                style::DIM,
+                &format!("{} (skipped, {})", path.display(), file.reason)
+            )
+        ));
+    }
+    if !lines.is_empty() {
+        println!("{}", lines.join("\n"));
+    }
+    Ok(())
+}
+
+/// deploys the files of a config to the system.
+/// With `only`, just the file or directory at this path relative to the config is deployed.
+/// With `--dry-run`, the same checks are made but only the changes are printed.
+fn deploy_single(
+    name: &str,
+    config: &Configuration,
+    root: &RootConfiguration,
+    only: Option<&Path>,
+    args: DeployArgs,
+) -> Result<()> {
+    if skipped(name, config) {
+        return Ok(());
+    }
+    dependencies::check_system(name, config)?;
+    let (files, skipped_files) = resolve_files(name, config, root, only)?;
+    let dirs = kept_dirs(name, config, root, only)?;
+
+    // checks if the target directories already have files in them
+    let require_empty = config
+        .target
+        .current()
+        .and_then(|target| target.require_empty)
+        .unwrap_or(config.target.require_empty);
+    let config_dir = RelativePathBuf::from(name).to_path(".");
+    let mappings = mapping::resolve(config)?;
+    mapping::check_outside_repository(name, &mappings)?;
+    let privileged = config
+        .deploy
+        .as_ref()
+        .is_some_and(|deploy| deploy.privileged);
+    let (pre_hooks, post_hooks) = config.deploy.as_ref().map_or((&[][..], &[][..]), |deploy| {
+        (&deploy.hooks.pre[..], &deploy.hooks.post[..])
     });
-    let exclude_patterns = exclude_patterns.build().unwrap();
+    let hook_env = HookEnv {
+        name,
+        target: mappings.first().map(|mapping| mapping.destination.clone()),
+    };
+    let target_dirs: Vec<&ResolvedMapping> = mappings
+        .iter()
+        .filter(|mapping| mapping.is_directory(&config_dir))
+        .collect();
+    for mapping in &target_dirs {
+        if require_empty && args.force {
+            warn_overwritten(mapping, &files);
+        } else if require_empty {
+            check_dir_null_or_empty(&mapping.destination)?;
+        }
+    }
+
+    let template_patterns = mapping::template_patterns(name, config)?;
+    let permissions = mapping::permissions(name, config)?;
+    let variables = template::variables(root);
+    let strategy = config.target.strategy.unwrap_or_default();
+    let symlink_style = args.symlink_style(config);
+    let eol = config.target.eol.unwrap_or_default();
+    let follow_symlinks = config.target.follow_symlinks;
 
     // copy files to target
-    for from in get_paths_in(&config_dir, "**/*")? {
-        let to = target_path.join(
-            from.strip_prefix(&config_dir)
-                .map_err(|_| anyhow!("could not resolve relative path"))?,
+    let mut manifest = Manifest::default();
+    // files of earlier deployments stay in the manifest as long as they are on the system,
+    // so that they are still removed when the config is undeployed
+    let mut remaining: Vec<ManifestEntry> = manifest::read(name)?
+        .map(|previous| previous.files)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|entry| {
+            (entry.destination.exists() || entry.destination.is_symlink())
+                && !files
+                    .iter()
+                    .any(|file| file.destination == entry.destination)
+        })
+        .collect();
+    // with a path, the other files of the config aren't stale
+    let prune =
+        (args.prune || config.deploy.as_ref().is_some_and(|deploy| deploy.prune)) && only.is_none();
+    // with `--changed`, the files which are unchanged since the last deploy keep their entries
+    let files = if args.changed {
+        let (changed, unchanged) = split_changed(name, files, &template_patterns)?;
+        manifest.files.extend(unchanged);
+        changed
+    } else {
+        files
+    };
+
+    if args.dry_run {
+        for hook in pre_hooks {
+            println!("{} {}", paint(style::YELLOW, "$"), hook);
+        }
+        print_deploy_plan(
+            name,
+            &files,
+            &skipped_files,
+            &template_patterns,
+            &variables,
+            config,
+            symlink_style,
+        )?;
+        for dir in dirs.iter().filter(|dir| !dir.destination.is_dir()) {
+            println!(
+                "{} {}/ -> {}",
+                paint(style::GREEN, "+"),
+                Path::new(name).join(&dir.path).display(),
+                dir.destination.display()
+            );
+        }
+        if prune {
+            prune_files(name, config, root, remaining, None)?;
+        }
+        for hook in post_hooks {
+            println!("{} {}", paint(style::YELLOW, "$"), hook);
+        }
+        return Ok(());
+    }
+
+    let snapshot = args.backup.then(|| backup::Snapshot::new(name));
+    let snapshot = snapshot.as_ref();
+    // a failed deploy puts back the files it already replaced, so that no config is left half deployed
+    // pre hooks can abort the deploy, so they run after everything is checked, but before anything is written
+    hooks::run(pre_hooks, &config_dir, &hook_env)
+        .context("Deploying was aborted because a hook failed")?;
+    let mut transaction = Transaction::new(name, privileged)?;
+    // create targets
+    for mapping in target_dirs {
+        match fs::create_dir_all(&mapping.destination) {
+            Err(error) if privileged && error.kind() == ErrorKind::PermissionDenied => {
+                privileged::create_dir(&mapping.destination)?
+            }
+            result => result.with_context(|| {
+                format!(
+                    "Could not create directory '{}'",
+                    mapping.destination.display()
+                )
+            })?,
+        }
+    }
+    // the files are deployed in parallel, only files which have to be written as root are deployed
+    // one after another afterwards, as that may ask for a password
+    let deploy_file = |file: &DeployedFile| -> Result<(ManifestEntry, bool)> {
+        let changed = if file.secret {
+            deploy_secret(&file.source, &file.destination, snapshot)?
+        } else if template_patterns.is_match(&file.path) {
+            deploy_template(&file.source, &file.destination, &variables, eol, snapshot)?
+        } else if strategy == DeployStrategy::Symlink && !file.source.is_symlink() {
+            let changed = !io::points_to(&file.destination, &file.source, symlink_style);
+            if changed {
+                back_up(snapshot, &file.destination, None)?;
+            }
+            // links of the repository are copied, a link to a link would break if it was changed
+            io::link_file(&file.source, &file.destination, symlink_style)?;
+            changed
+        } else if (eol != LineEnding::Keep && !file.source.is_symlink())
+            // a followed link deploys the file it points to
+            || (follow_symlinks && file.source.is_symlink())
+        {
+            let contents = fs::read(&file.source)
+                .with_context(|| format!("Could not read file '{}'", file.source.display()))?;
+            let contents = io::convert_line_endings(&contents, eol);
+            deploy_contents(&file.source, &file.destination, &contents, snapshot)?
+        } else {
+            deploy_copy(&file.source, &file.destination, snapshot)?
+        };
+        // the mode of a link is the mode of the file it points to, which may be in the repository
+        if let Some(mode) = permissions.mode(&file.path) {
+            if !file.destination.is_symlink() {
+                io::set_mode(&file.destination, mode)?;
+            }
+        }
+        let entry = ManifestEntry::new(&file.destination, &file.root)?.with_source(&file.source)?;
+        if !file.secret {
+            manifest::keep_base(name, &entry)?;
+        }
+        Ok((entry, changed))
+    };
+    let mut updated = 0;
+    let result = (|| -> Result<()> {
+        transaction.record_all(
+            files
+                .iter()
+                .map(|file| (file.destination.as_path(), file.root.as_path())),
+        )?;
+        let bar = progress::bar(files.len(), name);
+        let deployed: Vec<Result<(ManifestEntry, bool)>> = files
+            .par_iter()
+            .map(|file| {
+                bar.set_message(file.path.display().to_string());
+                let deployed = deploy_file(file);
+                bar.inc(1);
+                deployed
+            })
+            .collect();
+        bar.finish_and_clear();
+
+        for (file, deployed) in files.iter().zip(deployed) {
+            let entry = match deployed {
+                Err(error) if privileged && privileged::is_permission_error(&error) => {
+                    updated += 1;
+                    deploy_privileged(
+                        name,
+                        file,
+                        &template_patterns,
+                        &variables,
+                        config,
+                        symlink_style,
+                        permissions.mode(&file.path),
+                    )?
+                    .with_source(&file.source)?
+                }
+                deployed => {
+                    let (entry, changed) = deployed?;
+                    updated += usize::from(changed);
+                    entry
+                }
+            };
+            manifest.files.push(entry);
+        }
+
+        for dir in &dirs {
+            match fs::create_dir_all(&dir.destination) {
+                Err(error) if privileged && error.kind() == ErrorKind::PermissionDenied => {
+                    privileged::create_dir(&dir.destination)?
+                }
+                result => result.with_context(|| {
+                    format!("Could not create directory '{}'", dir.destination.display())
+                })?,
+            }
+        }
+
+        if prune {
+            remaining = prune_files(
+                name,
+                config,
+                root,
+                std::mem::take(&mut remaining),
+                Some(&mut transaction),
+            )?;
+        }
+        Ok(())
+    })();
+
+    if let Err(error) = result {
+        return match transaction.rollback() {
+            Ok(()) => Err(error.context("Deploying failed and was rolled back")),
+            Err(rollback_error) => Err(anyhow!(
+                "{:#}. Restoring the files which were already deployed failed as well: {:#}",
+                error,
+                rollback_error
+            )),
+        };
+    }
+    transaction.commit()?;
+    if !args.quiet {
+        println!(
+            "Deployed config '{}': {} file(s) updated, {} unchanged.",
+            name,
+            updated,
+            manifest.files.len() - updated
         );
+    }
+    manifest.files.extend(remaining);
+    manifest::store(name, &manifest)?;
+
+    hooks::run(post_hooks, &config_dir, &hook_env)
+        .context("The config was deployed, but running its hooks failed")
+}
 
-        if !(exclude_patterns.is_match(&from) || dotconfig == from) {
-            fs::create_dir_all(to.parent().unwrap())?;
-            fs::copy(from, to);
+/// warns about the files which deploying overwrites in a target directory that is required to be empty
+fn warn_overwritten(mapping: &ResolvedMapping, files: &[DeployedFile]) {
+    let existing: Vec<String> = files
+        .iter()
+        .filter(|file| file.destination.starts_with(&mapping.destination))
+        .filter(|file| file.destination.exists() || file.destination.is_symlink())
+        .map(|file| format!("\n  {}", file.destination.display()))
+        .collect();
+    if !existing.is_empty() {
+        style::warn(&format!(
+            "'{}' isn't empty, deploying overwrites these files:{}",
+            mapping.destination.display(),
+            existing.concat()
+        ));
+    }
+}
+
+/// deploys a file which the user isn't allowed to write as root, see [`privileged`].
+/// Without a `mode`, the file gets the mode it has in the repository.
+/// The file may not be readable afterwards, so its manifest entry is created from the written contents.
+fn deploy_privileged(
+    name: &str,
+    file: &DeployedFile,
+    template_patterns: &GlobSet,
+    variables: &HashMap<String, String>,
+    config: &Configuration,
+    symlink_style: SymlinkStyle,
+    mode: Option<u32>,
+) -> Result<ManifestEntry> {
+    progress::suspend(|| {
+        println!(
+            "Deploying '{}' with elevated permissions.",
+            file.destination.display()
+        )
+    });
+    let strategy = config.target.strategy.unwrap_or_default();
+    let contents = if file.secret {
+        secret::decrypt(&file.source)?
+    } else if template_patterns.is_match(&file.path) {
+        render_file(&file.source, variables)?
+    } else if file.source.is_symlink() {
+        let target = fs::read_link(&file.source)
+            .with_context(|| format!("Could not read the link '{}'", file.source.display()))?;
+        privileged::link(&target, &file.destination)?;
+        return ManifestEntry::new(&file.destination, &file.root);
+    } else if strategy == DeployStrategy::Symlink {
+        let source = fs::canonicalize(&file.source)
+            .with_context(|| format!("Could not resolve '{}'", file.source.display()))?;
+        // the parent directory may not exist yet, so relative links are resolved once it does
+        if let Some(parent) = file.destination.parent() {
+            privileged::create_dir(parent)?;
         }
+        let target = io::link_target(&source, &file.destination, symlink_style)?;
+        privileged::link(&target, &file.destination)?;
+        return ManifestEntry::new(&file.destination, &file.root);
+    } else {
+        fs::read(&file.source)
+            .with_context(|| format!("Could not read file '{}'", file.source.display()))?
+    };
+    let contents = if file.secret {
+        contents
+    } else {
+        let eol = config.target.eol.unwrap_or_default();
+        io::convert_line_endings(&contents, eol).into_owned()
+    };
+    let mode = if file.secret {
+        mode.unwrap_or(0o600)
+    } else {
+        mode.or_else(|| io::mode(&file.source)).unwrap_or(0o644)
+    };
+    privileged::write(name, &file.destination, &contents, mode)?;
+    if !file.secret {
+        manifest::store_base(name, &contents)?;
     }
+    Ok(ManifestEntry::with_contents(
+        &file.destination,
+        &file.root,
+        &contents,
+    ))
+}
+
+/// renders a templated file of the repository. Binary files are returned as they are.
+fn render_file(template: &Path, variables: &HashMap<String, String>) -> Result<Vec<u8>> {
+    let contents = fs::read(template)
+        .with_context(|| format!("Could not read file '{}'", template.display()))?;
+    match as_text(&contents) {
+        Some(source) => Ok(template::render(source, variables)
+            .with_context(|| format!("Could not render template '{}'", template.display()))?
+            .into_bytes()),
+        None => Ok(contents),
+    }
+}
 
+/// removes files which were deployed earlier but are no longer part of the config.
+/// Files which are excluded, outside of the current targets or modified since they were deployed are kept.
+/// Without a transaction, the files are only listed. Returns the files which were kept.
+fn prune_files(
+    name: &str,
+    config: &Configuration,
+    root: &RootConfiguration,
+    stale: Vec<ManifestEntry>,
+    mut transaction: Option<&mut Transaction>,
+) -> Result<Vec<ManifestEntry>> {
+    let mappings = mapping::resolve(config)?;
+    let exclude_patterns = mapping::exclude_patterns(name, config, root)?;
+    let mut kept = Vec::new();
+    for entry in stale {
+        // the path relative to the config, to check it against the excludes
+        let path_rel = mappings
+            .iter()
+            .find_map(|mapping| mapping.source_of(&entry.destination));
+        let Some(path_rel) = path_rel.filter(|path| !exclude_patterns.is_match(path)) else {
+            kept.push(entry);
+            continue;
+        };
+        if entry.is_modified() {
+            style::warn(&format!(
+                "Not pruning '{}', it was modified since it was deployed.",
+                entry.destination.display()
+            ));
+            kept.push(entry);
+            continue;
+        }
+
+        if let Some(transaction) = transaction.as_deref_mut() {
+            transaction.record(&entry.destination, &entry.root)?;
+            io::retry_locked(|| fs::remove_file(&entry.destination))
+                .with_context(|| format!("Could not remove '{}'", entry.destination.display()))?;
+            if let Some(parent) = entry.destination.parent() {
+                remove_empty_dirs(parent, &entry.root)?;
+            }
+        }
+        progress::suspend(|| {
+            println!(
+                "{} {}",
+                paint(style::RED, "-"),
+                Path::new(name).join(path_rel).display()
+            )
+        });
+    }
+    Ok(kept)
+}
+
+/// removes one or all deployed configs from the local system
+fn config_undeploy(matches: &ArgMatches, structure: Structure) -> Result<()> {
+    let force = matches.get_flag("force");
+    let selected = select_configs(matches, &structure)?;
+
+    if selected.len() == 1 {
+        return undeploy_single(
+            &selected[0],
+            &structure.configs[&selected[0]],
+            &structure.root,
+            force,
+        );
+    }
+    // configs are removed before the configs they depend on. Missing or circular dependencies
+    // don't stop anything from being removed, the configs are then removed in alphabetical order.
+    let order = dependencies::deploy_order(&structure.configs, &selected)
+        .map(|order| order.into_iter().rev().collect())
+        .unwrap_or(selected);
+    let mut failed = Vec::new();
+    for name in order {
+        if let Err(error) =
+            undeploy_single(&name, &structure.configs[&name], &structure.root, force)
+        {
+            eprintln!("Could not undeploy config '{}': {:#}", name, error);
+            failed.push(name);
+        }
+    }
+    if !failed.is_empty() {
+        failed.sort();
+        return Err(anyhow!(
+            "{} config(s) could not be undeployed: {}",
+            failed.len(),
+            failed.join(", ")
+        ));
+    }
     Ok(())
 }
 
-fn get_paths_in(dir: &Path, pattern: &str) -> Result<Vec<PathBuf>> {
-    let glob = Glob::new(dir.join(pattern).to_str().unwrap())
-        .unwrap()
-        .compile_matcher();
+/// removes the files of a config from the target.
+/// Only the files listed in the deploy manifest are removed, other files in the target directory are left untouched.
+/// Configs without a manifest, which aren't deployed or were deployed before manifests existed, can't be undeployed.
+/// Configs which are disabled on this machine are still undeployed if they have a manifest.
+fn undeploy_single(
+    name: &str,
+    config: &Configuration,
+    root: &RootConfiguration,
+    force: bool,
+) -> Result<()> {
+    let manifest = manifest::read(name)?;
+    if manifest.is_none() && skipped(name, config) {
+        return Ok(());
+    }
+    // without a manifest, there is no telling which files at the target were placed there by dottor
+    let Some(manifest) = manifest else {
+        return Err(anyhow!(
+            "Config '{}' has no deploy manifest, it is not deployed or was deployed by an older version of dottor. Redeploy it first to undeploy it.",
+            name
+        ));
+    };
+    let files: Vec<ManifestEntry> = manifest
+        .files
+        .into_iter()
+        .filter(|file| file.destination.is_file() || file.destination.is_symlink())
+        .collect();
+
+    if files.is_empty() {
+        println!("Config '{}' is not deployed.", name);
+        manifest::remove(name)?;
+        return Ok(());
+    }
+
+    for file in files.iter().filter(|file| file.is_modified()) {
+        style::warn(&format!(
+            "'{}' was modified since it was deployed.",
+            file.destination.display()
+        ));
+    }
 
-    return list_dir(&glob, dir);
+    if !force {
+        for file in &files {
+            println!("{} {}", paint(style::RED, "-"), file.destination.display());
+        }
+        if !prompt_bool(
+            &format!(
+                "{} file(s) of config '{}' will be removed from the system.",
+                files.len(),
+                name
+            ),
+            false,
+        ) {
+            return Ok(());
+        }
+    }
 
-    fn list_dir(glob: &GlobMatcher, dir: &Path) -> Result<Vec<PathBuf>> {
-        let mut paths = Vec::new();
+    for file in &files {
+        io::retry_locked(|| fs::remove_file(&file.destination))
+            .with_context(|| format!("Could not remove '{}'", file.destination.display()))?;
+        if let Some(parent) = file.destination.parent() {
+            remove_empty_dirs(parent, &file.root)?;
+        }
+    }
+    // kept directories are only removed if nothing was put into them since they were deployed
+    for dir in kept_dirs(name, config, root, None).unwrap_or_default() {
+        if dir.destination.is_dir() {
+            remove_empty_dirs(&dir.destination, &dir.root)?;
+        }
+    }
 
-        for value in WalkDir::new(dir) {
-            match value {
-                Ok(value) => {
-                    let path = value.path();
+    manifest::remove(name)
+}
 
-                    if path.is_file() && glob.is_match(path) {
-                        paths.push(path.into());
-                    }
-                }
-                Err(_) => return Err(anyhow!("walkdir error")),
-            }
+/// checks if a config is disabled on this machine and tells the user that it is skipped
+fn skipped(name: &str, config: &Configuration) -> bool {
+    match config.skip_reason(config::hostname().as_deref()) {
+        Some(reason) => {
+            println!("Config '{}' is {}, skipping.", name, reason);
+            true
         }
+        None => false,
+    }
+}
+
+/// separates the configs which aren't used on this machine from the selection.
+/// Returns the used configs and the skipped configs together with the reason.
+fn partition_skipped(names: Vec<String>, structure: &Structure) -> (Vec<String>, Vec<String>) {
+    let hostname = config::hostname();
+    let mut used = Vec::new();
+    let mut skipped = Vec::new();
+    for name in names {
+        match structure.configs[&name].skip_reason(hostname.as_deref()) {
+            Some(reason) => skipped.push(format!("{} ({})", name, reason)),
+            None => used.push(name),
+        }
+    }
+    (used, skipped)
+}
+
+/// prints the configs which were skipped during a run over all configs
+fn print_skipped(skipped: &[String]) {
+    if !skipped.is_empty() {
+        println!(
+            "Skipped {} config(s): {}",
+            skipped.len(),
+            skipped.join(", ")
+        );
+    }
+}
+
+/// renders a templated file and writes the result to the target.
+/// Binary files are copied without modification.
+fn deploy_template(
+    from: &Path,
+    to: &Path,
+    variables: &HashMap<String, String>,
+    eol: LineEnding,
+    snapshot: Option<&backup::Snapshot>,
+) -> Result<bool> {
+    let contents = fs::read(from)?;
+    match as_text(&contents) {
+        Some(source) => {
+            let rendered = template::render(source, variables)
+                .with_context(|| format!("Could not render template '{}'", from.display()))?;
+            let rendered = io::convert_line_endings(rendered.as_bytes(), eol);
+            deploy_contents(from, to, &rendered, snapshot)
+        }
+        None => deploy_copy(from, to, snapshot),
+    }
+}
 
-        Ok(paths)
+/// collects the files in a directory, or the path itself if it is a single file
+fn get_paths_under(path: &Path, follow_symlinks: bool) -> Result<Vec<PathBuf>> {
+    if path.is_dir() && (follow_symlinks || !path.is_symlink()) {
+        get_paths_in(path, "**/*", follow_symlinks)
+    } else if path.exists() || path.is_symlink() {
+        Ok(vec![path.to_path_buf()])
+    } else {
+        Ok(Vec::new())
     }
 }
+
+/// removes the paths which are ignored by the `.gitignore` files in the directory or its subdirectories
+fn filter_gitignored(
+    dir: &Path,
+    paths: Vec<PathBuf>,
+    follow_symlinks: bool,
+) -> Result<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(paths);
+    }
+    // only the .gitignore files inside of the directory are used, not the ones of the user or a surrounding repository.
+    // Unreadable files are reported by the regular walk.
+    let kept: HashSet<PathBuf> = ignore::WalkBuilder::new(dir)
+        .standard_filters(false)
+        .git_ignore(true)
+        .require_git(false)
+        .parents(false)
+        .follow_links(follow_symlinks)
+        .build()
+        .flatten()
+        .map(|entry| entry.into_path())
+        .collect();
+    Ok(paths
+        .into_iter()
+        .filter(|path| kept.contains(path))
+        .collect())
+}
+
+/// lists all files in the directory whose path relative to the directory matches the pattern
+fn get_paths_in(dir: &Path, pattern: &str, follow_symlinks: bool) -> Result<Vec<PathBuf>> {
+    let glob = Glob::new(pattern)
+        .with_context(|| format!("Invalid glob '{}'", pattern))?
+        .compile_matcher();
+
+    let mut paths = Vec::new();
+    for value in WalkDir::new(dir).follow_links(follow_symlinks) {
+        let value = match value {
+            Ok(value) => value,
+            // following a link to one of its parent directories would never end
+            Err(error) if error.loop_ancestor().is_some() => {
+                style::warn(&format!(
+                    "Skipping '{}', it links to its parent directory '{}'.",
+                    error.path().unwrap_or(dir).display(),
+                    error.loop_ancestor().unwrap_or(dir).display()
+                ));
+                continue;
+            }
+            Err(error) if error.path().is_some_and(Path::is_symlink) => {
+                style::warn(&format!(
+                    "Skipping '{}', the file it links to doesn't exist.",
+                    error.path().unwrap_or(dir).display()
+                ));
+                continue;
+            }
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("Could not read directory '{}'", dir.display()))
+            }
+        };
+        let path = value.path();
+
+        // without following them, links are collected as they are, even if they point to a directory
+        let is_file = if follow_symlinks {
+            path.is_file()
+        } else {
+            value.file_type().is_file() || value.path_is_symlink()
+        };
+        if is_file && path.strip_prefix(dir).is_ok_and(|rel| glob.is_match(rel)) {
+            paths.push(path.into());
+        }
+    }
+
+    Ok(paths)
+}
@@ -0,0 +1,238 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use relative_path::RelativePathBuf;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use toml::value::Datetime;
+
+use crate::{
+    io::{as_text, read_for_comparison, read_to_string, write},
+    time::Utc,
+};
+
+/// The directory in the repository in which dottor keeps the state of this machine.
+/// It is ignored by git and never treated as a config.
+pub const STATE_DIR: &str = ".dottor";
+
+/// The files a config placed on the system when it was last deployed.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ManifestEntry {
+    /// the location on the system the file was deployed to
+    pub destination: PathBuf,
+    /// the directory the file was deployed into, empty parent directories are only removed up to here
+    pub root: PathBuf,
+    /// the sha256 hash of the deployed contents
+    pub hash: String,
+    /// when the file was deployed, missing in manifests of older versions of dottor
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployed_at: Option<Datetime>,
+    /// the sha256 hash of the file in the repository when it was deployed, used by `deploy --changed`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_hash: Option<String>,
+}
+
+impl ManifestEntry {
+    /// Records a file which has just been deployed.
+    /// # Errors
+    /// Returns an error if the deployed file can't be read.
+    pub fn new(destination: &Path, root: &Path) -> Result<Self> {
+        Ok(ManifestEntry {
+            destination: destination.to_path_buf(),
+            root: root.to_path_buf(),
+            hash: hash_file(destination)?,
+            deployed_at: Utc::now().to_rfc3339().parse().ok(),
+            source_hash: None,
+        })
+    }
+
+    /// Records a file which has just been deployed with the given contents, without reading it again.
+    pub fn with_contents(destination: &Path, root: &Path, contents: &[u8]) -> Self {
+        ManifestEntry {
+            destination: destination.to_path_buf(),
+            root: root.to_path_buf(),
+            hash: hash(contents),
+            deployed_at: Utc::now().to_rfc3339().parse().ok(),
+            source_hash: None,
+        }
+    }
+
+    /// Records the file in the repository the deployed file was created from.
+    /// # Errors
+    /// Returns an error if the file in the repository can't be read.
+    pub fn with_source(mut self, source: &Path) -> Result<Self> {
+        self.source_hash = Some(hash_file(source)?);
+        Ok(self)
+    }
+
+    /// Checks if the file on the system was changed since it was deployed.
+    /// Files which can't be read count as modified.
+    pub fn is_modified(&self) -> bool {
+        hash_file(&self.destination).map_or(true, |hash| hash != self.hash)
+    }
+}
+
+/// Returns the directory in which the deployed contents of the text files of a config are kept by their hash.
+/// They are the common base when a file was changed in the repository as well as on the system.
+fn base_dir(name: &str) -> PathBuf {
+    RelativePathBuf::from(STATE_DIR)
+        .join("base")
+        .join(name)
+        .to_path(".")
+}
+
+/// Keeps the deployed contents of a file as the base for merging changes, unless they are already kept.
+/// Binary files and links aren't kept, and secrets must not be passed, as they would be stored in plain text.
+/// # Errors
+/// Returns an error if the deployed file can't be read or its contents can't be written.
+pub fn keep_base(name: &str, entry: &ManifestEntry) -> Result<()> {
+    let path = base_dir(name).join(&entry.hash);
+    if path.is_file() || entry.destination.is_symlink() {
+        return Ok(());
+    }
+    store_base(name, &fs::read(&entry.destination)?)
+}
+
+/// Keeps contents which were just deployed as the base for merging changes, see [`keep_base`].
+/// # Errors
+/// Returns an error if the contents can't be written.
+pub fn store_base(name: &str, contents: &[u8]) -> Result<()> {
+    if as_text(contents).is_none() {
+        return Ok(());
+    }
+    // the kept contents may be secret, so the state directory must be ignored before they are written
+    create_state_dir()?;
+    let dir = base_dir(name);
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Could not create directory '{}'", dir.display()))?;
+    write(&dir.join(hash(contents)), contents)
+}
+
+/// Reads the deployed contents of a file with the given hash, if they were kept.
+pub fn read_base(name: &str, hash: &str) -> Option<String> {
+    fs::read_to_string(base_dir(name).join(hash)).ok()
+}
+
+/// removes the kept contents which no file of the manifest was deployed with anymore
+fn prune_bases(name: &str, manifest: &Manifest) -> Result<()> {
+    let dir = base_dir(name);
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(());
+    };
+    for entry in entries.flatten() {
+        let hash = entry.file_name();
+        if !manifest.files.iter().any(|file| *file.hash == *hash) {
+            fs::remove_file(entry.path())
+                .with_context(|| format!("Could not remove '{}'", entry.path().display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the location of the manifest of a config.
+pub fn path(name: &str) -> PathBuf {
+    RelativePathBuf::from(STATE_DIR)
+        .join(format!("{}.toml", name))
+        .to_path(".")
+}
+
+/// Reads the manifest of a config.
+/// Returns `None` if the config was never deployed, or deployed by a version of dottor without manifests.
+/// # Errors
+/// Returns an error if the manifest exists but can't be read.
+pub fn read(name: &str) -> Result<Option<Manifest>> {
+    let path = path(name);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let source = read_to_string(&path)?;
+    let manifest = toml::from_str(&source)
+        .with_context(|| format!("Could not parse deploy manifest '{}'", path.display()))?;
+    Ok(Some(manifest))
+}
+
+/// Creates the state directory if it doesn't exist yet.
+pub fn create_state_dir() -> Result<()> {
+    let state_dir = RelativePathBuf::from(STATE_DIR).to_path(".");
+    fs::create_dir_all(&state_dir)
+        .with_context(|| format!("Could not create directory '{}'", state_dir.display()))?;
+    // the state belongs to this machine, so it must not end up in the repository
+    let gitignore = state_dir.join(".gitignore");
+    if !gitignore.is_file() {
+        write(&gitignore, b"*\n")?;
+    }
+    Ok(())
+}
+
+/// Writes the manifest of a config, replacing the previous one.
+pub fn store(name: &str, manifest: &Manifest) -> Result<()> {
+    create_state_dir()?;
+    write(&path(name), toml::to_string_pretty(manifest)?.as_bytes())?;
+    prune_bases(name, manifest)
+}
+
+/// Removes the manifest of a config after it was undeployed.
+pub fn remove(name: &str) -> Result<()> {
+    let path = path(name);
+    if path.is_file() {
+        fs::remove_file(&path).with_context(|| format!("Could not remove '{}'", path.display()))?;
+    }
+    let base_dir = base_dir(name);
+    if base_dir.is_dir() {
+        fs::remove_dir_all(&base_dir)
+            .with_context(|| format!("Could not remove '{}'", base_dir.display()))?;
+    }
+    Ok(())
+}
+
+/// Moves the manifest of a renamed config.
+pub fn rename(old: &str, new: &str) -> Result<()> {
+    let from = path(old);
+    if from.is_file() {
+        fs::rename(&from, path(new))
+            .with_context(|| format!("Could not move '{}'", from.display()))?;
+    }
+    let from = base_dir(old);
+    if from.is_dir() {
+        fs::rename(&from, base_dir(new))
+            .with_context(|| format!("Could not move '{}'", from.display()))?;
+    }
+    Ok(())
+}
+
+/// Returns the hashes of the files recorded in the manifest of a config by their location on the system.
+/// The map is empty if the config has no manifest.
+/// # Errors
+/// Returns an error if the manifest exists but can't be read.
+pub fn hashes(name: &str) -> Result<HashMap<PathBuf, String>> {
+    Ok(read(name)?
+        .map(|manifest| {
+            manifest
+                .files
+                .into_iter()
+                .map(|entry| (entry.destination, entry.hash))
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Computes the sha256 hash of a file as a hex string.
+/// For symlinks, the path they point to is hashed.
+pub fn hash_file(file: &Path) -> Result<String> {
+    Ok(hash(&read_for_comparison(file)?))
+}
+
+/// Computes the sha256 hash of the contents of a file as a hex string.
+pub fn hash(contents: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(contents))
+}
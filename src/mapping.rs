@@ -0,0 +1,462 @@
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use globset::{Glob, GlobMatcher, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use relative_path::RelativePathBuf;
+
+use crate::{
+    config::{self, Configuration, RootConfiguration},
+    io::{canonicalize_lenient, expand_path},
+};
+
+/// A file or directory of a config together with the location it is deployed to.
+#[derive(Debug)]
+pub struct ResolvedMapping {
+    /// the path relative to the config. An empty path stands for the whole config.
+    pub source: PathBuf,
+    /// the expanded location on the system
+    pub destination: PathBuf,
+    /// renames the files inside of the mapping
+    pub rename_rules: RenameRules,
+}
+
+impl ResolvedMapping {
+    /// Returns the location on the system of a file of the config which belongs to this mapping.
+    pub fn destination_of(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(&self.source) {
+            Ok(rest) if rest.as_os_str().is_empty() => self.destination.clone(),
+            Ok(rest) => self.destination.join(self.rename_rules.to_system(rest)),
+            Err(_) => self.destination.clone(),
+        }
+    }
+
+    /// Returns the path relative to the config of a file on the system, if it lies inside of this mapping.
+    pub fn source_of(&self, path: &Path) -> Option<PathBuf> {
+        let rest = path.strip_prefix(&self.destination).ok()?;
+        Some(self.source.join(self.rename_rules.to_repo(rest)))
+    }
+
+    /// Checks if the mapping deploys a directory rather than a single file.
+    pub fn is_directory(&self, config_dir: &Path) -> bool {
+        self.source.as_os_str().is_empty()
+            || config_dir.join(&self.source).is_dir()
+            || self.destination.is_dir()
+    }
+}
+
+/// Prefixes of file and directory names which are replaced when deploying, from `target.rename_rules`.
+/// With `"dot_" = "."`, `dot_config/dot_bashrc` in the repository is deployed as `.config/.bashrc`,
+/// and pulling renames the files the other way around.
+#[derive(Debug, Clone, Default)]
+pub struct RenameRules {
+    /// the prefixes in the repository and on the system
+    rules: Vec<(String, String)>,
+}
+
+impl RenameRules {
+    pub fn new(rules: &BTreeMap<String, String>) -> Self {
+        let rules = rules
+            .iter()
+            .filter(|(repo, system)| !repo.is_empty() && !system.is_empty())
+            .map(|(repo, system)| (repo.clone(), system.clone()))
+            .collect();
+        RenameRules { rules }
+    }
+
+    /// Renames a path inside of the config to its name on the system.
+    pub fn to_system(&self, path: &Path) -> PathBuf {
+        self.rename(path, false)
+    }
+
+    /// Renames a path on the system to its name in the config.
+    pub fn to_repo(&self, path: &Path) -> PathBuf {
+        self.rename(path, true)
+    }
+
+    /// replaces the longest matching prefix of every component, from the system to the repository if `reverse` is true
+    fn rename(&self, path: &Path, reverse: bool) -> PathBuf {
+        if self.rules.is_empty() {
+            return path.to_path_buf();
+        }
+        path.components()
+            .map(|component| {
+                let renamed = match component {
+                    Component::Normal(name) => name.to_str().and_then(|name| {
+                        self.rules
+                            .iter()
+                            .map(|(repo, system)| {
+                                if reverse {
+                                    (system, repo)
+                                } else {
+                                    (repo, system)
+                                }
+                            })
+                            .filter(|(from, _)| name.starts_with(from.as_str()))
+                            .max_by_key(|(from, _)| from.len())
+                            .map(|(from, to)| format!("{}{}", to, &name[from.len()..]))
+                    }),
+                    _ => None,
+                };
+                renamed.map_or_else(|| component.as_os_str().to_os_string(), Into::into)
+            })
+            .collect()
+    }
+}
+
+/// Collects the locations the files of a config are deployed to on the current operating system and host.
+/// The `directory` target deploys the whole config, the `file` target deploys the file `source`, or the file
+/// with the same name as the target, and every entry of `mappings`, `target.files` and `deploy.files` deploys a single file or directory to its own location.
+/// # Errors
+/// Returns an error if the operating system isn't supported, both `directory` and `file` are set,
+/// no target is set at all or a path can't be expanded.
+pub fn resolve(config: &Configuration) -> Result<Vec<ResolvedMapping>> {
+    let target = config.host_target(config::hostname().as_deref())?;
+    let rename_rules = RenameRules::new(&config.target.rename_rules);
+
+    let directory = target.directory.as_ref().filter(|d| !d.is_empty());
+    let file = target.file.as_ref().filter(|f| !f.is_empty());
+
+    let mut mappings = Vec::new();
+    match (directory, file) {
+        (Some(_), Some(_)) => {
+            return Err(anyhow!("Cannot use both 'directory' and 'file' targets."));
+        }
+        (Some(directory), None) => mappings.push(ResolvedMapping {
+            source: PathBuf::new(),
+            destination: expand_path(directory)?,
+            rename_rules: rename_rules.clone(),
+        }),
+        (None, Some(file)) => {
+            let destination = expand_path(file)?;
+            let source = match target.source.as_ref().filter(|s| !s.is_empty()) {
+                Some(source) => PathBuf::from(source),
+                None => rename_rules.to_repo(Path::new(
+                    destination
+                        .file_name()
+                        .ok_or_else(|| anyhow!("Target file '{}' has no file name.", file))?,
+                )),
+            };
+            mappings.push(ResolvedMapping {
+                source,
+                destination,
+                rename_rules: rename_rules.clone(),
+            });
+        }
+        (None, None) => {}
+    }
+
+    for mapping in &target.mappings {
+        mappings.push(ResolvedMapping {
+            source: PathBuf::from(&mapping.source),
+            destination: expand_path(&mapping.destination)?,
+            rename_rules: rename_rules.clone(),
+        });
+    }
+    for (source, destination) in config.files() {
+        mappings.push(ResolvedMapping {
+            source: PathBuf::from(source),
+            destination: expand_path(destination)?,
+            rename_rules: rename_rules.clone(),
+        });
+    }
+
+    if mappings.is_empty() {
+        return Err(anyhow!(
+            "'file', 'directory', 'mappings', 'target.files' or 'deploy.files' must be set"
+        ));
+    }
+    Ok(mappings)
+}
+
+/// Ensures that no target of a config lies inside the repository and that the repository doesn't
+/// lie inside a target, as deploying or pulling would then copy files into themselves.
+/// # Errors
+/// Returns an error naming the target if it overlaps with the repository.
+pub fn check_outside_repository(name: &str, mappings: &[ResolvedMapping]) -> Result<()> {
+    let repository = canonicalize_lenient(Path::new("."))?;
+    for mapping in mappings {
+        let destination = canonicalize_lenient(&mapping.destination)?;
+        if destination.starts_with(&repository) {
+            return Err(anyhow!(
+                "The target '{}' of config '{}' is inside the dotfiles repository.",
+                mapping.destination.display(),
+                name
+            ));
+        }
+        if repository.starts_with(&destination) {
+            return Err(anyhow!(
+                "The target '{}' of config '{}' contains the dotfiles repository.",
+                mapping.destination.display(),
+                name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Finds the mapping a file of the config belongs to.
+/// If the file is covered by several mappings, the one with the most specific source wins.
+pub fn owner<'a>(mappings: &'a [ResolvedMapping], path: &Path) -> Option<&'a ResolvedMapping> {
+    mappings
+        .iter()
+        .filter(|mapping| path.starts_with(&mapping.source))
+        .max_by_key(|mapping| mapping.source.components().count())
+}
+
+/// The name of the files with gitignore patterns of excluded files, in the repository and in each config.
+pub const IGNORE_FILE: &str = ".dottorignore";
+
+/// The exclude globs which apply to the files of a config.
+/// Files are excluded if they match a glob of the root configuration, the config or the target of
+/// the current operating system and host, or a pattern of the `.dottorignore` files of the repository
+/// and the config. An exclude can't be overridden by a more specific one.
+/// If the config has `include` globs, files which don't match any of them are excluded as well.
+pub struct Excludes<'a> {
+    root: &'a GlobSet,
+    config: GlobSet,
+    include: Option<GlobSet>,
+    /// the name of the config, the patterns of the repository are relative to its root
+    name: PathBuf,
+    root_ignore: Gitignore,
+    config_ignore: Gitignore,
+}
+
+impl Excludes<'_> {
+    /// Checks if a path relative to the config is excluded.
+    pub fn is_match(&self, path: &Path) -> bool {
+        self.is_ignored(path, false)
+            || self
+                .include
+                .as_ref()
+                .is_some_and(|include| !include.is_match(path))
+    }
+
+    /// Checks if a directory relative to the config is excluded.
+    /// Include globs are ignored, as they might match files inside of the directory.
+    pub fn is_dir_match(&self, path: &Path) -> bool {
+        self.is_ignored(path, true)
+    }
+
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        // like the deploy walk, which skips excluded directories, the files inside of them are excluded as well
+        path == Path::new(IGNORE_FILE)
+            || path
+                .ancestors()
+                .filter(|path| !path.as_os_str().is_empty())
+                .any(|path| self.root.is_match(path) || self.config.is_match(path))
+            || self
+                .config_ignore
+                .matched_path_or_any_parents(path, is_dir)
+                .is_ignore()
+            || self
+                .root_ignore
+                .matched_path_or_any_parents(self.name.join(path), is_dir)
+                .is_ignore()
+    }
+}
+
+/// Reads the patterns of a `.dottorignore` file, if it exists.
+/// Paths are matched relative to the directory of the file.
+fn read_ignore_file(file: &Path) -> Result<Gitignore> {
+    // the paths are already relative, so the root must not be stripped from them
+    let mut builder = GitignoreBuilder::new(".");
+    if file.is_file() {
+        if let Some(error) = builder.add(file) {
+            return Err(anyhow!(
+                "Invalid pattern in '{}': {}",
+                file.display(),
+                error
+            ));
+        }
+    }
+    Ok(builder.build()?)
+}
+
+/// Builds the exclude globs of a config for the current operating system and host.
+/// The globs are matched against paths relative to the config.
+/// # Errors
+/// Returns an error naming the pattern and the config if a glob is invalid.
+pub fn exclude_patterns<'a>(
+    name: &str,
+    config: &Configuration,
+    root: &'a RootConfiguration,
+) -> Result<Excludes<'a>> {
+    let mut exclude_patterns = GlobSetBuilder::new();
+    add_globs(
+        &mut exclude_patterns,
+        name,
+        "target.exclude",
+        &config.target.exclude,
+    )?;
+    if let Ok(target) = config.host_target(config::hostname().as_deref()) {
+        add_globs(
+            &mut exclude_patterns,
+            name,
+            &format!("target.{}.exclude", config.target.current_name()),
+            &target.exclude,
+        )?;
+    }
+    let include = if config.target.include.is_empty() {
+        None
+    } else {
+        let mut include_patterns = GlobSetBuilder::new();
+        add_globs(
+            &mut include_patterns,
+            name,
+            "target.include",
+            &config.target.include,
+        )?;
+        Some(include_patterns.build()?)
+    };
+    let config_dir = RelativePathBuf::from(name).to_path(".");
+    Ok(Excludes {
+        root: &root.exclude_patterns,
+        config: exclude_patterns.build()?,
+        include,
+        name: PathBuf::from(name),
+        root_ignore: read_ignore_file(Path::new(IGNORE_FILE))?,
+        config_ignore: read_ignore_file(&config_dir.join(IGNORE_FILE))?,
+    })
+}
+
+/// Builds the template globs of a config.
+/// # Errors
+/// Returns an error naming the pattern and the config if a glob is invalid.
+pub fn template_patterns(name: &str, config: &Configuration) -> Result<GlobSet> {
+    let mut template_patterns = GlobSetBuilder::new();
+    add_globs(
+        &mut template_patterns,
+        name,
+        "target.templates",
+        &config.target.templates,
+    )?;
+    Ok(template_patterns.build()?)
+}
+
+/// Builds the globs of the files of a config which are stored encrypted.
+/// The globs are matched against the decrypted paths, i.e. without the `.age` extension.
+/// # Errors
+/// Returns an error naming the pattern and the config if a glob is invalid.
+pub fn secret_patterns(name: &str, config: &Configuration) -> Result<GlobSet> {
+    let mut secret_patterns = GlobSetBuilder::new();
+    add_globs(
+        &mut secret_patterns,
+        name,
+        "target.secrets",
+        &config.target.secrets,
+    )?;
+    Ok(secret_patterns.build()?)
+}
+
+/// The modes of the files of a config from its `[deploy.permissions]` table.
+pub struct Permissions {
+    /// the longest patterns first, as they are usually the most specific ones
+    modes: Vec<(GlobMatcher, u32)>,
+}
+
+impl Permissions {
+    /// Returns the mode of a path relative to the config, if it has one.
+    /// If several patterns match, the longest one wins.
+    pub fn mode(&self, path: &Path) -> Option<u32> {
+        self.modes
+            .iter()
+            .find(|(matcher, _)| matcher.is_match(path))
+            .map(|(_, mode)| *mode)
+    }
+}
+
+/// Builds the permission globs of a config.
+/// # Errors
+/// Returns an error naming the pattern and the config if a glob or a mode is invalid.
+pub fn permissions(name: &str, config: &Configuration) -> Result<Permissions> {
+    let mut patterns: Vec<(&String, &String)> = config
+        .deploy
+        .iter()
+        .flat_map(|deploy| &deploy.permissions)
+        .collect();
+    patterns.sort_by_key(|(pattern, _)| std::cmp::Reverse(pattern.len()));
+
+    let mut modes = Vec::new();
+    for (pattern, mode) in patterns {
+        let glob = Glob::new(pattern).map_err(|error| {
+            anyhow!(
+                "Invalid glob '{}' in 'deploy.permissions' of config '{}': {}",
+                pattern,
+                name,
+                error.kind()
+            )
+        })?;
+        let mode = config::parse_mode(mode).ok_or_else(|| {
+            anyhow!(
+                "Invalid mode '{}' for '{}' in 'deploy.permissions' of config '{}'.",
+                mode,
+                pattern,
+                name
+            )
+        })?;
+        modes.push((glob.compile_matcher(), mode));
+    }
+    Ok(Permissions { modes })
+}
+
+/// compiles the patterns of a field of a config and adds them to the builder
+fn add_globs(
+    builder: &mut GlobSetBuilder,
+    name: &str,
+    field: &str,
+    patterns: &[String],
+) -> Result<()> {
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|error| {
+            anyhow!(
+                "Invalid glob '{}' in '{}' of config '{}': {}",
+                pattern,
+                field,
+                name,
+                error.kind()
+            )
+        })?;
+        builder.add(glob);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(target: &str) -> Configuration {
+        toml::from_str(&format!("[target]\n{target}")).unwrap()
+    }
+
+    #[test]
+    fn invalid_globs_name_the_field_and_config() {
+        let root = RootConfiguration::default();
+        let error = exclude_patterns("app", &config("exclude = ['a[']"), &root)
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Invalid glob 'a[' in 'target.exclude' of config 'app': unclosed character class; missing ']'"
+        );
+        let error = template_patterns("app", &config("templates = ['{a']")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid glob '{a' in 'target.templates' of config 'app': unclosed alternate group; missing '}' (maybe escape '{' with '[{]'?)"
+        );
+        assert!(secret_patterns("app", &config("secrets = ['**/[']")).is_err());
+    }
+
+    #[test]
+    fn valid_globs_match_paths_of_the_config() {
+        let templates =
+            template_patterns("app", &config("templates = ['*.tmpl', 'conf/**']")).unwrap();
+        assert!(templates.is_match("init.tmpl"));
+        assert!(templates.is_match("conf/nested/file"));
+        assert!(!templates.is_match("init.lua"));
+    }
+}
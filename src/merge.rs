@@ -0,0 +1,177 @@
+use std::ops::Range;
+
+use similar::{DiffTag, TextDiff};
+
+/// The lines of one side which replace a range of lines of the base.
+struct Edit<'a> {
+    base: Range<usize>,
+    lines: &'a [&'a str],
+}
+
+/// Merges the changes from `base` to `ours` and from `base` to `theirs` line by line, like `git merge-file`.
+/// Changes which are next to each other or overlap are only merged if both sides made the same change.
+/// Returns `None` if the sides conflict.
+pub fn merge(base: &str, ours: &str, theirs: &str) -> Option<String> {
+    merge_lines(base, ours, theirs, false)
+}
+
+/// Merges like [`merge`], but puts both versions of conflicting lines between conflict markers like git
+/// instead of failing. `ours` is the version of the repository and `theirs` the one of the system.
+pub fn merge_with_markers(base: &str, ours: &str, theirs: &str) -> String {
+    merge_lines(base, ours, theirs, true).unwrap_or_default()
+}
+
+fn merge_lines(base: &str, ours: &str, theirs: &str, markers: bool) -> Option<String> {
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+    let ours_lines: Vec<&str> = ours.split_inclusive('\n').collect();
+    let theirs_lines: Vec<&str> = theirs.split_inclusive('\n').collect();
+    let ours = edits(base, ours, &ours_lines);
+    let theirs = edits(base, theirs, &theirs_lines);
+
+    let mut merged = String::with_capacity(base.len());
+    let (mut next_ours, mut next_theirs) = (0, 0);
+    let mut position = 0;
+    loop {
+        let start = match (ours.get(next_ours), theirs.get(next_theirs)) {
+            (None, None) => break,
+            (Some(edit), None) | (None, Some(edit)) => edit.base.start,
+            (Some(a), Some(b)) => a.base.start.min(b.base.start),
+        };
+        // collect all edits of both sides which overlap or touch each other
+        let mut end = start;
+        let (first_ours, first_theirs) = (next_ours, next_theirs);
+        loop {
+            let before = (next_ours, next_theirs);
+            while let Some(edit) = ours.get(next_ours).filter(|edit| edit.base.start <= end) {
+                end = end.max(edit.base.end);
+                next_ours += 1;
+            }
+            while let Some(edit) = theirs
+                .get(next_theirs)
+                .filter(|edit| edit.base.start <= end)
+            {
+                end = end.max(edit.base.end);
+                next_theirs += 1;
+            }
+            if (next_ours, next_theirs) == before {
+                break;
+            }
+        }
+
+        merged.push_str(&base_lines[position..start].concat());
+        let original = base_lines[start..end].concat();
+        let ours_text = apply(&base_lines, start..end, &ours[first_ours..next_ours]);
+        let theirs_text = apply(&base_lines, start..end, &theirs[first_theirs..next_theirs]);
+        if ours_text == original || ours_text == theirs_text {
+            merged.push_str(&theirs_text);
+        } else if theirs_text == original {
+            merged.push_str(&ours_text);
+        } else if markers {
+            merged.push_str("<<<<<<< repository\n");
+            push_line(&mut merged, &ours_text);
+            merged.push_str("=======\n");
+            push_line(&mut merged, &theirs_text);
+            merged.push_str(">>>>>>> system\n");
+        } else {
+            return None;
+        }
+        position = end;
+    }
+    merged.push_str(&base_lines[position..].concat());
+    Some(merged)
+}
+
+/// appends lines which may be missing the final line ending, so that the next marker starts on its own line
+fn push_line(merged: &mut String, lines: &str) {
+    merged.push_str(lines);
+    if !lines.is_empty() && !lines.ends_with('\n') {
+        merged.push('\n');
+    }
+}
+
+/// the ranges of the base which are changed on one side
+fn edits<'a>(base: &str, side: &str, side_lines: &'a [&'a str]) -> Vec<Edit<'a>> {
+    TextDiff::from_lines(base, side)
+        .ops()
+        .iter()
+        .map(|op| op.as_tag_tuple())
+        .filter(|(tag, _, _)| *tag != DiffTag::Equal)
+        .map(|(_, base, side)| Edit {
+            base,
+            lines: &side_lines[side],
+        })
+        .collect()
+}
+
+/// the lines of the base in `range` with the edits of one side applied
+fn apply(base_lines: &[&str], range: Range<usize>, edits: &[Edit]) -> String {
+    let mut applied = String::new();
+    let mut position = range.start;
+    for edit in edits {
+        applied.push_str(&base_lines[position..edit.base.start].concat());
+        applied.push_str(&edit.lines.concat());
+        position = edit.base.end;
+    }
+    applied.push_str(&base_lines[position..range.end].concat());
+    applied
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "one\ntwo\nthree\nfour\nfive\n";
+
+    #[test]
+    fn disjoint_edits_are_combined() {
+        let ours = "ONE\ntwo\nthree\nfour\nfive\n";
+        let theirs = "one\ntwo\nthree\nfour\nFIVE\nsix\n";
+        assert_eq!(
+            merge(BASE, ours, theirs).as_deref(),
+            Some("ONE\ntwo\nthree\nfour\nFIVE\nsix\n")
+        );
+    }
+
+    #[test]
+    fn edits_of_one_side_are_taken() {
+        let changed = "one\n2\nthree\nfive\n";
+        assert_eq!(merge(BASE, BASE, changed).as_deref(), Some(changed));
+        assert_eq!(merge(BASE, changed, BASE).as_deref(), Some(changed));
+    }
+
+    #[test]
+    fn identical_edits_are_taken_once() {
+        let changed = "one\ntwo\nTHREE\nfour\nfive\n";
+        assert_eq!(merge(BASE, changed, changed).as_deref(), Some(changed));
+    }
+
+    #[test]
+    fn adjacent_edits_conflict() {
+        let ours = "one\nTWO\nthree\nfour\nfive\n";
+        let theirs = "one\ntwo\nTHREE\nfour\nfive\n";
+        assert_eq!(merge(BASE, ours, theirs), None);
+    }
+
+    #[test]
+    fn missing_trailing_newline_is_kept() {
+        let base = "one\ntwo\nthree";
+        let ours = "ONE\ntwo\nthree";
+        assert_eq!(merge(base, ours, base).as_deref(), Some(ours));
+        // adding the line ending changes the last line
+        assert_eq!(
+            merge(base, ours, "one\ntwo\nthree\n").as_deref(),
+            Some("ONE\ntwo\nthree\n")
+        );
+    }
+
+    #[test]
+    fn conflicts_are_marked() {
+        let ours = "one\nTWO\nthree\nfour\nfive";
+        let theirs = "one\n2\nthree\nfour\nFIVE";
+        assert_eq!(
+            merge_with_markers(BASE, ours, theirs),
+            "one\n<<<<<<< repository\nTWO\n=======\n2\n>>>>>>> system\nthree\nfour\n\
+             <<<<<<< repository\nfive\n=======\nFIVE\n>>>>>>> system\n"
+        );
+    }
+}
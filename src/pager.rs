@@ -0,0 +1,72 @@
+use std::{
+    env,
+    io::{self, stdin, stdout, IsTerminal, Write},
+    process::Stdio,
+    sync::OnceLock,
+};
+
+use crate::{io::shell, progress};
+
+/// The pager which is used if `PAGER` isn't set.
+#[cfg(unix)]
+const DEFAULT_PAGER: &str = "less -R";
+#[cfg(windows)]
+const DEFAULT_PAGER: &str = "more";
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+/// Decides once whether long output is shown in a pager, which is only done if stdin and stdout are terminals.
+/// Without a call to this function, output is never paged.
+pub fn init(enabled: bool) {
+    ENABLED
+        .set(enabled && stdin().is_terminal() && stdout().is_terminal())
+        .ok();
+}
+
+/// Prints the text, through the pager if it doesn't fit on the screen.
+/// If the pager can't be started, the text is printed directly.
+pub fn page(text: &str) {
+    progress::suspend(|| {
+        if !exceeds_screen(text) || show(text).is_err() {
+            print!("{}", text);
+        }
+    });
+}
+
+fn exceeds_screen(text: &str) -> bool {
+    if !*ENABLED.get().unwrap_or(&false) {
+        return false;
+    }
+    match terminal_size::terminal_size() {
+        Some((_, terminal_size::Height(height))) => text.lines().count() >= height as usize,
+        None => false,
+    }
+}
+
+/// pipes the text into the pager from `PAGER` and waits until it is closed
+fn show(text: &str) -> io::Result<()> {
+    let pager = env::var("PAGER")
+        .ok()
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| String::from(DEFAULT_PAGER));
+    let mut child = shell(&pager).stdin(Stdio::piped()).spawn()?;
+    if let Some(mut input) = child.stdin.take() {
+        // the pager may be closed before it has read everything
+        match input.write_all(text.as_bytes()) {
+            Err(error) if error.kind() != io::ErrorKind::BrokenPipe => {
+                child.wait()?;
+                return Err(error);
+            }
+            _ => {}
+        }
+    }
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "The pager failed with {}",
+            status
+        )))
+    }
+}
@@ -0,0 +1,612 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{
+    diff::ChangeKind,
+    io::{mode, set_mode, write},
+};
+
+/// The changes to a single file in a patch.
+#[derive(Debug, Default)]
+pub struct FilePatch {
+    /// the path relative to the directory the patch is applied in
+    path: String,
+    created: bool,
+    deleted: bool,
+    /// whether the file is executable afterwards, if the patch sets its mode
+    executable: Option<bool>,
+    binary: bool,
+    hunks: Vec<Hunk>,
+}
+
+/// A part of a file which is changed, together with the lines around it.
+#[derive(Debug, Default)]
+struct Hunk {
+    /// the first line of the hunk in the old file, starting at 1
+    old_start: usize,
+    /// the lines which are expected in the old file, including their line endings
+    old: Vec<String>,
+    /// the lines which replace them
+    new: Vec<String>,
+}
+
+/// What applying a patch does to a single file.
+enum Outcome {
+    Write(String),
+    Remove,
+    /// only the mode changes
+    Keep,
+}
+
+/// Parses a unified diff, with or without the headers of git.
+/// # Errors
+/// Returns an error if the patch is malformed or doesn't change any file.
+pub fn parse(text: &str) -> Result<Vec<FilePatch>> {
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut lines = text.split_inclusive('\n').enumerate().peekable();
+
+    while let Some((number, line)) = lines.next() {
+        let content = line.trim_end_matches(['\n', '\r']);
+        let error = |message: &str| anyhow!("{} in line {} of the patch", message, number + 1);
+
+        if let Some(paths) = content.strip_prefix("diff --git ") {
+            let path = paths
+                .rsplit_once(" b/")
+                .map(|(_, path)| path)
+                .ok_or_else(|| error("Invalid 'diff --git' header"))?;
+            files.push(FilePatch {
+                path: String::from(path),
+                ..Default::default()
+            });
+            continue;
+        }
+        if let Some(path) = content.strip_prefix("--- ") {
+            // plain unified diffs start with the name of the old file
+            let path = header_path(path, "a/");
+            let current = match files.last_mut() {
+                Some(file) if file.hunks.is_empty() => file,
+                _ => {
+                    files.push(FilePatch::default());
+                    files.last_mut().unwrap()
+                }
+            };
+            match path {
+                None => current.created = true,
+                Some(path) if current.path.is_empty() => current.path = path,
+                Some(_) => {}
+            }
+            continue;
+        }
+
+        let Some(file) = files.last_mut() else {
+            // text before the first file, like the message of a mail
+            continue;
+        };
+        if let Some(path) = content.strip_prefix("+++ ") {
+            match header_path(path, "b/") {
+                None => file.deleted = true,
+                Some(path) => file.path = path,
+            }
+        } else if content.starts_with("new file mode ") {
+            file.created = true;
+            file.executable = Some(content.ends_with("755"));
+        } else if content.starts_with("deleted file mode ") {
+            file.deleted = true;
+        } else if let Some(mode) = content.strip_prefix("new mode ") {
+            file.executable = Some(mode.ends_with("755"));
+        } else if content.starts_with("Binary files ") || content == "GIT binary patch" {
+            file.binary = true;
+        } else if let Some(header) = content.strip_prefix("@@ ") {
+            let (old_start, old_count, new_count) =
+                hunk_header(header).ok_or_else(|| error("Invalid hunk header"))?;
+            let mut hunk = Hunk {
+                old_start,
+                ..Default::default()
+            };
+            // the number of lines is known, so removed lines starting with `--` aren't mistaken for headers
+            while hunk.old.len() < old_count || hunk.new.len() < new_count {
+                let Some((number, line)) = lines.next() else {
+                    return Err(anyhow!("The patch ends in the middle of a hunk"));
+                };
+                let (marker, rest) = line.split_at(line.len().min(1));
+                match marker {
+                    " " | "\n" | "\r" => {
+                        let rest = if marker == " " { rest } else { line };
+                        hunk.old.push(String::from(rest));
+                        hunk.new.push(String::from(rest));
+                    }
+                    "-" => hunk.old.push(String::from(rest)),
+                    "+" => hunk.new.push(String::from(rest)),
+                    "\\" => continue,
+                    _ => {
+                        return Err(anyhow!(
+                            "Unexpected line {} in a hunk of the patch",
+                            number + 1
+                        ))
+                    }
+                }
+                skip_no_newline(&mut lines, &mut hunk, marker);
+            }
+            file.hunks.push(hunk);
+        }
+    }
+
+    if files.is_empty() {
+        return Err(anyhow!("The patch doesn't change any files"));
+    }
+    Ok(files)
+}
+
+/// removes the line ending of the last line if the next line says that the file doesn't end with one
+fn skip_no_newline<'a>(
+    lines: &mut std::iter::Peekable<impl Iterator<Item = (usize, &'a str)>>,
+    hunk: &mut Hunk,
+    marker: &str,
+) {
+    if !lines.peek().is_some_and(|(_, line)| line.starts_with('\\')) {
+        return;
+    }
+    lines.next();
+    let trim = |line: Option<&mut String>| {
+        if let Some(line) = line {
+            let trimmed = line.trim_end_matches(['\n', '\r']).len();
+            line.truncate(trimmed);
+        }
+    };
+    match marker {
+        "-" => trim(hunk.old.last_mut()),
+        "+" => trim(hunk.new.last_mut()),
+        _ => {
+            trim(hunk.old.last_mut());
+            trim(hunk.new.last_mut());
+        }
+    }
+}
+
+/// the path of a `---` or `+++` header without its prefix, `None` for `/dev/null`
+fn header_path(header: &str, prefix: &str) -> Option<String> {
+    // `diff -u` appends the modification time after a tab
+    let path = header.split('\t').next().unwrap_or(header);
+    if path == "/dev/null" {
+        return None;
+    }
+    Some(String::from(path.strip_prefix(prefix).unwrap_or(path)))
+}
+
+/// parses `-1,3 +1,4 @@` into the first old line and the number of old and new lines
+fn hunk_header(header: &str) -> Option<(usize, usize, usize)> {
+    let mut ranges = header.split_whitespace();
+    let range = |range: &str| -> Option<(usize, usize)> {
+        match range.split_once(',') {
+            Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+    let (old_start, old_count) = range(ranges.next()?.strip_prefix('-')?)?;
+    let (_, new_count) = range(ranges.next()?.strip_prefix('+')?)?;
+    Some((old_start, old_count, new_count))
+}
+
+/// Checks that a path of a patch stays inside of the directory it is applied in.
+/// Configs may contain links, so none of the parts of the path may be a link either.
+fn checked_path(dir: &Path, path: &str) -> Result<PathBuf> {
+    let checked = PathBuf::from(path);
+    if path.is_empty()
+        || !checked
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(anyhow!(
+            "The patch changes '{}', which is outside of the config.",
+            path
+        ));
+    }
+    let mut current = dir.to_path_buf();
+    for component in checked.components() {
+        current.push(component);
+        if current.is_symlink() {
+            return Err(anyhow!(
+                "The patch changes '{}' through the link '{}', which may point outside of the config.",
+                path,
+                current.strip_prefix(dir).unwrap_or(&current).display()
+            ));
+        }
+    }
+    Ok(checked)
+}
+
+/// Applies a patch to the files in `dir`. All files are checked before the first one is changed,
+/// so a patch which doesn't apply leaves every file as it is.
+/// With `check`, nothing is changed at all.
+/// Returns the changed files relative to `dir`.
+/// # Errors
+/// Returns an error if a path lies outside of `dir`, a hunk doesn't match the file or a file can't be written.
+pub fn apply(dir: &Path, files: &[FilePatch], check: bool) -> Result<Vec<(ChangeKind, PathBuf)>> {
+    let mut outcomes = Vec::new();
+    for file in files {
+        let path = checked_path(dir, &file.path)?;
+        let outcome = outcome(&dir.join(&path), file)
+            .with_context(|| format!("Could not apply the changes to '{}'", file.path))?;
+        outcomes.push((path, outcome, file));
+    }
+    if check {
+        return Ok(outcomes
+            .iter()
+            .map(|(path, _, file)| (kind(file), path.clone()))
+            .collect());
+    }
+
+    let mut changed = Vec::new();
+    for (path, outcome, file) in outcomes {
+        let absolute = dir.join(&path);
+        match outcome {
+            Outcome::Write(contents) => {
+                if let Some(parent) = absolute.parent() {
+                    fs::create_dir_all(parent).with_context(|| {
+                        format!("Could not create directory '{}'", parent.display())
+                    })?;
+                }
+                write(&absolute, contents.as_bytes())?;
+            }
+            Outcome::Remove => fs::remove_file(&absolute)
+                .with_context(|| format!("Could not remove '{}'", absolute.display()))?,
+            Outcome::Keep => {}
+        }
+        if let (Some(executable), false) = (file.executable, file.deleted) {
+            let current = mode(&absolute).unwrap_or(0o644);
+            set_mode(
+                &absolute,
+                if executable {
+                    current | 0o111
+                } else {
+                    current & !0o111
+                },
+            )?;
+        }
+        changed.push((kind(file), path));
+    }
+    Ok(changed)
+}
+
+/// the kind of change a file patch makes, like the changes which are pulled
+fn kind(file: &FilePatch) -> ChangeKind {
+    if file.created {
+        ChangeKind::Added
+    } else if file.deleted {
+        ChangeKind::Removed
+    } else if file.hunks.is_empty() {
+        ChangeKind::Mode
+    } else {
+        ChangeKind::Modified
+    }
+}
+
+/// computes the new contents of a file
+fn outcome(path: &Path, file: &FilePatch) -> Result<Outcome> {
+    if file.binary {
+        return Err(anyhow!("Binary changes can't be applied"));
+    }
+    let exists = path.is_file();
+    if file.created && exists {
+        return Err(anyhow!("The file already exists"));
+    }
+    if !file.created && !exists {
+        return Err(anyhow!("The file doesn't exist"));
+    }
+    if file.hunks.is_empty() && !file.deleted {
+        return Ok(Outcome::Keep);
+    }
+
+    let contents = if exists {
+        fs::read_to_string(path).with_context(|| format!("Could not read '{}'", path.display()))?
+    } else {
+        String::new()
+    };
+    let lines: Vec<&str> = contents.split_inclusive('\n').collect();
+
+    let mut patched = String::with_capacity(contents.len());
+    let mut position = 0;
+    for (index, hunk) in file.hunks.iter().enumerate() {
+        let start = find_hunk(&lines, hunk, position).ok_or_else(|| {
+            anyhow!(
+                "Hunk {} doesn't match the file, it was changed since the patch was made",
+                index + 1
+            )
+        })?;
+        patched.extend(lines[position..start].iter().copied());
+        patched.extend(hunk.new.iter().map(String::as_str));
+        position = start + hunk.old.len();
+    }
+    patched.extend(lines[position..].iter().copied());
+
+    if file.deleted {
+        if !patched.is_empty() {
+            return Err(anyhow!(
+                "The file has contents which the patch doesn't remove"
+            ));
+        }
+        return Ok(Outcome::Remove);
+    }
+    Ok(Outcome::Write(patched))
+}
+
+/// finds the line at which the old lines of a hunk are, starting with the line the hunk expects
+/// and moving further away from it, like `patch` does when lines were added above the hunk
+fn find_hunk(lines: &[&str], hunk: &Hunk, from: usize) -> Option<usize> {
+    // a hunk without old lines inserts after its start line
+    let expected = if hunk.old.is_empty() {
+        hunk.old_start
+    } else {
+        hunk.old_start.saturating_sub(1)
+    };
+    let last = lines.len().checked_sub(hunk.old.len())?;
+    let matches = |start: usize| {
+        start >= from
+            && start <= last
+            && lines[start..start + hunk.old.len()]
+                .iter()
+                .zip(&hunk.old)
+                .all(|(line, old)| *line == old)
+    };
+    (0..=lines.len()).find_map(|distance| {
+        [
+            expected.checked_add(distance),
+            expected.checked_sub(distance),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|start| matches(*start))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIT_PATCH: &str = "\
+diff --git a/init.lua b/init.lua
+index 1111111..2222222 100644
+--- a/init.lua
++++ b/init.lua
+@@ -1,3 +1,3 @@
+ one
+-two
++TWO
+ three
+diff --git a/new.sh b/new.sh
+new file mode 100755
+--- /dev/null
++++ b/new.sh
+@@ -0,0 +1 @@
++echo new
+diff --git a/old b/old
+deleted file mode 100644
+--- a/old
++++ /dev/null
+@@ -1 +0,0 @@
+-gone
+diff --git a/run.sh b/run.sh
+old mode 100644
+new mode 100755
+";
+
+    fn dir_with(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, contents) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    fn read(dir: &tempfile::TempDir, path: &str) -> String {
+        fs::read_to_string(dir.path().join(path)).unwrap()
+    }
+
+    #[test]
+    fn git_headers_are_parsed() {
+        let files = parse(GIT_PATCH).unwrap();
+        let summary: Vec<(&str, bool, bool, Option<bool>, usize)> = files
+            .iter()
+            .map(|file| {
+                (
+                    file.path.as_str(),
+                    file.created,
+                    file.deleted,
+                    file.executable,
+                    file.hunks.len(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("init.lua", false, false, None, 1),
+                ("new.sh", true, false, Some(true), 1),
+                ("old", false, true, None, 1),
+                ("run.sh", false, false, Some(true), 0),
+            ]
+        );
+        let hunk = &files[0].hunks[0];
+        assert_eq!(hunk.old_start, 1);
+        assert_eq!(hunk.old, ["one\n", "two\n", "three\n"]);
+        assert_eq!(hunk.new, ["one\n", "TWO\n", "three\n"]);
+    }
+
+    #[test]
+    fn plain_headers_are_parsed() {
+        let patch = "\
+Some text of a mail before the patch.
+--- config.toml\t2024-01-01 10:00:00.000000000 +0100
++++ config.toml\t2024-01-02 10:00:00.000000000 +0100
+@@ -2 +2,2 @@
+-b = 1
++b = 2
++c = 3
+";
+        let files = parse(patch).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "config.toml");
+        assert_eq!(files[0].hunks[0].old, ["b = 1\n"]);
+        assert_eq!(files[0].hunks[0].new, ["b = 2\n", "c = 3\n"]);
+    }
+
+    #[test]
+    fn hunk_headers_are_parsed() {
+        assert_eq!(hunk_header("-1,3 +1,4 @@"), Some((1, 3, 4)));
+        assert_eq!(hunk_header("-5 +5 @@ fn main() {"), Some((5, 1, 1)));
+        assert_eq!(hunk_header("-0,0 +1 @@"), Some((0, 0, 1)));
+        assert_eq!(hunk_header("+1,3 -1,4 @@"), None);
+        assert_eq!(hunk_header("-a,3 +1 @@"), None);
+        assert!(parse("--- a\n+++ b\n@@ -x +1 @@\n").is_err());
+        assert!(parse("--- a\n+++ b\n@@ -1,2 +1,2 @@\n a\n").is_err());
+        assert!(parse("just some text\n").is_err());
+    }
+
+    #[test]
+    fn missing_line_endings_at_the_end_are_kept() {
+        let patch = "\
+--- a/file
++++ b/file
+@@ -1,2 +1,2 @@
+ one
+-two
+\\ No newline at end of file
++TWO
+\\ No newline at end of file
+";
+        let files = parse(patch).unwrap();
+        assert_eq!(files[0].hunks[0].old, ["one\n", "two"]);
+        assert_eq!(files[0].hunks[0].new, ["one\n", "TWO"]);
+
+        let dir = dir_with(&[("file", "one\ntwo")]);
+        apply(dir.path(), &files, false).unwrap();
+        assert_eq!(read(&dir, "file"), "one\nTWO");
+    }
+
+    #[test]
+    fn hunks_are_found_away_from_their_line() {
+        let patch = "--- a/file\n+++ b/file\n@@ -2,2 +2,2 @@\n b\n-c\n+C\n";
+        let files = parse(patch).unwrap();
+        // two lines were added above the hunk since the patch was made
+        let dir = dir_with(&[("file", "x\ny\na\nb\nc\nd\n")]);
+        apply(dir.path(), &files, false).unwrap();
+        assert_eq!(read(&dir, "file"), "x\ny\na\nb\nC\nd\n");
+
+        let hunk = &files[0].hunks[0];
+        assert_eq!(find_hunk(&["b\n", "c\n"], hunk, 0), Some(0));
+        assert_eq!(find_hunk(&["b\n", "c\n"], hunk, 1), None);
+        assert_eq!(find_hunk(&["c\n", "b\n"], hunk, 0), None);
+    }
+
+    #[test]
+    fn files_are_created_deleted_and_made_executable() {
+        let dir = dir_with(&[
+            ("init.lua", "one\ntwo\nthree\n"),
+            ("old", "gone\n"),
+            ("run.sh", "run\n"),
+        ]);
+        let changed = apply(dir.path(), &parse(GIT_PATCH).unwrap(), false).unwrap();
+        let kinds: Vec<ChangeKind> = changed.into_iter().map(|(kind, _)| kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                ChangeKind::Modified,
+                ChangeKind::Added,
+                ChangeKind::Removed,
+                ChangeKind::Mode
+            ]
+        );
+        assert_eq!(read(&dir, "init.lua"), "one\nTWO\nthree\n");
+        assert_eq!(read(&dir, "new.sh"), "echo new\n");
+        assert!(!dir.path().join("old").exists());
+        assert_eq!(read(&dir, "run.sh"), "run\n");
+        #[cfg(unix)]
+        {
+            assert_eq!(mode(&dir.path().join("new.sh")).unwrap() & 0o111, 0o111);
+            assert_eq!(mode(&dir.path().join("run.sh")).unwrap() & 0o111, 0o111);
+        }
+    }
+
+    #[test]
+    fn paths_outside_of_the_config_are_rejected() {
+        let dir = dir_with(&[("file", "a\n")]);
+        for path in ["../file", "/etc/passwd", "dir/../../file"] {
+            let patch = format!("--- a/{path}\n+++ b/{path}\n@@ -1 +1 @@\n-a\n+b\n");
+            let error = apply(dir.path(), &parse(&patch).unwrap(), false).unwrap_err();
+            assert!(
+                error.to_string().contains("outside of the config"),
+                "{}: {}",
+                path,
+                error
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn paths_through_links_are_rejected() {
+        let outside = dir_with(&[("file", "a\n")]);
+        let dir = dir_with(&[]);
+        crate::io::create_symlink(outside.path(), &dir.path().join("link")).unwrap();
+        crate::io::create_symlink(&outside.path().join("file"), &dir.path().join("file")).unwrap();
+
+        for path in ["link/file", "file"] {
+            let patch = format!("--- a/{path}\n+++ b/{path}\n@@ -1 +1 @@\n-a\n+b\n");
+            let error = apply(dir.path(), &parse(&patch).unwrap(), false).unwrap_err();
+            assert!(error.to_string().contains("through the link"), "{}", error);
+        }
+        let patch = "--- /dev/null\n+++ b/link/new\n@@ -0,0 +1 @@\n+b\n";
+        assert!(apply(dir.path(), &parse(patch).unwrap(), false).is_err());
+        assert_eq!(read(&outside, "file"), "a\n");
+        assert!(!outside.path().join("new").exists());
+    }
+
+    #[test]
+    fn nothing_is_changed_if_a_hunk_does_not_match() {
+        let dir = dir_with(&[("init.lua", "one\ntwo\nthree\n"), ("other", "changed\n")]);
+        let patch = "\
+--- a/init.lua
++++ b/init.lua
+@@ -2 +2 @@
+-two
++TWO
+--- /dev/null
++++ b/new
+@@ -0,0 +1 @@
++new
+--- a/other
++++ b/other
+@@ -1 +1 @@
+-original
++patched
+";
+        let error = apply(dir.path(), &parse(patch).unwrap(), false).unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            "Could not apply the changes to 'other': Hunk 1 doesn't match the file, it was changed since the patch was made"
+        );
+        assert_eq!(read(&dir, "init.lua"), "one\ntwo\nthree\n");
+        assert!(!dir.path().join("new").exists());
+        assert_eq!(read(&dir, "other"), "changed\n");
+    }
+
+    #[test]
+    fn checking_changes_nothing() {
+        let dir = dir_with(&[
+            ("init.lua", "one\ntwo\nthree\n"),
+            ("old", "gone\n"),
+            ("run.sh", ""),
+        ]);
+        let changed = apply(dir.path(), &parse(GIT_PATCH).unwrap(), true).unwrap();
+        assert_eq!(changed.len(), 4);
+        assert_eq!(read(&dir, "init.lua"), "one\ntwo\nthree\n");
+        assert!(dir.path().join("old").exists());
+        assert!(!dir.path().join("new.sh").exists());
+    }
+}
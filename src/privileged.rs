@@ -0,0 +1,200 @@
+use std::{
+    env, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::OnceLock,
+};
+
+use anyhow::{anyhow, Context, Result};
+use relative_path::RelativePathBuf;
+
+use crate::{
+    dependencies::find_program,
+    io,
+    manifest::{self, STATE_DIR},
+    progress,
+};
+
+/// The programs which are used to run commands as root, the first one found is used.
+/// On windows, powershell asks for elevation with the UAC prompt.
+#[cfg(unix)]
+const PROGRAMS: [&str; 2] = ["sudo", "doas"];
+#[cfg(windows)]
+const PROGRAMS: [&str; 2] = ["powershell", "pwsh"];
+
+static PROGRAM: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// A change to the file system which is made as root.
+#[derive(Debug, Clone, Copy)]
+enum Operation<'a> {
+    /// creates a directory and its parents
+    CreateDir(&'a Path),
+    /// copies a file, an existing file keeps its owner and mode
+    Copy {
+        from: &'a Path,
+        to: &'a Path,
+    },
+    Remove(&'a Path),
+    /// creates a symlink at `link` pointing to `target`
+    Link {
+        target: &'a Path,
+        link: &'a Path,
+    },
+    SetMode(&'a Path, u32),
+}
+
+/// Returns the program which runs commands as root.
+/// # Errors
+/// Returns an error if none of the programs is installed.
+fn program() -> Result<&'static Path> {
+    PROGRAM
+        .get_or_init(|| PROGRAMS.iter().find_map(|program| find_program(program)))
+        .as_deref()
+        .ok_or_else(|| {
+            anyhow!(
+                "Deploying this config requires elevated permissions, but neither {} was found.",
+                PROGRAMS.join(" nor ")
+            )
+        })
+}
+
+/// Checks if an error was caused by missing permissions.
+pub fn is_permission_error(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|error| error.kind() == ErrorKind::PermissionDenied)
+    })
+}
+
+/// runs an operation as root with sudo or doas, which ask for the password on the terminal if they need one.
+#[cfg(unix)]
+fn run(operation: Operation) -> Result<()> {
+    use std::ffi::OsString;
+
+    let args: Vec<OsString> = match operation {
+        Operation::CreateDir(dir) => vec!["mkdir".into(), "-p".into(), dir.into()],
+        Operation::Copy { from, to } => vec!["cp".into(), from.into(), to.into()],
+        Operation::Remove(path) => vec!["rm".into(), "-f".into(), path.into()],
+        Operation::Link { target, link } => {
+            vec!["ln".into(), "-sfn".into(), target.into(), link.into()]
+        }
+        Operation::SetMode(path, mode) => {
+            vec!["chmod".into(), format!("{:o}", mode).into(), path.into()]
+        }
+    };
+
+    let program = program()?;
+    // files are written in parallel, but only one command may ask for the password at a time
+    let _terminal = progress::terminal();
+    let status = progress::suspend(|| Command::new(program).args(&args).status())
+        .with_context(|| format!("Could not run '{}'", program.display()))?;
+    if !status.success() {
+        return Err(anyhow!(
+            "'{} {}' failed with {}",
+            program.display(),
+            args.iter()
+                .map(|arg| arg.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" "),
+            status
+        ));
+    }
+    Ok(())
+}
+
+/// runs an operation as administrator with cmd, started from powershell so that windows shows the UAC prompt.
+/// Modes don't exist on windows, so they are ignored.
+#[cfg(windows)]
+fn run(operation: Operation) -> Result<()> {
+    let quote = |path: &Path| format!("\"{}\"", path.display());
+    let command = match operation {
+        Operation::CreateDir(dir) => format!("if not exist {0} mkdir {0}", quote(dir)),
+        Operation::Copy { from, to } => format!("copy /y {} {}", quote(from), quote(to)),
+        Operation::Remove(path) => format!("del /f /q {}", quote(path)),
+        Operation::Link { target, link } => {
+            let resolved = link.parent().unwrap_or(Path::new(".")).join(target);
+            let kind = if resolved.is_dir() { "/d " } else { "" };
+            format!("mklink {}{} {}", kind, quote(link), quote(target))
+        }
+        Operation::SetMode(..) => return Ok(()),
+    };
+
+    // single quotes are escaped by doubling them in powershell strings
+    let script = format!(
+        "$process = Start-Process -FilePath cmd.exe -ArgumentList '/c {}' -Verb RunAs -Wait -PassThru -WindowStyle Hidden; exit $process.ExitCode",
+        command.replace('\'', "''")
+    );
+    let program = program()?;
+    // files are written in parallel, but only one command may ask for the password at a time
+    let _terminal = progress::terminal();
+    let status = progress::suspend(|| {
+        Command::new(program)
+            .args(["-NoProfile", "-NonInteractive", "-Command", &script])
+            .status()
+    })
+    .with_context(|| format!("Could not run '{}'", program.display()))?;
+    if !status.success() {
+        return Err(anyhow!(
+            "'{}' failed as administrator with {}",
+            command,
+            status
+        ));
+    }
+    Ok(())
+}
+
+/// Creates a directory and its parents as root.
+pub fn create_dir(dir: &Path) -> Result<()> {
+    run(Operation::CreateDir(dir))
+}
+
+/// Writes a file as root, creating its parent directories. The file gets the given mode.
+/// The contents are first written to a file in the state directory which only the user can read,
+/// so that decrypted secrets don't end up in a world readable location.
+pub fn write(name: &str, destination: &Path, contents: &[u8], mode: u32) -> Result<()> {
+    manifest::create_state_dir()?;
+    let staged = RelativePathBuf::from(STATE_DIR)
+        .join(format!("{}.staged", name))
+        .to_path(".");
+    io::write_private(&staged, contents)
+        .with_context(|| format!("Could not write '{}'", staged.display()))?;
+
+    // the elevated process may run in another directory
+    let result = env::current_dir()
+        .map_err(anyhow::Error::from)
+        .and_then(|dir| copy(&dir.join(&staged), destination))
+        .and_then(|_| run(Operation::SetMode(destination, mode)));
+    fs::remove_file(&staged).with_context(|| format!("Could not remove '{}'", staged.display()))?;
+    result
+}
+
+/// Copies a file as root, creating the parent directories of `to`.
+/// An existing file at `to` keeps its owner and mode.
+pub fn copy(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        create_dir(parent)?;
+    }
+    // copying onto a link would change the file it points to instead of the link
+    if to.is_symlink() {
+        remove(to)?;
+    }
+    run(Operation::Copy { from, to })
+}
+
+/// Removes a file as root.
+pub fn remove(path: &Path) -> Result<()> {
+    run(Operation::Remove(path))
+}
+
+/// Creates a symlink at `link` pointing to `target` as root, replacing an existing file.
+pub fn link(target: &Path, link: &Path) -> Result<()> {
+    if let Some(parent) = link.parent() {
+        create_dir(parent)?;
+    }
+    if cfg!(windows) && (link.exists() || link.is_symlink()) {
+        remove(link)?;
+    }
+    run(Operation::Link { target, link })
+}
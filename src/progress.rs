@@ -0,0 +1,51 @@
+use std::{
+    io::{stderr, IsTerminal},
+    sync::{Mutex, MutexGuard, OnceLock},
+};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressFinish, ProgressStyle};
+
+/// All progress bars are drawn together, so that configs which are handled in parallel don't overwrite each other.
+static PROGRESS: OnceLock<MultiProgress> = OnceLock::new();
+/// configs are deployed in parallel, but only one of them may use the terminal for hooks and password prompts
+static TERMINAL: Mutex<()> = Mutex::new(());
+
+/// Enables progress bars, unless `quiet` is set or stderr isn't a terminal.
+/// Without a call to this function, progress bars are hidden.
+pub fn init(quiet: bool) {
+    let target = if quiet || !stderr().is_terminal() {
+        ProgressDrawTarget::hidden()
+    } else {
+        ProgressDrawTarget::stderr()
+    };
+    PROGRESS.set(MultiProgress::with_draw_target(target)).ok();
+}
+
+fn progress() -> &'static MultiProgress {
+    PROGRESS.get_or_init(|| MultiProgress::with_draw_target(ProgressDrawTarget::hidden()))
+}
+
+/// Creates a progress bar for processing `len` files of a config.
+/// The current file can be shown with [`ProgressBar::set_message`].
+pub fn bar(len: usize, name: &str) -> ProgressBar {
+    // bars which are dropped early, e.g. because of an error, don't stay on the screen
+    let bar = progress().add(ProgressBar::new(len as u64).with_finish(ProgressFinish::AndClear));
+    bar.set_style(
+        ProgressStyle::with_template("{prefix} [{bar:30}] {pos}/{len} {wide_msg}")
+            .expect("valid progress template")
+            .progress_chars("=> "),
+    );
+    bar.set_prefix(name.to_string());
+    bar
+}
+
+/// Waits until no other config uses the terminal and keeps it until the guard is dropped.
+/// Hooks, privileged commands and the passphrase prompt take it, so that their output and questions aren't mixed up.
+pub fn terminal() -> MutexGuard<'static, ()> {
+    TERMINAL.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+/// Hides the progress bars while `f` prints to the terminal or asks for input.
+pub fn suspend<R>(f: impl FnOnce() -> R) -> R {
+    progress().suspend(f)
+}
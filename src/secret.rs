@@ -0,0 +1,106 @@
+use std::{
+    env,
+    ffi::OsStr,
+    io::{stdin, IsTerminal},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use age::{
+    scrypt::{Identity, Recipient},
+    secrecy::SecretString,
+};
+use anyhow::{anyhow, Context, Result};
+use dialoguer::Password;
+
+use crate::progress;
+
+/// The extension of encrypted files in the repository.
+pub const EXTENSION: &str = "age";
+/// The environment variable the passphrase is read from before it is asked for.
+pub const PASSPHRASE_VARIABLE: &str = "DOTTOR_PASSPHRASE";
+
+/// the passphrase is only asked for once, even if several configs are deployed in parallel
+static PASSPHRASE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Returns the passphrase for secrets, asking for it if it isn't set in the environment.
+/// With `confirm`, a passphrase which is asked for has to be entered twice, since a typo
+/// would encrypt the secret with a passphrase nobody knows.
+/// The passphrase is only kept in memory.
+fn passphrase(confirm: bool) -> Result<SecretString> {
+    let mut passphrase = PASSPHRASE.lock().unwrap_or_else(|error| error.into_inner());
+    if passphrase.is_none() {
+        let value = match env::var(PASSPHRASE_VARIABLE) {
+            Ok(value) => value,
+            Err(_) if stdin().is_terminal() => {
+                // taken before the progress bars are hidden, as a hook holding it may hide them as well
+                let _terminal = progress::terminal();
+                progress::suspend(|| {
+                    let prompt = Password::new().with_prompt("Passphrase for secrets");
+                    if confirm {
+                        prompt
+                            .with_confirmation(
+                                "Repeat the passphrase",
+                                "The passphrases don't match.",
+                            )
+                            .interact()
+                    } else {
+                        prompt.interact()
+                    }
+                })?
+            }
+            Err(_) => {
+                return Err(anyhow!(
+                    "A passphrase is needed for secrets. Please set '{}'.",
+                    PASSPHRASE_VARIABLE
+                ))
+            }
+        };
+        *passphrase = Some(value);
+    }
+    Ok(SecretString::from(passphrase.clone().unwrap_or_default()))
+}
+
+/// Encrypts the contents of a secret file with the passphrase.
+/// If the passphrase wasn't used yet, it has to be confirmed.
+pub fn encrypt(plaintext: &[u8]) -> Result<Vec<u8>> {
+    let recipient = Recipient::new(passphrase(true)?);
+    age::encrypt(&recipient, plaintext).context("Could not encrypt secret")
+}
+
+/// Decrypts a secret file of the repository with the passphrase.
+/// # Errors
+/// Returns an error if the file can't be read or the passphrase is wrong.
+pub fn decrypt(file: &Path) -> Result<Vec<u8>> {
+    let ciphertext =
+        std::fs::read(file).with_context(|| format!("Could not read file '{}'", file.display()))?;
+    let identity = Identity::new(passphrase(false)?);
+    age::decrypt(&identity, &ciphertext)
+        .inspect_err(|_| {
+            // a mistyped passphrase must not be kept for encrypting other secrets
+            *PASSPHRASE.lock().unwrap_or_else(|error| error.into_inner()) = None;
+        })
+        .with_context(|| {
+            format!(
+                "Could not decrypt '{}'. Is the passphrase correct?",
+                file.display()
+            )
+        })
+}
+
+/// Returns the path under which a secret is stored in the repository.
+pub fn encrypted_path(path: &Path) -> PathBuf {
+    let mut encrypted = path.as_os_str().to_owned();
+    encrypted.push(".");
+    encrypted.push(EXTENSION);
+    PathBuf::from(encrypted)
+}
+
+/// Returns the path of the decrypted secret if `path` is an encrypted file.
+pub fn decrypted_path(path: &Path) -> Option<PathBuf> {
+    if path.extension() == Some(OsStr::new(EXTENSION)) {
+        Some(path.with_extension(""))
+    } else {
+        None
+    }
+}
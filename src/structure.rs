@@ -1,46 +1,138 @@
-use std::collections::{HashMap, HashSet};
+use std::{collections::HashMap, path::PathBuf};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 use crate::{
     config::{self, read_configuration, read_root_configuration, Configuration, RootConfiguration},
     io::{is_root_present, list_root},
+    manifest, style,
 };
 
 #[derive(Debug)]
 pub struct Structure {
     pub root: RootConfiguration,
     pub configs: HashMap<String, Configuration>,
+    /// the configs whose configuration file couldn't be read, together with the reason
+    pub errors: HashMap<String, anyhow::Error>,
 }
 
 impl Structure {
+    /// Reads the root configuration and all configs.
+    /// A config which can't be read doesn't prevent the others from being used, its error is kept in `errors`.
+    /// Configs whose names only differ in case would collide on case-insensitive file systems,
+    /// so they are all treated as broken.
+    /// # Errors
+    /// Returns an error if the root configuration can't be read.
     pub fn resolve() -> Result<Option<Self>> {
         if is_root_present() {
-            let root = read_root_configuration().unwrap();
-
-            let mut exclude = HashSet::new();
-            root.exclude.iter().for_each(|p| {
-                let mut p = p.clone();
-                if p.ends_with('/') {
-                    p.remove(p.len() - 1);
-                }
-                exclude.insert(p);
-            });
+            let root = read_root_configuration()?;
 
             let mut configs = HashMap::new();
-
-            for path in list_root().unwrap() {
-                let path = path.unwrap().path();
-                let key = path.file_name().unwrap().to_str().unwrap().to_string();
-
-                if path.is_dir() && !exclude.contains(&key) {
-                    let config = read_configuration(&path.join(config::CONFIG_PATH)).unwrap();
-                    configs.insert(key, config);
+            let mut errors = HashMap::new();
+            let dirs = config_dirs(&root)?;
+            for (key, path) in &dirs {
+                let key = key.clone();
+                let collisions: Vec<&str> = dirs
+                    .iter()
+                    .map(|(other, _)| other.as_str())
+                    .filter(|other| *other != key && same_name(other, &key))
+                    .collect();
+                if !collisions.is_empty() {
+                    errors.insert(
+                        key.clone(),
+                        anyhow!(
+                            "The name only differs in case from '{}'. Config names are compared case-insensitively, please rename one of them.",
+                            collisions.join("', '")
+                        ),
+                    );
+                    continue;
+                }
+                match read_configuration(&path.join(config::CONFIG_PATH)) {
+                    Ok(config) => {
+                        configs.insert(key, config);
+                    }
+                    Err(error) => {
+                        errors.insert(key, error);
+                    }
                 }
             }
 
-            return Ok(Some(Structure { root, configs }));
+            return Ok(Some(Structure {
+                root,
+                configs,
+                errors,
+            }));
         }
         Ok(None)
     }
+
+    /// Returns the name of the config whose name equals `name` when case is ignored, even if it couldn't be read.
+    /// On case-insensitive file systems, such a config occupies the directory `name` would be created in.
+    pub fn find(&self, name: &str) -> Option<&str> {
+        self.configs
+            .keys()
+            .chain(self.errors.keys())
+            .find(|key| same_name(key, name))
+            .map(String::as_str)
+    }
+
+    /// Returns the config with the name.
+    /// # Errors
+    /// Returns an error if the config doesn't exist or couldn't be read.
+    pub fn config(&self, name: &str) -> Result<&Configuration> {
+        if let Some(config) = self.configs.get(name) {
+            return Ok(config);
+        }
+        match self.errors.get(name) {
+            Some(error) => Err(anyhow!(
+                "Config '{}' could not be read: {}",
+                name,
+                format!("{:#}", error).trim_end()
+            )),
+            None => Err(anyhow!("Config '{}' does not exist.", name)),
+        }
+    }
+
+    /// Warns about every config which couldn't be read and is therefore left out.
+    pub fn warn_errors(&self) {
+        let mut names: Vec<&String> = self.errors.keys().collect();
+        names.sort();
+        for name in names {
+            style::warn(&format!(
+                "Config '{}' could not be read and is skipped: {}",
+                name,
+                format!("{:#}", self.errors[name]).trim_end()
+            ));
+        }
+    }
+}
+
+/// Compares config names like a case-insensitive file system would.
+pub fn same_name(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+/// Lists the names and paths of all directories in the root directory which contain configurations.
+/// Directories matching the root `exclude` globs, the directory of the root configuration and the
+/// state directory are skipped.
+pub fn config_dirs(root: &RootConfiguration) -> Result<Vec<(String, PathBuf)>> {
+    // the directory of a nested root configuration isn't a config
+    let root_dir = config::root_path()
+        .parent()
+        .and_then(|parent| parent.iter().next());
+
+    let mut dirs = Vec::new();
+    for path in list_root()? {
+        let path = path?.path();
+        let key = path.file_name().unwrap().to_string_lossy().to_string();
+
+        if path.is_dir()
+            && !root.exclude_patterns.is_match(&key)
+            && root_dir.is_none_or(|dir| dir != key.as_str())
+            && key != manifest::STATE_DIR
+        {
+            dirs.push((key, path));
+        }
+    }
+    Ok(dirs)
 }
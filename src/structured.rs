@@ -0,0 +1,151 @@
+use std::{fmt, path::Path};
+
+use serde_json::{Map, Value};
+
+/// A change to a single key of a TOML, JSON or YAML file.
+pub enum KeyChange {
+    Added(Key, Value),
+    Removed(Key, Value),
+    Changed(Key, Value, Value),
+}
+
+/// The path to a value in a structured file, like `editor."font.size"` or `plugins[2]`.
+pub struct Key(String);
+
+impl Key {
+    fn field(&self, name: &str) -> Key {
+        let bare = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+        let name = if bare {
+            String::from(name)
+        } else {
+            Value::String(String::from(name)).to_string()
+        };
+        if self.0.is_empty() {
+            Key(name)
+        } else {
+            Key(format!("{}.{}", self.0, name))
+        }
+    }
+
+    fn index(&self, index: usize) -> Key {
+        Key(format!("{}[{}]", self.0, index))
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            write!(f, "(whole file)")
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
+/// Parses both versions of a TOML, JSON or YAML file, chosen by its extension, and lists the keys which changed.
+/// Reordered keys and changes to the formatting or comments don't show up.
+/// Returns `None` if the file has none of these formats or one of the versions can't be parsed.
+pub fn changes(path: &Path, old: &str, new: &str) -> Option<Vec<KeyChange>> {
+    let parse: fn(&str) -> Option<Value> = match path.extension()?.to_str()? {
+        "toml" => |text| toml::from_str(text).ok().map(from_toml),
+        // editors like VS Code allow comments in their settings
+        "json" | "jsonc" => |text| serde_json::from_str(&strip_json_comments(text)).ok(),
+        "yaml" | "yml" => |text| serde_yaml::from_str(text).ok(),
+        _ => return None,
+    };
+    let mut changes = Vec::new();
+    compare(Key(String::new()), &parse(old)?, &parse(new)?, &mut changes);
+    Some(changes)
+}
+
+fn compare(key: Key, old: &Value, new: &Value, changes: &mut Vec<KeyChange>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (name, value) in old {
+                match new.get(name) {
+                    Some(new) => compare(key.field(name), value, new, changes),
+                    None => changes.push(KeyChange::Removed(key.field(name), value.clone())),
+                }
+            }
+            for (name, value) in new {
+                if !old.contains_key(name) {
+                    changes.push(KeyChange::Added(key.field(name), value.clone()));
+                }
+            }
+        }
+        (Value::Array(old), Value::Array(new)) => {
+            for index in 0..old.len().max(new.len()) {
+                match (old.get(index), new.get(index)) {
+                    (Some(old), Some(new)) => compare(key.index(index), old, new, changes),
+                    (Some(old), None) => {
+                        changes.push(KeyChange::Removed(key.index(index), old.clone()))
+                    }
+                    (None, Some(new)) => {
+                        changes.push(KeyChange::Added(key.index(index), new.clone()))
+                    }
+                    (None, None) => {}
+                }
+            }
+        }
+        _ if old != new => changes.push(KeyChange::Changed(key, old.clone(), new.clone())),
+        _ => {}
+    }
+}
+
+/// converts a TOML value into the same representation as JSON and YAML
+fn from_toml(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(string) => Value::String(string),
+        toml::Value::Integer(integer) => Value::from(integer),
+        toml::Value::Float(float) => Value::from(float),
+        toml::Value::Boolean(boolean) => Value::Bool(boolean),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(array) => Value::Array(array.into_iter().map(from_toml).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, from_toml(value)))
+                .collect::<Map<String, Value>>(),
+        ),
+    }
+}
+
+/// removes `//` and `/* */` comments outside of strings
+fn strip_json_comments(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            stripped.push(c);
+            match c {
+                '\\' => stripped.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                stripped.push(c);
+            }
+            ('/', Some('/')) => while chars.next_if(|c| *c != '\n').is_some() {},
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            _ => stripped.push(c),
+        }
+    }
+    stripped
+}
@@ -0,0 +1,74 @@
+use std::{
+    env,
+    io::{stdout, IsTerminal},
+    sync::OnceLock,
+};
+
+use crate::progress;
+
+pub const RESET: &str = "\x1b[0m";
+pub const DIM: &str = "\x1b[2m";
+pub const ITALIC: &str = "\x1b[0;3m";
+pub const RED: &str = "\x1b[31m";
+pub const GREEN: &str = "\x1b[32m";
+pub const YELLOW: &str = "\x1b[33m";
+pub const CYAN: &str = "\x1b[36m";
+pub const BRIGHT_RED: &str = "\x1b[91m";
+pub const BRIGHT_GREEN: &str = "\x1b[92m";
+
+/// The bounds of the width used for tables, regardless of the terminal size.
+const MIN_WIDTH: usize = 40;
+const MAX_WIDTH: usize = 160;
+/// The width used for tables if the output isn't a terminal.
+const DEFAULT_WIDTH: usize = 80;
+
+/// The values accepted by the `--color` flag.
+pub const COLOR_CHOICES: [&str; 3] = ["auto", "always", "never"];
+
+static COLOR: OnceLock<bool> = OnceLock::new();
+
+/// Decides once whether colors are used for the rest of the program.
+/// `auto` only enables colors if stdout is a terminal and the `NO_COLOR` environment variable isn't set.
+pub fn init(choice: &str) {
+    let enabled = match choice {
+        "always" => true,
+        "never" => false,
+        _ => stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+    };
+    COLOR.set(enabled).ok();
+}
+
+/// Checks if colored output is enabled.
+pub fn enabled() -> bool {
+    *COLOR.get().unwrap_or(&false)
+}
+
+/// Returns the escape sequence if colors are enabled, or an empty string otherwise.
+pub fn escape(code: &'static str) -> &'static str {
+    if enabled() {
+        code
+    } else {
+        ""
+    }
+}
+
+/// Wraps the text in the escape sequence if colors are enabled.
+pub fn paint(code: &'static str, text: &str) -> String {
+    format!("{}{}{}", escape(code), text, escape(RESET))
+}
+
+/// Returns the width available for drawing tables.
+/// Falls back to 80 columns if stdout isn't a terminal.
+pub fn width() -> usize {
+    match terminal_size::terminal_size() {
+        Some((terminal_size::Width(width), _)) if stdout().is_terminal() => {
+            (width as usize).clamp(MIN_WIDTH, MAX_WIDTH)
+        }
+        _ => DEFAULT_WIDTH,
+    }
+}
+
+/// Prints a highlighted warning.
+pub fn warn(message: &str) {
+    progress::suspend(|| println!("{} {}", paint(YELLOW, "Warning:"), message));
+}
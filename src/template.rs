@@ -0,0 +1,55 @@
+use std::{collections::HashMap, env};
+
+use anyhow::{anyhow, Result};
+use regex::{Captures, Regex};
+
+use crate::config::{self, RootConfiguration};
+
+/// Collects the variables which are available in templates.
+/// Besides the built-in variables `hostname`, `home`, `user` and `os`,
+/// all entries of the `[variables]` table in the root configuration are available.
+/// Variables from the root configuration take precedence over the built-in ones.
+pub fn variables(root: &RootConfiguration) -> HashMap<String, String> {
+    let mut variables = HashMap::new();
+
+    if let Some(hostname) = config::hostname() {
+        variables.insert(String::from("hostname"), hostname);
+    }
+    variables.insert(String::from("home"), shellexpand::tilde("~").into_owned());
+    if let Ok(user) = env::var("USER").or_else(|_| env::var("USERNAME")) {
+        variables.insert(String::from("user"), user);
+    }
+    variables.insert(String::from("os"), String::from(env::consts::OS));
+
+    for (key, value) in &root.variables {
+        variables.insert(key.clone(), value.clone());
+    }
+
+    variables
+}
+
+/// Replaces all `{{ variable }}` placeholders in `source` with the value of the variable.
+/// # Errors
+/// Returns an error naming the placeholder if a variable is not defined.
+pub fn render(source: &str, variables: &HashMap<String, String>) -> Result<String> {
+    lazy_static::lazy_static! {
+        static ref RE: Regex = Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").unwrap();
+    }
+
+    let mut missing = None;
+    let rendered = RE.replace_all(source, |captures: &Captures| {
+        let name = &captures[1];
+        match variables.get(name) {
+            Some(value) => value.clone(),
+            None => {
+                missing.get_or_insert_with(|| name.to_string());
+                String::new()
+            }
+        }
+    });
+
+    match missing {
+        Some(name) => Err(anyhow!("Undefined template variable '{{{{ {} }}}}'", name)),
+        None => Ok(rendered.into_owned()),
+    }
+}
@@ -0,0 +1,618 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Result;
+use tempfile::TempDir;
+
+use super::*;
+
+/// the commands work in the current directory, which is shared by all tests running in parallel
+static CWD: Mutex<()> = Mutex::new(());
+
+/// A scratch repository with a single target directory next to it.
+struct Repo {
+    dir: TempDir,
+}
+
+impl Repo {
+    fn repo(&self) -> PathBuf {
+        self.dir.path().join("repo")
+    }
+
+    fn target(&self) -> PathBuf {
+        self.dir.path().join("target")
+    }
+
+    /// creates a config which is deployed into the target directory on every operating system
+    fn config(&self, name: &str, extra: &str, files: &[(&str, &str)]) {
+        self.config_at(name, &self.target(), extra, files);
+    }
+
+    /// creates a config which is deployed into `target` on every operating system
+    fn config_at(&self, name: &str, target: &Path, extra: &str, files: &[(&str, &str)]) {
+        let dir = self.repo().join(name);
+        fs::create_dir_all(&dir).unwrap();
+        let target = target.to_string_lossy().replace('\\', "/");
+        fs::write(
+            dir.join(config::CONFIG_PATH),
+            format!(
+                "[target]\nexclude = [\"dotconfig.toml\"]\n{extra}\n[target.linux]\ndirectory = '{target}'\n[target.macos]\ndirectory = '{target}'\n[target.windows]\ndirectory = '{target}'\n"
+            ),
+        )
+        .unwrap();
+        for (path, contents) in files {
+            write_file(&dir.join(path), contents);
+        }
+    }
+}
+
+fn write_file(path: &Path, contents: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+/// runs `f` inside of a new repository with the given root configuration, or the default one if it is empty
+fn in_repo(root: &str, f: impl FnOnce(&Repo)) {
+    let _cwd = CWD.lock().unwrap_or_else(|error| error.into_inner());
+    let repo = Repo {
+        dir: tempfile::tempdir().unwrap(),
+    };
+    fs::create_dir_all(repo.repo()).unwrap();
+    fs::create_dir_all(repo.target()).unwrap();
+    let root = match root {
+        "" => toml::to_string_pretty(&RootConfiguration::default()).unwrap(),
+        root => String::from(root),
+    };
+    fs::write(repo.repo().join(config::ROOT_PATH), root).unwrap();
+    let previous = env::current_dir().unwrap();
+    env::set_current_dir(repo.repo()).unwrap();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(&repo)));
+    env::set_current_dir(previous).unwrap();
+    if let Err(panic) = result {
+        std::panic::resume_unwind(panic);
+    }
+}
+
+/// runs a `dottor config` subcommand in the current repository
+fn run(args: &[&str]) -> Result<()> {
+    let matches = cli().try_get_matches_from(["dottor", "config"].iter().chain(args))?;
+    let (_, sub_matches) = matches.subcommand().unwrap();
+    config(sub_matches, Structure::resolve())
+}
+
+#[test]
+fn undeploy_removes_the_files_of_the_manifest() {
+    in_repo("", |repo| {
+        repo.config("app", "", &[("a", "a\n"), ("dir/b", "b\n")]);
+        run(&["deploy", "app", "-y", "-q", "--no-backup"]).unwrap();
+
+        let manifest = manifest::read("app").unwrap().unwrap();
+        let mut deployed: Vec<PathBuf> = manifest
+            .files
+            .into_iter()
+            .map(|file| file.destination)
+            .collect();
+        deployed.sort();
+        assert_eq!(
+            deployed,
+            [repo.target().join("a"), repo.target().join("dir").join("b")]
+        );
+
+        run(&["undeploy", "app", "-f"]).unwrap();
+        assert!(!repo.target().join("a").exists());
+        assert!(!repo.target().join("dir").exists());
+        assert!(manifest::read("app").unwrap().is_none());
+    });
+}
+
+#[test]
+fn undeploy_leaves_unmanaged_files_alone() {
+    in_repo("", |repo| {
+        repo.config("app", "", &[("a", "a\n")]);
+        // a file which the config would deploy, but which dottor never placed there
+        write_file(&repo.target().join("a"), "a\n");
+        write_file(&repo.target().join("other"), "other\n");
+
+        let error = run(&["undeploy", "app", "-f"]).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Config 'app' has no deploy manifest"),
+            "{}",
+            error
+        );
+        assert!(repo.target().join("a").exists());
+
+        run(&["deploy", "app", "-y", "-q", "--no-backup", "--force"]).unwrap();
+        run(&["undeploy", "app", "-f"]).unwrap();
+        assert!(!repo.target().join("a").exists());
+        assert!(repo.target().join("other").exists());
+    });
+}
+
+#[test]
+fn secrets_are_private_and_survive_a_round_trip() {
+    in_repo("", |repo| {
+        env::set_var(secret::PASSPHRASE_VARIABLE, "test passphrase");
+        repo.config("app", "secrets = [\"token\"]", &[]);
+        let encrypted = repo.repo().join("app").join("token.age");
+        fs::write(&encrypted, secret::encrypt(b"first\n").unwrap()).unwrap();
+        #[cfg(unix)]
+        io::set_mode(&encrypted, 0o644).unwrap();
+
+        run(&["deploy", "app", "-y", "-q", "--no-backup"]).unwrap();
+        let deployed = repo.target().join("token");
+        assert_eq!(fs::read(&deployed).unwrap(), b"first\n");
+        #[cfg(unix)]
+        assert_eq!(io::mode(&deployed).unwrap() & 0o777, 0o600);
+
+        fs::write(&deployed, "second\n").unwrap();
+        run(&["pull", "app", "-f", "-q"]).unwrap();
+        assert_eq!(secret::decrypt(&encrypted).unwrap(), b"second\n");
+        assert!(!repo.repo().join("app").join("token").exists());
+    });
+}
+
+#[test]
+fn root_excludes_apply_to_nested_files_when_pulling() {
+    let root = RootConfiguration {
+        exclude: vec![String::from(".git/"), String::from("node_modules/")],
+        ..Default::default()
+    };
+    in_repo(&toml::to_string_pretty(&root).unwrap(), |repo| {
+        repo.config("app", "", &[("a", "a\n")]);
+        run(&["deploy", "app", "-y", "-q", "--no-backup"]).unwrap();
+        write_file(&repo.target().join("node_modules").join("x.js"), "x\n");
+        write_file(&repo.target().join("b"), "b\n");
+
+        run(&["pull", "app", "-f", "-q"]).unwrap();
+        assert!(repo.repo().join("app").join("b").exists());
+        assert!(!repo.repo().join("app").join("node_modules").exists());
+    });
+}
+
+#[test]
+fn kept_contents_are_ignored_by_git() {
+    in_repo("", |repo| {
+        manifest::store_base("app", b"contents\n").unwrap();
+        let gitignore = repo.repo().join(manifest::STATE_DIR).join(".gitignore");
+        assert_eq!(fs::read(gitignore).unwrap(), b"*\n");
+    });
+}
+
+#[cfg(unix)]
+#[test]
+fn permissions_are_kept_when_deploying_and_pulling() {
+    in_repo("", |repo| {
+        repo.config("app", "", &[("script", "#!/bin/sh\n"), ("config", "x\n")]);
+        let script = repo.repo().join("app").join("script");
+        io::set_mode(&script, 0o755).unwrap();
+
+        run(&["deploy", "app", "-y", "-q", "--no-backup"]).unwrap();
+        let deployed = repo.target().join("script");
+        assert_eq!(io::mode(&deployed).unwrap() & 0o777, 0o755);
+
+        // a file which was only made executable on the system is pulled as a mode change
+        io::set_mode(&repo.target().join("config"), 0o755).unwrap();
+        run(&["pull", "app", "-f", "-q"]).unwrap();
+        assert_eq!(
+            io::mode(&repo.repo().join("app").join("config")).unwrap() & 0o777,
+            0o755
+        );
+    });
+}
+
+#[test]
+fn deleting_requires_an_existing_config() {
+    in_repo("", |repo| {
+        repo.config("app", "", &[]);
+        let error = run(&["delete", "missing"]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "There is no config with the name 'missing'"
+        );
+        assert!(repo.repo().join("app").is_dir());
+    });
+}
+
+#[test]
+fn targets_must_not_overlap_with_the_repository() {
+    in_repo("", |repo| {
+        let cases = [
+            (
+                repo.repo().join("inner"),
+                "is inside the dotfiles repository",
+            ),
+            (
+                repo.dir.path().to_path_buf(),
+                "contains the dotfiles repository",
+            ),
+        ];
+        for (target, problem) in cases {
+            repo.config_at("app", &target, "", &[]);
+            let structure = Structure::resolve().unwrap().unwrap();
+            let mappings = mapping::resolve(&structure.configs["app"]).unwrap();
+            let error = mapping::check_outside_repository("app", &mappings).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!(
+                    "The target '{}' of config 'app' {problem}.",
+                    target.display()
+                )
+            );
+        }
+    });
+}
+
+#[test]
+fn pulled_deletions_remove_empty_directories() {
+    in_repo("", |repo| {
+        repo.config(
+            "app",
+            "",
+            &[
+                (".dottorignore", "*.log\n"),
+                ("dir/sub/a", "a\n"),
+                ("dir/keep/b", "b\n"),
+                ("dir/keep/x.log", "log\n"),
+            ],
+        );
+        run(&["deploy", "app", "-y", "-q", "--no-backup"]).unwrap();
+        fs::remove_file(repo.target().join("dir").join("sub").join("a")).unwrap();
+        fs::remove_file(repo.target().join("dir").join("keep").join("b")).unwrap();
+
+        run(&["pull", "app", "-f", "-q"]).unwrap();
+        let dir = repo.repo().join("app").join("dir");
+        assert!(!dir.join("sub").exists());
+        // the excluded file is still in the directory
+        assert!(dir.join("keep").join("x.log").exists());
+        assert!(!dir.join("keep").join("b").exists());
+    });
+}
+
+#[cfg(unix)]
+#[test]
+fn links_are_deployed_as_links() {
+    in_repo("", |repo| {
+        repo.config("app", "", &[("a", "a\n"), ("dir/b", "b\n")]);
+        let config_dir = repo.repo().join("app");
+        io::create_symlink(Path::new("a"), &config_dir.join("link")).unwrap();
+        io::create_symlink(Path::new(".."), &config_dir.join("dir").join("loop")).unwrap();
+
+        run(&["deploy", "app", "-y", "-q", "--no-backup"]).unwrap();
+        for (link, target) in [("link", "a"), ("dir/loop", "..")] {
+            let link = repo.target().join(link);
+            assert!(link.is_symlink());
+            assert_eq!(fs::read_link(&link).unwrap(), Path::new(target));
+        }
+    });
+}
+
+#[cfg(unix)]
+#[test]
+fn followed_links_to_a_parent_are_skipped() {
+    in_repo("", |repo| {
+        repo.config(
+            "app",
+            "follow_symlinks = true",
+            &[("a", "a\n"), ("dir/b", "b\n")],
+        );
+        let config_dir = repo.repo().join("app");
+        io::create_symlink(Path::new("a"), &config_dir.join("link")).unwrap();
+        io::create_symlink(Path::new(".."), &config_dir.join("dir").join("loop")).unwrap();
+
+        run(&["deploy", "app", "-y", "-q", "--no-backup"]).unwrap();
+        let link = repo.target().join("link");
+        assert!(!link.is_symlink());
+        assert_eq!(fs::read(&link).unwrap(), b"a\n");
+        assert!(repo.target().join("dir").join("b").is_file());
+        assert!(!repo.target().join("dir").join("loop").exists());
+    });
+}
+
+#[test]
+fn config_names_are_compared_case_insensitively() {
+    in_repo("", |repo| {
+        repo.config("Vim", "", &[]);
+        let structure = Structure::resolve().unwrap().unwrap();
+        assert_eq!(structure.find("vim"), Some("Vim"));
+        assert_eq!(structure.find("VIM"), Some("Vim"));
+        assert_eq!(structure.find("neovim"), None);
+
+        // only changing the case of the name is allowed
+        run(&["rename", "vim", "VIM"]).unwrap();
+        assert!(repo.repo().join("VIM").is_dir());
+        let error = run(&["create", "vim"]).unwrap_err();
+        assert!(error.to_string().contains("'VIM'"), "{}", error);
+
+        // names which only differ in case would collide on other machines.
+        // On a case-insensitive file system they can't even be created.
+        if repo.repo().join("vim").exists() {
+            return;
+        }
+        repo.config("vim", "", &[]);
+        let structure = Structure::resolve().unwrap().unwrap();
+        assert!(structure.configs.is_empty());
+        let error = structure.config("vim").unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("only differs in case from 'VIM'"),
+            "{}",
+            error
+        );
+    });
+}
+
+#[test]
+fn adopting_and_validating_find_configs_of_another_case() {
+    in_repo("", |repo| {
+        repo.config("nvim", "", &[("init.lua", "old\n")]);
+        let source = repo.dir.path().join("source");
+        write_file(&source.join("lua").join("plugins.lua"), "plugins\n");
+
+        run(&["adopt", "NVIM", &source.to_string_lossy()]).unwrap();
+        assert!(repo
+            .repo()
+            .join("nvim")
+            .join("lua")
+            .join("plugins.lua")
+            .is_file());
+        let names: Vec<String> = fs::read_dir(repo.repo())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.eq_ignore_ascii_case("nvim"))
+            .collect();
+        assert_eq!(names, ["nvim"]);
+
+        run(&["validate", "NVIM"]).unwrap();
+        let error = run(&["validate", "vim"]).unwrap_err();
+        assert_eq!(error.to_string(), "No configurations matched the query.");
+    });
+}
+
+#[cfg(unix)]
+#[test]
+fn hooks_of_configs_deployed_in_parallel_run_one_at_a_time() {
+    in_repo("", |repo| {
+        let hooks = "[deploy]\nhooks = { pre = [\"echo start >> ../hooks.log\", \"sleep 0.2\", \"echo end >> ../hooks.log\"] }";
+        repo.config("a", hooks, &[("a", "a\n")]);
+        repo.config("b", hooks, &[("b", "b\n")]);
+
+        // more than one thread, even on machines with a single core
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap()
+            .install(|| run(&["deploy", "-a", "-y", "-q", "--no-backup"]))
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(repo.repo().join("hooks.log")).unwrap(),
+            "start\nend\nstart\nend\n"
+        );
+        assert!(repo.target().join("a").exists());
+        assert!(repo.target().join("b").exists());
+    });
+}
+
+#[test]
+fn configs_requiring_a_failed_dependency_are_skipped() {
+    in_repo("", |repo| {
+        repo.config(
+            "base",
+            "[deploy]\nhooks = { pre = [\"exit 1\"] }",
+            &[("base", "base\n")],
+        );
+        repo.config(
+            "app",
+            "[dependencies]\nlocal = [{ name = \"base\" }]",
+            &[("app", "app\n")],
+        );
+        repo.config(
+            "extra",
+            "[dependencies]\nlocal = [{ name = \"base\", required = false }]",
+            &[("extra", "extra\n")],
+        );
+
+        let error = run(&["deploy", "-a", "-y", "-q", "--no-backup"]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "2 config(s) could not be deployed: app, base"
+        );
+        assert!(!repo.target().join("base").exists());
+        assert!(!repo.target().join("app").exists());
+        assert!(repo.target().join("extra").exists());
+    });
+}
+
+#[test]
+fn deploying_with_dependencies_fails_if_the_config_fails() {
+    in_repo("", |repo| {
+        repo.config("base", "", &[("base", "base\n")]);
+        repo.config(
+            "app",
+            "[deploy]\nhooks = { pre = [\"exit 1\"] }\n[dependencies]\nlocal = [{ name = \"base\" }]",
+            &[("app", "app\n")],
+        );
+
+        let error = run(&["deploy", "app", "--with-deps", "-y", "-q", "--no-backup"]).unwrap_err();
+        assert_eq!(error.to_string(), "1 config(s) could not be deployed: app");
+        assert!(repo.target().join("base").exists());
+        assert!(!repo.target().join("app").exists());
+    });
+}
+
+#[test]
+fn pre_hooks_only_run_once_the_deploy_was_checked() {
+    in_repo("", |repo| {
+        let hooks = "[deploy]\nhooks = { pre = [\"echo ran >> ../hooks.log\"] }";
+        repo.config(
+            "full",
+            &format!("require_empty = true\n{hooks}"),
+            &[("a", "a\n")],
+        );
+        write_file(&repo.target().join("other"), "other\n");
+        repo.config(
+            "invalid",
+            &format!("templates = [\"[\"]\n{hooks}"),
+            &[("b", "b\n")],
+        );
+
+        run(&["deploy", "full", "-y", "-q", "--no-backup"]).unwrap_err();
+        run(&["deploy", "invalid", "-y", "-q", "--no-backup"]).unwrap_err();
+        assert!(!repo.repo().join("hooks.log").exists());
+        assert!(!repo.target().join("a").exists());
+        assert!(!repo.target().join("b").exists());
+    });
+}
+
+#[cfg(unix)]
+#[test]
+fn links_are_rewritten_when_their_style_changes() {
+    in_repo("", |repo| {
+        repo.config("app", "strategy = \"symlink\"", &[("a", "a\n")]);
+        let link = repo.target().join("a");
+        let source = fs::canonicalize(repo.repo().join("app").join("a")).unwrap();
+
+        run(&["deploy", "app", "-y", "-q", "--no-backup"]).unwrap();
+        assert_eq!(fs::read_link(&link).unwrap(), source);
+
+        run(&["deploy", "app", "-y", "-q", "--no-backup", "--relative"]).unwrap();
+        let target = fs::read_link(&link).unwrap();
+        assert!(target.is_relative(), "{}", target.display());
+        assert_eq!(fs::canonicalize(&link).unwrap(), source);
+
+        run(&["deploy", "app", "-y", "-q", "--no-backup"]).unwrap();
+        assert_eq!(fs::read_link(&link).unwrap(), source);
+    });
+}
+
+#[test]
+fn files_changed_on_both_sides_are_conflicts() {
+    in_repo("", |repo| {
+        repo.config("app", "", &[("a", "base\n")]);
+        run(&["deploy", "app", "-y", "-q", "--no-backup"]).unwrap();
+        let in_repo = repo.repo().join("app").join("a");
+        fs::write(&in_repo, "repository\n").unwrap();
+        fs::write(repo.target().join("a"), "system\n").unwrap();
+
+        let error = run(&["pull", "app", "-f", "-q"]).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("1 file(s) were changed in the repository as well as on the system"),
+            "{}",
+            error
+        );
+        assert_eq!(fs::read_to_string(&in_repo).unwrap(), "repository\n");
+
+        run(&["pull", "app", "-f", "-q", "--ours"]).unwrap();
+        assert_eq!(fs::read_to_string(&in_repo).unwrap(), "repository\n");
+
+        run(&["pull", "app", "-f", "-q", "--theirs"]).unwrap();
+        assert_eq!(fs::read_to_string(&in_repo).unwrap(), "system\n");
+    });
+}
+
+#[test]
+fn created_configs_survive_a_round_trip() {
+    in_repo("", |repo| {
+        let mut written = Configuration::default();
+        written.target.exclude = vec![String::from("cache")];
+        let target = written.target.current_mut().unwrap();
+        target.directory = Some(repo.target().to_string_lossy().into_owned());
+        target.require_empty = Some(false);
+        config::create_config("app", &written).unwrap();
+        write_file(&repo.repo().join("app").join("a"), "a\n");
+
+        let read = config::read_configuration(&Path::new("app").join(config::CONFIG_PATH)).unwrap();
+        assert!(read.validate().is_empty(), "{:?}", read.validate());
+        assert_eq!(read.target.exclude, ["cache"]);
+        assert_eq!(
+            read.target.current().unwrap().directory,
+            written.target.current().unwrap().directory
+        );
+        assert_eq!(read.target.current().unwrap().require_empty, Some(false));
+
+        run(&["deploy", "app", "-y", "-q", "--no-backup"]).unwrap();
+        assert_eq!(fs::read_to_string(repo.target().join("a")).unwrap(), "a\n");
+    });
+}
+
+#[test]
+fn files_of_the_deploy_table_are_deployed() {
+    in_repo("", |repo| {
+        let destination = repo.target().join("other").join("b");
+        let destination = destination.to_string_lossy().replace('\\', "/");
+        repo.config(
+            "app",
+            &format!("[deploy.files]\n'b' = '{destination}'"),
+            &[("a", "a\n"), ("b", "b\n")],
+        );
+
+        run(&["deploy", "app", "-y", "-q", "--no-backup"]).unwrap();
+        assert_eq!(
+            fs::read_to_string(repo.target().join("other").join("b")).unwrap(),
+            "b\n"
+        );
+    });
+}
+
+#[test]
+fn undeploying_a_config_without_manifest_fails() {
+    in_repo("", |repo| {
+        repo.config("app", "", &[("a", "a\n")]);
+        let error = run(&["undeploy", "app", "-f"]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Config 'app' has no deploy manifest, it is not deployed or was deployed by an older version of dottor. Redeploy it first to undeploy it."
+        );
+
+        // disabled configs are skipped instead
+        repo.config("disabled", "", &[("b", "b\n")]);
+        let dotconfig = repo.repo().join("disabled").join(config::CONFIG_PATH);
+        let contents = fs::read_to_string(&dotconfig).unwrap();
+        fs::write(&dotconfig, format!("enabled = false\n{contents}")).unwrap();
+        run(&["undeploy", "disabled", "-f"]).unwrap();
+    });
+}
+
+#[test]
+fn undeploying_several_configs_fails_if_any_of_them_failed() {
+    in_repo("", |repo| {
+        repo.config("app", "", &[("a", "a\n")]);
+        repo.config("other", "", &[("b", "b\n")]);
+        run(&["deploy", "app", "-y", "-q", "--no-backup"]).unwrap();
+
+        let error = run(&["undeploy", "-a", "-f"]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "1 config(s) could not be undeployed: other"
+        );
+        assert!(!repo.target().join("a").exists());
+    });
+}
+
+#[test]
+fn renamed_configs_keep_their_backups() {
+    in_repo("", |repo| {
+        repo.config("app", "", &[("a", "new\n")]);
+        write_file(&repo.target().join("a"), "old\n");
+        run(&["deploy", "app", "-y", "-q", "--force"]).unwrap();
+        assert_eq!(
+            fs::read_to_string(repo.target().join("a")).unwrap(),
+            "new\n"
+        );
+
+        run(&["rename", "app", "renamed"]).unwrap();
+        assert!(!backup::dir("app").exists());
+        run(&["restore", "renamed", "-f"]).unwrap();
+        assert_eq!(
+            fs::read_to_string(repo.target().join("a")).unwrap(),
+            "old\n"
+        );
+    });
+}
@@ -0,0 +1,59 @@
+use std::time::SystemTime;
+
+/// A point in time in UTC, split into its calendar date and time of day.
+#[derive(Debug, Clone, Copy)]
+pub struct Utc {
+    pub year: i64,
+    pub month: i64,
+    pub day: i64,
+    pub hour: u64,
+    pub minute: u64,
+    pub second: u64,
+}
+
+impl Utc {
+    /// Converts a point in time to UTC. Times before 1970 are treated as 1970-01-01.
+    pub fn new(time: SystemTime) -> Self {
+        let seconds = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let (days, seconds) = (seconds / 86400, seconds % 86400);
+
+        // converts the days since 1970-01-01 to a date, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let days = days as i64 + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 {
+            month_index + 3
+        } else {
+            month_index - 9
+        };
+
+        Utc {
+            year: year_of_era + era * 400 + i64::from(month <= 2),
+            month,
+            day,
+            hour: seconds / 3600,
+            minute: seconds / 60 % 60,
+            second: seconds % 60,
+        }
+    }
+
+    /// Returns the current time.
+    pub fn now() -> Self {
+        Utc::new(SystemTime::now())
+    }
+
+    /// Formats the time as in RFC 3339, e.g. `2024-03-01T12:30:00Z`.
+    pub fn to_rfc3339(self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use relative_path::RelativePathBuf;
+
+use crate::{
+    io::{copy_file, remove_empty_dirs},
+    manifest::{self, STATE_DIR},
+    privileged,
+};
+
+/// The directory inside the state directory in which the replaced files of running deploys are kept.
+const TRANSACTION_DIR: &str = "transactions";
+
+/// How a change to a file of the system is undone.
+#[derive(Debug)]
+enum Undo {
+    /// the file didn't exist before, empty parent directories are removed up to `root`
+    Remove { path: PathBuf, root: PathBuf },
+    /// the file was replaced, its previous version is kept at `saved`
+    Restore { path: PathBuf, saved: PathBuf },
+}
+
+/// Records the files a deploy changes, so that the system can be put back into its previous state
+/// if the deploy fails halfway.
+#[derive(Debug)]
+pub struct Transaction {
+    dir: PathBuf,
+    undo: Vec<Undo>,
+    /// files which the user isn't allowed to change are put back as root
+    privileged: bool,
+}
+
+impl Transaction {
+    /// Starts a transaction for a config. Leftovers of an earlier transaction which was interrupted are discarded.
+    /// With `privileged`, files which can't be put back because of missing permissions are put back as root.
+    /// # Errors
+    /// Returns an error if the leftovers can't be removed.
+    pub fn new(name: &str, privileged: bool) -> Result<Self> {
+        let dir = RelativePathBuf::from(STATE_DIR)
+            .join(TRANSACTION_DIR)
+            .join(name)
+            .to_path(".");
+        if dir.exists() {
+            fs::remove_dir_all(&dir)
+                .with_context(|| format!("Could not remove '{}'", dir.display()))?;
+        }
+        Ok(Transaction {
+            dir,
+            undo: Vec::new(),
+            privileged,
+        })
+    }
+
+    /// Saves the current state of a file before it is changed.
+    /// `root` is the directory up to which empty directories are removed if the file is new.
+    /// # Errors
+    /// Returns an error if the file exists but can't be saved.
+    pub fn record(&mut self, path: &Path, root: &Path) -> Result<()> {
+        self.record_all([(path, root)])
+    }
+
+    /// Saves the current state of several files before they are changed, see [`Transaction::record`].
+    /// The files are saved in parallel. If one of them can't be saved, none of them are recorded.
+    pub fn record_all<'a>(
+        &mut self,
+        files: impl IntoIterator<Item = (&'a Path, &'a Path)>,
+    ) -> Result<()> {
+        let mut pending: Vec<Undo> = Vec::new();
+        for (path, root) in files {
+            if self
+                .undo
+                .iter()
+                .chain(&pending)
+                .any(|undo| undo.path() == path)
+            {
+                continue;
+            }
+            pending.push(if path.is_file() || path.is_symlink() {
+                Undo::Restore {
+                    path: path.to_path_buf(),
+                    saved: self.dir.join((self.undo.len() + pending.len()).to_string()),
+                }
+            } else {
+                Undo::Remove {
+                    path: path.to_path_buf(),
+                    root: root.to_path_buf(),
+                }
+            });
+        }
+
+        if pending
+            .iter()
+            .any(|undo| matches!(undo, Undo::Restore { .. }))
+        {
+            manifest::create_state_dir()?;
+        }
+        pending.par_iter().try_for_each(|undo| match undo {
+            Undo::Restore { path, saved } => match copy_file(path, saved) {
+                Err(error) if self.privileged && privileged::is_permission_error(&error) => {
+                    privileged::copy(path, saved)
+                }
+                result => result.with_context(|| format!("Could not save '{}'", path.display())),
+            },
+            Undo::Remove { .. } => Ok(()),
+        })?;
+        self.undo.extend(pending);
+        Ok(())
+    }
+
+    /// Discards the saved files once all changes were made.
+    pub fn commit(self) -> Result<()> {
+        self.clean_up()
+    }
+
+    /// Undoes all recorded changes, the last one first.
+    /// # Errors
+    /// Returns an error if a file can't be put back. The remaining files are still restored.
+    pub fn rollback(self) -> Result<()> {
+        let mut result = Ok(());
+        for undo in self.undo.iter().rev() {
+            let undone = match undo {
+                Undo::Remove { path, root } => remove(path, root),
+                Undo::Restore { path, saved } => copy_file(saved, path),
+            };
+            // empty directories which only root may remove are kept
+            let undone = match undone {
+                Err(error) if self.privileged && privileged::is_permission_error(&error) => {
+                    match undo {
+                        Undo::Remove { path, .. } => privileged::remove(path),
+                        Undo::Restore { path, saved } if saved.is_symlink() => fs::read_link(saved)
+                            .map_err(anyhow::Error::from)
+                            .and_then(|target| privileged::link(&target, path)),
+                        Undo::Restore { path, saved } => privileged::copy(saved, path),
+                    }
+                }
+                undone => undone,
+            };
+            if let Err(error) = undone {
+                result = result.and(Err(error));
+            }
+        }
+        // the saved files are kept if something couldn't be restored
+        result.and_then(|_| self.clean_up())
+    }
+
+    fn clean_up(&self) -> Result<()> {
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir)
+                .with_context(|| format!("Could not remove '{}'", self.dir.display()))?;
+        }
+        // fails if a transaction of another config is running, which is fine
+        if let Some(parent) = self.dir.parent() {
+            let _ = fs::remove_dir(parent);
+        }
+        Ok(())
+    }
+}
+
+impl Undo {
+    /// the file which is put back
+    fn path(&self) -> &Path {
+        match self {
+            Undo::Remove { path, .. } | Undo::Restore { path, .. } => path,
+        }
+    }
+}
+
+/// removes a file which was created by the transaction, together with the directories created for it
+fn remove(path: &Path, root: &Path) -> Result<()> {
+    if path.is_file() || path.is_symlink() {
+        fs::remove_file(path).with_context(|| format!("Could not remove '{}'", path.display()))?;
+    }
+    match path.parent() {
+        Some(parent) if parent.exists() => remove_empty_dirs(parent, root),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    collections::HashMap,
+    env,
+    path::PathBuf,
+    sync::mpsc::{channel, RecvTimeoutError},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use notify::{EventKind, RecursiveMode, Watcher};
+use relative_path::RelativePathBuf;
+
+use crate::style;
+
+/// How long to wait for further changes before the changed configs are reported.
+/// Editors often write a file in several steps, which should only cause a single redeploy.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Watches the directories of the configs until the program is stopped.
+/// Once the files stop changing, `changed` is called for every config with the paths of its
+/// changed files, relative to the config.
+/// # Errors
+/// Returns an error if a directory can't be watched.
+pub fn watch(names: &[String], mut changed: impl FnMut(&str, &[PathBuf])) -> Result<()> {
+    let (sender, receiver) = channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+
+    let current_dir = env::current_dir()?;
+    let mut dirs = Vec::new();
+    for name in names {
+        let dir = RelativePathBuf::from(name.as_str()).to_path(&current_dir);
+        watcher
+            .watch(&dir, RecursiveMode::Recursive)
+            .with_context(|| format!("Could not watch '{}'", dir.display()))?;
+        dirs.push((name, dir));
+    }
+
+    let mut pending: HashMap<&str, Vec<PathBuf>> = HashMap::new();
+    loop {
+        match receiver.recv_timeout(DEBOUNCE) {
+            Ok(event) => {
+                // a single failed event doesn't stop watching, e.g. if the watcher's queue overflowed
+                let event = match event {
+                    Ok(event) => event,
+                    Err(error) => {
+                        style::warn(&format!("Could not watch for changes: {}", error));
+                        continue;
+                    }
+                };
+                if matches!(event.kind, EventKind::Access(_)) {
+                    continue;
+                }
+                for path in event.paths {
+                    let config = dirs
+                        .iter()
+                        .find_map(|(name, dir)| Some((name, path.strip_prefix(dir).ok()?)));
+                    if let Some((name, path_rel)) = config {
+                        pending
+                            .entry(name.as_str())
+                            .or_default()
+                            .push(path_rel.to_path_buf());
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                let mut configs: Vec<(&str, Vec<PathBuf>)> = pending.drain().collect();
+                configs.sort();
+                for (name, paths) in configs {
+                    changed(name, &paths);
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}